@@ -110,11 +110,24 @@ impl FileCategory {
 /// # Errors
 ///
 /// Returns an error if the file writing fails.
-
 pub fn write_to_file<T: Serialize>(data: &T, file_name: &str, file_type: FileCategory) -> Result<(), Box<dyn StdError>> {
-    println!("{}", "Writing to file".green());
     let file_path = get_file_path(file_name, file_type.as_str())?;
-    ensure_directory_exists(&file_path)?;
+    write_to_path(data, &file_path)
+}
+
+/// Writes the given data to a file at an explicit path.
+///
+/// # Arguments
+///
+/// * `data` - The data to be written to the file.
+/// * `file_path` - The path of the file.
+///
+/// # Errors
+///
+/// Returns an error if the file writing fails.
+pub fn write_to_path<T: Serialize>(data: &T, file_path: &Path) -> Result<(), Box<dyn StdError>> {
+    println!("{}", "Writing to file".green());
+    ensure_directory_exists(file_path)?;
     let serialized_data = bincode::serialize(data)?;
     println!("{}: {}", "File written to".green(), file_path.to_str().unwrap_or_default());
     fs::write(file_path, serialized_data)?;
@@ -134,6 +147,19 @@ pub fn write_to_file<T: Serialize>(data: &T, file_name: &str, file_type: FileCat
 /// Returns an error if the file reading fails.
 pub fn read_from_file<T: DeserializeOwned>(file_name: &str, file_type: FileCategory) -> Result<T, Box<dyn StdError>> {
     let file_path = get_file_path(file_name, file_type.as_str())?;
+    read_from_path(&file_path)
+}
+
+/// Reads data from a file at an explicit path.
+///
+/// # Arguments
+///
+/// * `file_path` - The path of the file.
+///
+/// # Errors
+///
+/// Returns an error if the file reading fails.
+pub fn read_from_path<T: DeserializeOwned>(file_path: &Path) -> Result<T, Box<dyn StdError>> {
     let serialized_data = fs::read(file_path)?;
     let data: T = bincode::deserialize(&serialized_data)?;
     Ok(data)
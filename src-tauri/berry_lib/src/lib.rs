@@ -1,5 +1,6 @@
 pub mod api;
 pub mod auth;
+pub mod moderation;
 pub mod openai;
 pub mod twitch;
 pub mod user;
@@ -0,0 +1,358 @@
+//! An append-only record of moderation decisions.
+//!
+//! Every decision is written as one JSON line as soon as it is made, so the
+//! log survives crashes and can be read with standard tools. Entries can be
+//! filtered with an `AuditQuery` and exported to CSV or JSONL for reviews.
+
+use crate::file_sys::app_bin::{self, FileCategory};
+use crate::openai::moderation::{
+    FlaggedMessage, ModerationCategory, ModerationScores, PunishmentAction,
+};
+use crate::twitch::twitch_api::normalize_channel;
+use chrono::{DateTime, Utc};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+const AUDIT_FILE: &str = "moderation_audit.jsonl";
+
+#[derive(Debug)]
+pub enum AuditError {
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
+    CsvError(csv::Error),
+    StorageError(String),
+}
+
+impl std::fmt::Display for AuditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AuditError::IoError(e) => write!(f, "IO Error: {}", e),
+            AuditError::JsonError(e) => write!(f, "JSON Error: {}", e),
+            AuditError::CsvError(e) => write!(f, "CSV Error: {}", e),
+            AuditError::StorageError(e) => write!(f, "Storage Error: {}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for AuditError {
+    fn from(err: std::io::Error) -> Self {
+        AuditError::IoError(err)
+    }
+}
+
+impl From<serde_json::Error> for AuditError {
+    fn from(err: serde_json::Error) -> Self {
+        AuditError::JsonError(err)
+    }
+}
+
+impl From<csv::Error> for AuditError {
+    fn from(err: csv::Error) -> Self {
+        AuditError::CsvError(err)
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for AuditError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        AuditError::StorageError(err.to_string())
+    }
+}
+
+/// Who made the decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecisionOrigin {
+    /// The bot, from the channel's policy and strikes.
+    Automatic,
+    /// A moderator, e.g. from the app.
+    Manual,
+}
+
+/// What came of carrying out the action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionResult {
+    Applied,
+    /// The decision was to do nothing.
+    NoAction,
+    /// Holds why the action could not be carried out.
+    Failed(String),
+    /// Not carried out because the channel is in shadow mode.
+    Shadow,
+}
+
+impl std::fmt::Display for ActionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ActionResult::Applied => write!(f, "Applied"),
+            ActionResult::NoAction => write!(f, "No Action"),
+            ActionResult::Failed(e) => write!(f, "Failed: {}", e),
+            ActionResult::Shadow => write!(f, "Shadow"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Unix time in seconds.
+    pub timestamp: i64,
+    pub channel: String,
+    pub origin: DecisionOrigin,
+    pub message: FlaggedMessage,
+    /// Every category's score, not only the one acted on.
+    pub scores: ModerationScores,
+    /// The policy threshold for the category, `None` if it has no rule.
+    pub threshold: Option<f64>,
+    pub action: PunishmentAction,
+    pub result: ActionResult,
+}
+
+impl AuditEntry {
+    /// An entry stamped with the current time.
+    pub fn new(
+        channel: &str,
+        origin: DecisionOrigin,
+        message: FlaggedMessage,
+        scores: ModerationScores,
+        threshold: Option<f64>,
+        action: PunishmentAction,
+        result: ActionResult,
+    ) -> Self {
+        AuditEntry {
+            timestamp: Utc::now().timestamp(),
+            channel: normalize_channel(channel),
+            origin,
+            message,
+            scores,
+            threshold,
+            action,
+            result,
+        }
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.timestamp, 0).unwrap_or_default()
+    }
+}
+
+/// Filters for `AuditLog::query`. Unset fields match everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AuditQuery {
+    pub channel: Option<String>,
+    /// A user id or login.
+    pub user: Option<String>,
+    pub category: Option<ModerationCategory>,
+    /// Unix time in seconds, inclusive.
+    pub since: Option<i64>,
+    /// Unix time in seconds, exclusive.
+    pub until: Option<i64>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let channel = self
+            .channel
+            .as_ref()
+            .is_none_or(|c| normalize_channel(c) == entry.channel);
+        let user = self.user.as_ref().is_none_or(|user| {
+            let user = user.trim_start_matches('@');
+            entry.message.user_id == user || entry.message.username.eq_ignore_ascii_case(user)
+        });
+        let category = self
+            .category
+            .as_ref()
+            .is_none_or(|category| entry.message.category == *category);
+        let since = self.since.is_none_or(|since| entry.timestamp >= since);
+        let until = self.until.is_none_or(|until| entry.timestamp < until);
+        channel && user && category && since && until
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+/// The audit log, shared between the bot and the app.
+///
+/// Cloning is cheap and every clone appends to the same log.
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    entries: Arc<RwLock<Vec<AuditEntry>>>,
+    /// The JSONL file entries are appended to, `None` to keep them in
+    /// memory only.
+    path: Option<PathBuf>,
+}
+
+impl AuditLog {
+    pub fn in_memory() -> Self {
+        AuditLog::default()
+    }
+
+    /// Loads the log kept in the app's data directory.
+    pub fn load() -> Result<Self, AuditError> {
+        let path = app_bin::get_file_path(AUDIT_FILE, FileCategory::App.as_str())?;
+        Self::load_from(path)
+    }
+
+    /// Loads the log at `path`, starting empty if there is no file. Lines
+    /// that cannot be read, such as one cut short by a crash, are skipped.
+    pub fn load_from(path: PathBuf) -> Result<Self, AuditError> {
+        let mut entries = Vec::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str(&line) {
+                    Ok(entry) => entries.push(entry),
+                    Err(e) => println!("{}: {}", "Skipping Audit Entry".bright_red(), e),
+                }
+            }
+        }
+        Ok(AuditLog {
+            entries: Arc::new(RwLock::new(entries)),
+            path: Some(path),
+        })
+    }
+
+    /// Appends an entry, writing it to disk before it shows up in queries.
+    pub fn record(&self, entry: AuditEntry) -> Result<(), AuditError> {
+        let mut entries = self.entries.write().unwrap();
+        if let Some(path) = &self.path {
+            app_bin::ensure_directory_exists(path)?;
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            let mut line = serde_json::to_string(&entry)?;
+            line.push('\n');
+            file.write_all(line.as_bytes())?;
+        }
+        entries.push(entry);
+        Ok(())
+    }
+
+    /// Matching entries, oldest first.
+    pub fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|entry| query.matches(entry))
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the matching entries to `writer`.
+    pub fn export<W: Write>(
+        &self,
+        query: &AuditQuery,
+        format: ExportFormat,
+        writer: W,
+    ) -> Result<(), AuditError> {
+        let entries = self.query(query);
+        match format {
+            ExportFormat::Csv => export_csv(&entries, writer),
+            ExportFormat::Jsonl => export_jsonl(&entries, writer),
+        }
+    }
+}
+
+/// One JSON object per line, as in the log file.
+pub fn export_jsonl<W: Write>(entries: &[AuditEntry], mut writer: W) -> Result<(), AuditError> {
+    for entry in entries {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// One row per entry, with a column per category score. Categories
+/// without a field get a column if any entry has a score for them.
+pub fn export_csv<W: Write>(entries: &[AuditEntry], writer: W) -> Result<(), AuditError> {
+    let other: BTreeSet<&String> = entries
+        .iter()
+        .flat_map(|entry| entry.scores.other.keys())
+        .collect();
+    let score_columns: Vec<ModerationCategory> = ModerationCategory::ALL
+        .into_iter()
+        .chain(
+            other
+                .into_iter()
+                .map(|name| ModerationCategory::from(name.as_str())),
+        )
+        .collect();
+    let mut csv = csv::Writer::from_writer(writer);
+
+    let mut header: Vec<String> = [
+        "time",
+        "channel",
+        "origin",
+        "user_id",
+        "username",
+        "message_id",
+        "text",
+        "category",
+        "score",
+        "threshold",
+        "action",
+        "result",
+    ]
+    .iter()
+    .map(|column| column.to_string())
+    .collect();
+    header.extend(score_columns.iter().map(|category| category.to_string()));
+    csv.write_record(&header)?;
+
+    for entry in entries {
+        let mut row = vec![
+            entry.time().to_rfc3339(),
+            entry.channel.clone(),
+            format!("{:?}", entry.origin),
+            entry.message.user_id.clone(),
+            entry.message.username.clone(),
+            entry.message.message_id.clone(),
+            entry.message.text.clone(),
+            entry.message.category.to_string(),
+            entry.message.score.to_string(),
+            entry.threshold.map(|t| t.to_string()).unwrap_or_default(),
+            format!("{:?}", entry.action),
+            entry.result.to_string(),
+        ];
+        row.extend(
+            score_columns
+                .iter()
+                .map(|category| score_cell(&entry.scores, category)),
+        );
+        csv.write_record(&row)?;
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+/// The score for `category`, always with a decimal point, empty if the
+/// entry has none for a category without a field.
+fn score_cell(scores: &ModerationScores, category: &ModerationCategory) -> String {
+    match category {
+        ModerationCategory::Other(name) => scores
+            .other
+            .get(name)
+            .map(|score| format!("{:?}", score))
+            .unwrap_or_default(),
+        category => format!("{:?}", scores.get(category)),
+    }
+}
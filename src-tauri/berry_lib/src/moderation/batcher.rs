@@ -0,0 +1,299 @@
+//! Batching and caching calls to OpenAI's moderation endpoint.
+//!
+//! The endpoint takes an array of inputs, so instead of one request per
+//! chat message the `ModerationBatcher` waits a short window for more
+//! messages and classifies them together, mapping each result back to the
+//! message it belongs to. Chat repeats itself a lot, so results are also
+//! kept in an LRU cache keyed on the message's skeleton, and a repeated
+//! message is answered without asking OpenAI at all. Messages sent with an
+//! image skip both.
+//!
+//! `ModerationMetrics` counts what the batcher saved and how long OpenAI
+//! took. It is a cloneable handle, so the app can read the numbers of the
+//! bot's batcher.
+
+use super::normalize::{skeleton, NormalizedText};
+use super::provider::{ModerationProvider, ModerationResult};
+use crate::openai::moderation::{ModerationError, OpenAiProvider};
+use async_trait::async_trait;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+
+/// How long to wait for more messages and how many to send at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchConfig {
+    /// How long the first message of a batch waits for others.
+    pub window: Duration,
+    pub max_batch: usize,
+    /// Results kept for repeated messages.
+    pub cache_capacity: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            window: Duration::from_millis(50),
+            max_batch: 32,
+            cache_capacity: 1000,
+        }
+    }
+}
+
+/// What the batcher has done so far.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    /// Messages classified, including those answered from the cache.
+    pub messages: u64,
+    pub cache_hits: u64,
+    /// Requests made to OpenAI, which is what moderation costs.
+    pub requests: u64,
+    pub failed_requests: u64,
+    /// Inputs sent across all requests, after dropping duplicates.
+    pub inputs_sent: u64,
+    pub average_latency_ms: f64,
+    pub max_latency_ms: u64,
+}
+
+impl MetricsSnapshot {
+    /// Requests avoided by batching and caching compared to one request
+    /// per message.
+    pub fn requests_saved(&self) -> u64 {
+        self.messages.saturating_sub(self.requests)
+    }
+
+    pub fn cache_hit_rate(&self) -> f64 {
+        if self.messages == 0 {
+            return 0.0;
+        }
+        self.cache_hits as f64 / self.messages as f64
+    }
+}
+
+/// Shared counters for a batcher.
+#[derive(Clone, Default)]
+pub struct ModerationMetrics {
+    snapshot: Arc<Mutex<MetricsSnapshot>>,
+}
+
+impl ModerationMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        *self.snapshot.lock().unwrap() = MetricsSnapshot::default();
+    }
+
+    fn record_message(&self, cache_hit: bool) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        snapshot.messages += 1;
+        if cache_hit {
+            snapshot.cache_hits += 1;
+        }
+    }
+
+    fn record_request(&self, inputs: usize, latency: Duration, failed: bool) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let latency_ms = latency.as_millis() as u64;
+        let total = snapshot.average_latency_ms * snapshot.requests as f64 + latency_ms as f64;
+        snapshot.requests += 1;
+        snapshot.average_latency_ms = total / snapshot.requests as f64;
+        snapshot.max_latency_ms = snapshot.max_latency_ms.max(latency_ms);
+        snapshot.inputs_sent += inputs as u64;
+        if failed {
+            snapshot.failed_requests += 1;
+        }
+    }
+}
+
+type Cache = Arc<Mutex<LruCache<String, ModerationResult>>>;
+
+/// A message waiting for its batch to be sent.
+struct Pending {
+    text: String,
+    reply: oneshot::Sender<Result<ModerationResult, ModerationError>>,
+}
+
+/// Classifies messages with OpenAI in batches, answering repeats from a
+/// cache. Clones share the batch queue, cache and metrics.
+#[derive(Clone)]
+pub struct ModerationBatcher {
+    openai: OpenAiProvider,
+    config: BatchConfig,
+    cache: Cache,
+    metrics: ModerationMetrics,
+    /// Started on the first message, from within the runtime.
+    queue: Arc<OnceLock<mpsc::UnboundedSender<Pending>>>,
+}
+
+impl ModerationBatcher {
+    pub fn new(openai: OpenAiProvider, config: BatchConfig) -> Self {
+        let capacity = NonZeroUsize::new(config.cache_capacity).unwrap_or(NonZeroUsize::MIN);
+        ModerationBatcher {
+            openai,
+            config,
+            cache: Arc::new(Mutex::new(LruCache::new(capacity))),
+            metrics: ModerationMetrics::new(),
+            queue: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Counts into these metrics, e.g. ones shared with the app.
+    pub fn with_metrics(mut self, metrics: ModerationMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn metrics(&self) -> &ModerationMetrics {
+        &self.metrics
+    }
+
+    /// Classifies a message already reduced to its skeleton, which is also
+    /// its cache key.
+    async fn classify_skeleton(&self, text: String) -> Result<ModerationResult, ModerationError> {
+        if let Some(result) = self.cache.lock().unwrap().get(&text) {
+            self.metrics.record_message(true);
+            return Ok(result.clone());
+        }
+        self.metrics.record_message(false);
+
+        let (reply, answer) = oneshot::channel();
+        self.queue()
+            .send(Pending { text, reply })
+            .map_err(|_| ModerationError::Unavailable)?;
+        answer.await.map_err(|_| ModerationError::Unavailable)?
+    }
+
+    fn queue(&self) -> &mpsc::UnboundedSender<Pending> {
+        self.queue.get_or_init(|| {
+            let (queue, pending) = mpsc::unbounded_channel();
+            tokio::spawn(run_batches(
+                self.openai.clone(),
+                self.config.clone(),
+                self.cache.clone(),
+                self.metrics.clone(),
+                pending,
+            ));
+            queue
+        })
+    }
+}
+
+#[async_trait]
+impl ModerationProvider for ModerationBatcher {
+    fn name(&self) -> &str {
+        self.openai.name()
+    }
+
+    async fn classify(&self, text: &str) -> Result<ModerationResult, ModerationError> {
+        self.classify_skeleton(skeleton(text)).await
+    }
+
+    /// Messages linking an image are sent on their own, as a multimodal
+    /// input cannot share a request with other inputs, and are not cached.
+    async fn classify_message(
+        &self,
+        message: &NormalizedText,
+    ) -> Result<ModerationResult, ModerationError> {
+        if self.openai.image_urls(&message.original).is_empty() {
+            return self.classify_skeleton(message.skeleton.clone()).await;
+        }
+        self.metrics.record_message(false);
+        let started = Instant::now();
+        let result = self.openai.classify_message(message).await;
+        self.metrics
+            .record_request(1, started.elapsed(), result.is_err());
+        result
+    }
+}
+
+/// Gathers messages into batches until every batcher is dropped. Each
+/// batch is sent on its own task, so a slow request does not hold up the
+/// next batch.
+async fn run_batches(
+    openai: OpenAiProvider,
+    config: BatchConfig,
+    cache: Cache,
+    metrics: ModerationMetrics,
+    mut pending: mpsc::UnboundedReceiver<Pending>,
+) {
+    while let Some(first) = pending.recv().await {
+        let deadline = tokio::time::Instant::now() + config.window;
+        let mut batch = vec![first];
+        while batch.len() < config.max_batch.max(1) {
+            match tokio::time::timeout_at(deadline, pending.recv()).await {
+                Ok(Some(next)) => batch.push(next),
+                _ => break,
+            }
+        }
+        tokio::spawn(send_batch(
+            openai.clone(),
+            cache.clone(),
+            metrics.clone(),
+            batch,
+        ));
+    }
+}
+
+/// Sends each distinct text in the batch once and answers every message.
+async fn send_batch(
+    openai: OpenAiProvider,
+    cache: Cache,
+    metrics: ModerationMetrics,
+    batch: Vec<Pending>,
+) {
+    let mut texts: Vec<String> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for message in &batch {
+        if !positions.contains_key(&message.text) {
+            positions.insert(message.text.clone(), texts.len());
+            texts.push(message.text.clone());
+        }
+    }
+
+    let started = Instant::now();
+    let results = openai.classify_batch(&texts).await;
+    metrics.record_request(texts.len(), started.elapsed(), results.is_err());
+
+    match results {
+        Ok(results) => {
+            let mut cache = cache.lock().unwrap();
+            for (text, result) in texts.into_iter().zip(&results) {
+                cache.put(text, result.clone());
+            }
+            drop(cache);
+            for message in batch {
+                let result = results[positions[&message.text]].clone();
+                let _ = message.reply.send(Ok(result));
+            }
+        }
+        Err(e) => {
+            for message in batch {
+                let _ = message.reply.send(Err(copy_error(&e)));
+            }
+        }
+    }
+}
+
+/// `ModerationError` holds an `io::Error`, which cannot be cloned.
+fn copy_error(error: &ModerationError) -> ModerationError {
+    match error {
+        ModerationError::IoError(e) => {
+            ModerationError::IoError(std::io::Error::new(e.kind(), e.to_string()))
+        }
+        ModerationError::ApiError => ModerationError::ApiError,
+        ModerationError::ConnectionError => ModerationError::ConnectionError,
+        ModerationError::Unavailable => ModerationError::Unavailable,
+        ModerationError::TimedOut => ModerationError::TimedOut,
+    }
+}
@@ -0,0 +1,407 @@
+//! Channel-specific blocked terms.
+//!
+//! Terms catch what the moderation provider never will, such as doxxing
+//! patterns, rival spam domains and slurs in other languages. A term is a
+//! literal phrase, a phrase with `*` wildcards, or a regular expression,
+//! and carries its own punishment. Matching runs on the message's
+//! skeleton, so `ѕрам` written in Cyrillic or `$p4m` still match `spam`.
+//!
+//! Literal and wildcard terms sync both ways with the channel's Twitch
+//! blocked-terms list. Regex terms have no Twitch equivalent and stay
+//! local.
+
+use super::normalize::{skeleton, NormalizedText};
+use super::policy::RuleAction;
+use super::strikes::LadderStep;
+use crate::file_sys::app_bin::{self, FileCategory};
+use crate::twitch::helix::{HelixBlockedTerm, HelixClient, HelixError};
+use crate::twitch::twitch_api::normalize_channel;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+const BLOCKED_TERMS_FILE: &str = "moderation_blocked_terms.bin";
+
+/// The category blocked-term matches are recorded under.
+pub const BLOCKED_TERM_CATEGORY: &str = "blocked_term";
+
+/// Compiled patterns may not grow past this many bytes.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Debug)]
+pub enum BlockedTermError {
+    EmptyTerm,
+    /// A regex that does not compile. Holds the pattern and why.
+    InvalidPattern {
+        pattern: String,
+        message: String,
+    },
+    HelixError(HelixError),
+    StorageError(String),
+}
+
+impl std::fmt::Display for BlockedTermError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BlockedTermError::EmptyTerm => write!(f, "Blocked terms cannot be empty"),
+            BlockedTermError::InvalidPattern { pattern, message } => {
+                write!(f, "Invalid pattern {}: {}", pattern, message)
+            }
+            BlockedTermError::HelixError(e) => write!(f, "Helix Error: {}", e),
+            BlockedTermError::StorageError(e) => write!(f, "Storage Error: {}", e),
+        }
+    }
+}
+
+impl From<HelixError> for BlockedTermError {
+    fn from(err: HelixError) -> Self {
+        BlockedTermError::HelixError(err)
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for BlockedTermError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        BlockedTermError::StorageError(err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TermKind {
+    /// The phrase as whole words.
+    Literal,
+    /// Like `Literal`, but `*` stands for any run of characters within a
+    /// word, as on Twitch.
+    Wildcard,
+    Regex,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockedTerm {
+    pub pattern: String,
+    pub kind: TermKind,
+    pub punishment: LadderStep,
+    /// The term's id on the Twitch blocked-terms list, once synced.
+    pub twitch_id: Option<String>,
+}
+
+impl BlockedTerm {
+    pub fn new(kind: TermKind, pattern: &str) -> Self {
+        BlockedTerm {
+            pattern: pattern.trim().to_string(),
+            kind,
+            punishment: LadderStep::new(RuleAction::Delete),
+            twitch_id: None,
+        }
+    }
+
+    pub fn literal(pattern: &str) -> Self {
+        Self::new(TermKind::Literal, pattern)
+    }
+
+    pub fn wildcard(pattern: &str) -> Self {
+        Self::new(TermKind::Wildcard, pattern)
+    }
+
+    pub fn regex(pattern: &str) -> Self {
+        Self::new(TermKind::Regex, pattern)
+    }
+
+    pub fn with_punishment(mut self, punishment: LadderStep) -> Self {
+        self.punishment = punishment;
+        self
+    }
+
+    /// Whether the term can be kept on Twitch's blocked-terms list.
+    pub fn syncs_with_twitch(&self) -> bool {
+        self.kind != TermKind::Regex
+    }
+
+    /// A term pulled from Twitch, a wildcard if it contains `*`.
+    fn from_twitch(term: &HelixBlockedTerm) -> Self {
+        let kind = if term.text.contains('*') {
+            TermKind::Wildcard
+        } else {
+            TermKind::Literal
+        };
+        BlockedTerm {
+            twitch_id: Some(term.id.clone()),
+            ..Self::new(kind, &term.text)
+        }
+    }
+
+    fn compile(&self) -> Result<Regex, BlockedTermError> {
+        if self.pattern.trim().is_empty() {
+            return Err(BlockedTermError::EmptyTerm);
+        }
+        let pattern = match self.kind {
+            TermKind::Regex => self.pattern.clone(),
+            TermKind::Literal => whole_words(&regex::escape(&skeleton(&self.pattern))),
+            TermKind::Wildcard => whole_words(
+                &skeleton(&self.pattern)
+                    .split('*')
+                    .map(regex::escape)
+                    .collect::<Vec<_>>()
+                    .join(r"\S*"),
+            ),
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|e| BlockedTermError::InvalidPattern {
+                pattern: self.pattern.clone(),
+                message: e.to_string(),
+            })
+    }
+}
+
+/// How a sync changed both lists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    /// Terms added on Twitch that are now kept here too.
+    pub added_here: usize,
+    /// Terms added here that are now on Twitch too.
+    pub added_on_twitch: usize,
+    /// Terms removed on Twitch that are now removed here too.
+    pub removed_here: usize,
+    /// Terms removed here that are now removed on Twitch too.
+    pub removed_on_twitch: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ChannelTerms {
+    terms: Vec<BlockedTerm>,
+    /// Twitch ids of synced terms removed here, removed on Twitch at the
+    /// next sync.
+    removed_twitch_ids: Vec<String>,
+}
+
+type CompiledTerms = HashMap<String, Vec<(BlockedTerm, Regex)>>;
+
+/// Blocked terms of every channel, shared between the bot and the app.
+///
+/// Cloning is cheap and every clone sees the same terms.
+#[derive(Clone, Default)]
+pub struct BlockedTermStore {
+    channels: Arc<RwLock<HashMap<String, ChannelTerms>>>,
+    /// The terms compiled for matching, rebuilt on every change.
+    compiled: Arc<RwLock<CompiledTerms>>,
+    /// Where terms are saved, `None` to keep them in memory only.
+    path: Option<PathBuf>,
+}
+
+impl BlockedTermStore {
+    pub fn in_memory() -> Self {
+        BlockedTermStore::default()
+    }
+
+    /// Loads the terms saved in the app's config directory.
+    pub fn load() -> Result<Self, BlockedTermError> {
+        let path = app_bin::get_file_path(BLOCKED_TERMS_FILE, FileCategory::Config.as_str())?;
+        Self::load_from(path)
+    }
+
+    /// Loads terms saved at `path`, starting empty if there is no file.
+    pub fn load_from(path: PathBuf) -> Result<Self, BlockedTermError> {
+        let channels = if path.exists() {
+            app_bin::read_from_path(&path)?
+        } else {
+            HashMap::new()
+        };
+        let compiled = compile_all(&channels)?;
+        Ok(BlockedTermStore {
+            channels: Arc::new(RwLock::new(channels)),
+            compiled: Arc::new(RwLock::new(compiled)),
+            path: Some(path),
+        })
+    }
+
+    pub fn terms(&self, channel: &str) -> Vec<BlockedTerm> {
+        self.channels
+            .read()
+            .unwrap()
+            .get(&normalize_channel(channel))
+            .map(|list| list.terms.clone())
+            .unwrap_or_default()
+    }
+
+    /// Adds a term, or replaces the punishment of the same term.
+    pub fn add(&self, channel: &str, term: BlockedTerm) -> Result<(), BlockedTermError> {
+        term.compile()?;
+        self.update(|channels| {
+            let list = channels.entry(normalize_channel(channel)).or_default();
+            match list
+                .terms
+                .iter_mut()
+                .find(|existing| existing.kind == term.kind && existing.pattern == term.pattern)
+            {
+                Some(existing) => existing.punishment = term.punishment,
+                None => list.terms.push(term),
+            }
+        })
+    }
+
+    /// Removes a term by its pattern and returns it. A synced term is also
+    /// removed on Twitch at the next sync.
+    pub fn remove(
+        &self,
+        channel: &str,
+        pattern: &str,
+    ) -> Result<Option<BlockedTerm>, BlockedTermError> {
+        let mut removed = None;
+        self.update(|channels| {
+            if let Some(list) = channels.get_mut(&normalize_channel(channel)) {
+                if let Some(index) = list.terms.iter().position(|term| term.pattern == pattern) {
+                    let term = list.terms.remove(index);
+                    list.removed_twitch_ids.extend(term.twitch_id.clone());
+                    removed = Some(term);
+                }
+            }
+        })?;
+        Ok(removed)
+    }
+
+    /// The first of the channel's terms found in `text`.
+    pub fn find(&self, channel: &str, text: &str) -> Option<BlockedTerm> {
+        self.find_normalized(channel, &NormalizedText::new(text))
+    }
+
+    /// Like `find`, for a message already normalized. Regex terms are also
+    /// tried on the original text, so they can match what normalizing
+    /// changes.
+    pub fn find_normalized(&self, channel: &str, text: &NormalizedText) -> Option<BlockedTerm> {
+        let compiled = self.compiled.read().unwrap();
+        compiled
+            .get(&normalize_channel(channel))?
+            .iter()
+            .find(|(term, regex)| {
+                regex.is_match(&text.skeleton)
+                    || (term.kind == TermKind::Regex && regex.is_match(&text.original))
+            })
+            .map(|(term, _)| term.clone())
+    }
+
+    /// Brings the channel's terms and its Twitch blocked-terms list in line.
+    /// Removals on either side are carried over, then unsynced terms are
+    /// added to the other side.
+    pub async fn sync(
+        &self,
+        channel: &str,
+        helix: &HelixClient,
+        broadcaster_id: &str,
+    ) -> Result<SyncReport, BlockedTermError> {
+        let key = normalize_channel(channel);
+        let local = self
+            .channels
+            .read()
+            .unwrap()
+            .get(&key)
+            .cloned()
+            .unwrap_or_default();
+        let mut report = SyncReport::default();
+
+        for id in &local.removed_twitch_ids {
+            helix.remove_blocked_term(broadcaster_id, id).await?;
+            report.removed_on_twitch += 1;
+        }
+
+        let remote = helix.get_blocked_terms(broadcaster_id).await?;
+        let mut pushed: Vec<(String, String)> = Vec::new();
+        for term in local
+            .terms
+            .iter()
+            .filter(|term| term.syncs_with_twitch() && term.twitch_id.is_none())
+        {
+            let id = match remote
+                .iter()
+                .find(|remote| remote.text.eq_ignore_ascii_case(&term.pattern))
+            {
+                Some(existing) => existing.id.clone(),
+                None => {
+                    report.added_on_twitch += 1;
+                    helix
+                        .add_blocked_term(broadcaster_id, &term.pattern)
+                        .await?
+                        .id
+                }
+            };
+            pushed.push((term.pattern.clone(), id));
+        }
+
+        let mut known: HashSet<String> = remote.iter().map(|term| term.id.clone()).collect();
+        known.extend(pushed.iter().map(|(_, id)| id.clone()));
+        self.update(|channels| {
+            let list = channels.entry(key).or_default();
+            list.removed_twitch_ids
+                .retain(|id| !local.removed_twitch_ids.contains(id));
+
+            for (pattern, id) in &pushed {
+                if let Some(term) = list.terms.iter_mut().find(|term| {
+                    term.syncs_with_twitch() && term.twitch_id.is_none() && term.pattern == *pattern
+                }) {
+                    term.twitch_id = Some(id.clone());
+                }
+            }
+
+            let before = list.terms.len();
+            list.terms
+                .retain(|term| term.twitch_id.as_ref().is_none_or(|id| known.contains(id)));
+            report.removed_here = before - list.terms.len();
+
+            for term in &remote {
+                let tracked = list
+                    .terms
+                    .iter()
+                    .any(|local| local.twitch_id.as_deref() == Some(term.id.as_str()));
+                if !tracked && !list.removed_twitch_ids.contains(&term.id) {
+                    list.terms.push(BlockedTerm::from_twitch(term));
+                    report.added_here += 1;
+                }
+            }
+        })?;
+        Ok(report)
+    }
+
+    /// Applies `change` and saves the result, leaving the terms untouched
+    /// if saving fails.
+    fn update<F>(&self, change: F) -> Result<(), BlockedTermError>
+    where
+        F: FnOnce(&mut HashMap<String, ChannelTerms>),
+    {
+        let mut channels = self.channels.write().unwrap();
+        let mut updated = channels.clone();
+        change(&mut updated);
+        let compiled = compile_all(&updated)?;
+        if let Some(path) = &self.path {
+            app_bin::write_to_path(&updated, path)?;
+        }
+        *channels = updated;
+        *self.compiled.write().unwrap() = compiled;
+        Ok(())
+    }
+}
+
+fn compile_all(
+    channels: &HashMap<String, ChannelTerms>,
+) -> Result<CompiledTerms, BlockedTermError> {
+    channels
+        .iter()
+        .map(|(channel, list)| {
+            let terms = list
+                .terms
+                .iter()
+                .map(|term| Ok((term.clone(), term.compile()?)))
+                .collect::<Result<Vec<_>, BlockedTermError>>()?;
+            Ok((channel.clone(), terms))
+        })
+        .collect()
+}
+
+/// Surrounds a pattern so it only matches whole words.
+fn whole_words(pattern: &str) -> String {
+    format!(r"(?:^|[^\p{{L}}\p{{N}}]){}(?:$|[^\p{{L}}\p{{N}}])", pattern)
+}
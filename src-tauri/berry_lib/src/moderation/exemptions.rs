@@ -0,0 +1,99 @@
+//! Chatters whose messages are never sent for moderation.
+//!
+//! Exempt messages skip the moderation provider entirely, so trusted
+//! chatters cost nothing to moderate and can never be punished by mistake.
+
+use crate::twitch::chat_event::SubTier;
+use crate::twitch::tags::Badges;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Logins of common chat bots, whose messages are usually links and
+/// command output.
+pub const KNOWN_BOTS: &[&str] = &[
+    "nightbot",
+    "streamelements",
+    "streamlabs",
+    "moobot",
+    "fossabot",
+    "wizebot",
+    "sery_bot",
+    "soundalerts",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExemptionRules {
+    pub broadcaster: bool,
+    pub moderators: bool,
+    pub vips: bool,
+    /// Subscribers of at least this tier are exempt.
+    pub subscriber_tier: Option<SubTier>,
+    /// Subscribers of at least this many months are exempt. When both this
+    /// and `subscriber_tier` are set, a subscriber has to meet both.
+    pub subscriber_months: Option<u32>,
+    /// User ids that are always exempt.
+    pub allowed_user_ids: HashSet<String>,
+    /// Logins of bots that are always exempt.
+    pub known_bots: HashSet<String>,
+}
+
+impl Default for ExemptionRules {
+    fn default() -> Self {
+        ExemptionRules {
+            broadcaster: true,
+            moderators: true,
+            vips: true,
+            subscriber_tier: None,
+            subscriber_months: None,
+            allowed_user_ids: HashSet::new(),
+            known_bots: KNOWN_BOTS.iter().map(|bot| bot.to_string()).collect(),
+        }
+    }
+}
+
+impl ExemptionRules {
+    /// Rules that exempt nobody.
+    pub fn none() -> Self {
+        ExemptionRules {
+            broadcaster: false,
+            moderators: false,
+            vips: false,
+            subscriber_tier: None,
+            subscriber_months: None,
+            allowed_user_ids: HashSet::new(),
+            known_bots: HashSet::new(),
+        }
+    }
+
+    pub fn allow_user(mut self, user_id: &str) -> Self {
+        self.allowed_user_ids.insert(user_id.to_string());
+        self
+    }
+
+    pub fn with_bot(mut self, login: &str) -> Self {
+        self.known_bots.insert(login.to_lowercase());
+        self
+    }
+
+    pub fn is_exempt(&self, user_id: &str, login: &str, badges: &Badges) -> bool {
+        (self.broadcaster && badges.broadcaster)
+            || (self.moderators && badges.moderator)
+            || (self.vips && badges.vip)
+            || self.exempts_subscriber(badges)
+            || self.allowed_user_ids.contains(user_id)
+            || self.known_bots.contains(&login.to_lowercase())
+    }
+
+    fn exempts_subscriber(&self, badges: &Badges) -> bool {
+        let (tier, months) = match (badges.subscriber_tier(), badges.subscriber) {
+            (Some(tier), Some(months)) => (tier, months),
+            _ => return false,
+        };
+        if self.subscriber_tier.is_none() && self.subscriber_months.is_none() {
+            return false;
+        }
+        self.subscriber_tier.is_none_or(|min| tier >= min)
+            && self.subscriber_months.is_none_or(|min| months >= min)
+    }
+}
@@ -0,0 +1,131 @@
+//! An offline classifier built on a lexicon of terms.
+//!
+//! It is far cruder than a hosted model, but needs no network or key and
+//! always scores a message the same way, which also makes it useful in
+//! tests.
+
+use super::provider::{ModerationProvider, ModerationResult};
+use crate::openai::moderation::{ModerationCategory, ModerationError};
+use async_trait::async_trait;
+
+/// Scores at or above this mark the category as flagged.
+pub const FLAG_SCORE: f64 = 0.5;
+
+/// Phrases that are almost never harmless, with the category and score
+/// they get.
+const DEFAULT_LEXICON: &[(ModerationCategory, &str, f64)] = &[
+    (
+        ModerationCategory::HarassmentThreatening,
+        "kill yourself",
+        0.98,
+    ),
+    (ModerationCategory::HarassmentThreatening, "kys", 0.98),
+    (
+        ModerationCategory::HarassmentThreatening,
+        "i will find you",
+        0.9,
+    ),
+    (ModerationCategory::Violence, "i will kill you", 0.96),
+    (ModerationCategory::Violence, "i'm going to kill you", 0.96),
+    (ModerationCategory::Violence, "im going to kill you", 0.96),
+    (ModerationCategory::Violence, "shoot you", 0.9),
+    (ModerationCategory::SelfHarmIntent, "kill myself", 0.96),
+    (ModerationCategory::SelfHarmIntent, "i want to die", 0.96),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexiconEntry {
+    pub category: ModerationCategory,
+    /// Whole words to look for, matched case-insensitively.
+    pub term: String,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalClassifier {
+    lexicon: Vec<LexiconEntry>,
+}
+
+impl Default for LocalClassifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalClassifier {
+    /// A classifier with the built-in lexicon.
+    pub fn new() -> Self {
+        DEFAULT_LEXICON
+            .iter()
+            .fold(Self::empty(), |classifier, (category, term, score)| {
+                classifier.with_term(category.clone(), term, *score)
+            })
+    }
+
+    /// A classifier that flags nothing until terms are added.
+    pub fn empty() -> Self {
+        LocalClassifier {
+            lexicon: Vec::new(),
+        }
+    }
+
+    /// Scores messages containing `term` with `score` in `category`.
+    pub fn with_term(mut self, category: ModerationCategory, term: &str, score: f64) -> Self {
+        self.lexicon.push(LexiconEntry {
+            category,
+            term: normalize(term),
+            score,
+        });
+        self
+    }
+
+    pub fn lexicon(&self) -> &[LexiconEntry] {
+        &self.lexicon
+    }
+
+    /// Scores each category by its highest matching term.
+    pub fn classify_text(&self, text: &str) -> ModerationResult {
+        let text = normalize(text);
+        let mut result = ModerationResult::default();
+
+        for entry in &self.lexicon {
+            if entry.term.is_empty() || !contains_words(&text, &entry.term) {
+                continue;
+            }
+            if entry.score > result.scores.get(&entry.category) {
+                result.scores.set(&entry.category, entry.score);
+            }
+            if entry.score >= FLAG_SCORE {
+                result.categories.set(&entry.category, true);
+                result.flagged = true;
+            }
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl ModerationProvider for LocalClassifier {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    async fn classify(&self, text: &str) -> Result<ModerationResult, ModerationError> {
+        Ok(self.classify_text(text))
+    }
+}
+
+/// Lowercases and keeps only words, separated by single spaces.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether `words` appears in `text` starting and ending on word boundaries.
+fn contains_words(text: &str, words: &str) -> bool {
+    format!(" {} ", text).contains(&format!(" {} ", words))
+}
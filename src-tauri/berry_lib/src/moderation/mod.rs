@@ -0,0 +1,13 @@
+pub mod audit;
+pub mod batcher;
+pub mod blocked_terms;
+pub mod exemptions;
+pub mod local;
+pub mod normalize;
+pub mod policy;
+pub mod pool;
+pub mod provider;
+pub mod review;
+pub mod shadow;
+pub mod spam;
+pub mod strikes;
@@ -0,0 +1,274 @@
+//! Turning chat messages into a canonical skeleton before moderation.
+//!
+//! Chatters dodge filters with lookalike letters from other scripts,
+//! full-width and stylised characters, zero-width joiners, stretched
+//! letters and leetspeak. The skeleton undoes all of that, so `ｆ𝐫ее m0ney`
+//! and `free money` look the same to blocked terms, the spam filters and the
+//! moderation provider. The original text is kept for logs and replies.
+//!
+//! The stages run in this order:
+//!
+//! 1. invisible characters such as zero-width spaces are dropped,
+//! 2. NFKC folds full-width, stylised and compatibility characters,
+//! 3. decorative accents and zalgo marks are stripped from Latin letters,
+//! 4. the text is lowercased,
+//! 5. words that mix in Latin letters or are spelled entirely in lookalikes
+//!    are mapped to Latin, leaving ordinary text in other scripts alone,
+//! 6. leetspeak is decoded in words that contain letters,
+//! 7. runs of three or more of the same character, and of whitespace, are
+//!    collapsed to one.
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// A chat message as sent and as moderated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizedText {
+    pub original: String,
+    pub skeleton: String,
+}
+
+impl NormalizedText {
+    pub fn new(text: &str) -> Self {
+        NormalizedText {
+            original: text.to_string(),
+            skeleton: skeleton(text),
+        }
+    }
+
+    /// Whether normalizing changed anything beyond case and spacing.
+    pub fn was_obfuscated(&self) -> bool {
+        let plain = collapse_whitespace(&self.original.to_lowercase());
+        plain != self.skeleton
+    }
+}
+
+/// Runs every stage over `text`.
+pub fn skeleton(text: &str) -> String {
+    let text = strip_invisible(text);
+    let text: String = text.nfkc().collect();
+    let text = collapse_whitespace(&strip_marks(&text).to_lowercase());
+    let text = map_confusables(&text);
+    let text = decode_leet(&text);
+    collapse_repeats(&text)
+}
+
+/// Drops zero-width and other invisible formatting characters.
+pub fn strip_invisible(text: &str) -> String {
+    text.chars().filter(|c| !is_invisible(*c)).collect()
+}
+
+/// Drops combining marks from the diacritical blocks, which is what zalgo
+/// text is stacked from, and reduces accented Latin letters to their base
+/// letter. Marks that belong to other scripts, e.g. Devanagari vowel signs,
+/// are kept.
+pub fn strip_marks(text: &str) -> String {
+    text.chars()
+        .filter(|c| !is_combining_mark(*c))
+        .map(|c| {
+            if c.is_ascii() || !c.is_alphabetic() {
+                return c;
+            }
+            match c.to_string().nfd().next() {
+                Some(base) if base.is_ascii_alphabetic() => base,
+                _ => c,
+            }
+        })
+        .collect()
+}
+
+/// Maps lookalike letters to Latin in words that contain Latin letters or
+/// consist only of lookalikes, so `раypal` and `ѕрам` are caught while
+/// `привет` stays Cyrillic. Expects lowercase text.
+pub fn map_confusables(text: &str) -> String {
+    map_words(text, |word| {
+        let has_latin = word.chars().any(|c| c.is_ascii_alphabetic());
+        let all_confusable = word
+            .chars()
+            .filter(|c| c.is_alphabetic() && !c.is_ascii())
+            .all(|c| confusable(c).is_some());
+        if has_latin || all_confusable {
+            word.chars().map(|c| confusable(c).unwrap_or(c)).collect()
+        } else {
+            word.to_string()
+        }
+    })
+}
+
+/// Decodes digits and symbols standing in for letters, e.g. `h4ck3r`.
+/// Words without letters, such as numbers, are left alone. Symbols are only
+/// decoded between letters or digits, so `!hello`, `@user` and `hello!`
+/// keep theirs.
+pub fn decode_leet(text: &str) -> String {
+    map_words(text, |word| {
+        if !word.chars().any(char::is_alphabetic) {
+            return word.to_string();
+        }
+        let mut alphanumeric = word
+            .char_indices()
+            .filter(|(_, c)| c.is_alphanumeric())
+            .map(|(i, _)| i);
+        let first = alphanumeric.next().unwrap_or(0);
+        let last = alphanumeric.next_back().unwrap_or(first);
+        word.char_indices()
+            .map(|(i, c)| match (leet(c), c.is_ascii_digit()) {
+                (Some(letter), true) => letter,
+                (Some(letter), false) if first < i && i < last => letter,
+                _ => c,
+            })
+            .collect()
+    })
+}
+
+/// Collapses runs of three or more of the same character to one, so
+/// `heeeey` becomes `hey` while `good` keeps its double `o`, and runs of
+/// whitespace to a single space.
+pub fn collapse_repeats(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let chars: Vec<char> = collapse_whitespace(text).chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let run = chars[i..].iter().take_while(|c| **c == chars[i]).count();
+        let keep = if run >= 3 { 1 } else { run };
+        collapsed.extend(std::iter::repeat_n(chars[i], keep));
+        i += run;
+    }
+    collapsed
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Applies `map` to every space-separated word.
+fn map_words<F>(text: &str, map: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    text.split(' ').map(map).collect::<Vec<_>>().join(" ")
+}
+
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{034F}'
+            | '\u{061C}'
+            | '\u{115F}'
+            | '\u{1160}'
+            | '\u{17B4}'
+            | '\u{17B5}'
+            | '\u{180B}'..='\u{180F}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{206F}'
+            | '\u{3164}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FEFF}'
+            | '\u{FFA0}'
+            | '\u{E0000}'..='\u{E007F}'
+    )
+}
+
+/// Combining marks from the diacritical blocks, stacked on letters to make
+/// zalgo text.
+pub(crate) fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+/// The Latin letter a lowercase character is commonly passed off as.
+fn confusable(c: char) -> Option<char> {
+    let latin = match c {
+        // Cyrillic
+        'а' => 'a',
+        'в' => 'b',
+        'с' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ё' => 'e',
+        'һ' => 'h',
+        'і' | 'ӏ' => 'i',
+        'ј' => 'j',
+        'к' => 'k',
+        'м' => 'm',
+        'п' => 'n',
+        'о' => 'o',
+        'р' => 'p',
+        'ԛ' => 'q',
+        'г' => 'r',
+        'ѕ' => 's',
+        'т' => 't',
+        'ѵ' => 'v',
+        'ԝ' | 'ш' => 'w',
+        'х' => 'x',
+        'у' => 'y',
+        // Greek
+        'α' => 'a',
+        'β' => 'b',
+        'ϲ' => 'c',
+        'ε' => 'e',
+        'η' => 'n',
+        'ι' => 'i',
+        'κ' => 'k',
+        'ν' => 'v',
+        'ο' | 'σ' => 'o',
+        'ρ' => 'p',
+        'τ' => 't',
+        'υ' => 'u',
+        'ω' => 'w',
+        'χ' => 'x',
+        'γ' => 'y',
+        // Armenian
+        'ո' => 'n',
+        'օ' => 'o',
+        'ս' => 'u',
+        'հ' => 'h',
+        // Latin small capitals and other lookalikes
+        'ᴀ' | 'ɑ' => 'a',
+        'ʙ' => 'b',
+        'ᴄ' => 'c',
+        'ᴅ' | 'đ' => 'd',
+        'ᴇ' => 'e',
+        'ɢ' | 'ɡ' => 'g',
+        'ʜ' | 'ħ' => 'h',
+        'ɪ' | 'ı' => 'i',
+        'ᴊ' => 'j',
+        'ᴋ' => 'k',
+        'ʟ' | 'ł' => 'l',
+        'ᴍ' => 'm',
+        'ɴ' => 'n',
+        'ᴏ' | 'ø' => 'o',
+        'ᴘ' => 'p',
+        'ʀ' => 'r',
+        'ꜱ' => 's',
+        'ᴛ' => 't',
+        'ᴜ' => 'u',
+        'ᴠ' => 'v',
+        'ᴡ' => 'w',
+        'ʏ' => 'y',
+        'ᴢ' => 'z',
+        _ => return None,
+    };
+    Some(latin)
+}
+
+/// The letter a digit or symbol stands for in leetspeak.
+fn leet(c: char) -> Option<char> {
+    let letter = match c {
+        '0' => 'o',
+        '1' | '!' | '|' => 'i',
+        '3' => 'e',
+        '4' | '@' => 'a',
+        '5' | '$' => 's',
+        '7' | '+' => 't',
+        _ => return None,
+    };
+    Some(letter)
+}
@@ -0,0 +1,530 @@
+//! Per-channel moderation policies.
+//!
+//! A policy holds, for every OpenAI moderation category, the score at which
+//! a message is punished and what the punishment is. Policies are saved as
+//! versioned JSON in the app's config directory and shared through
+//! `PolicyStore`, so edits from the desktop app reach a running bot without
+//! a restart.
+
+use crate::file_sys::app_bin::{self, FileCategory};
+use crate::openai::moderation::{self, ModerationCategory, ModerationScores, PunishmentAction};
+use crate::twitch::twitch_api::normalize_channel;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+const POLICY_FILE: &str = "moderation_policies.json";
+
+/// The version of the saved policies. Bump it, and migrate in
+/// `PolicyFile::read`, whenever a saved policy changes shape.
+const POLICY_VERSION: u32 = 1;
+
+/// The longest timeout Twitch allows: two weeks.
+pub const MAX_TIMEOUT_SECS: u64 = 1_209_600;
+
+#[derive(Debug)]
+pub enum PolicyError {
+    /// A threshold outside 0.0 to 1.0.
+    InvalidThreshold {
+        category: String,
+        threshold: f64,
+    },
+    /// A timeout shorter than a second or longer than two weeks.
+    InvalidDuration {
+        category: String,
+        duration_secs: u64,
+    },
+    StorageError(String),
+}
+
+impl std::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PolicyError::InvalidThreshold {
+                category,
+                threshold,
+            } => write!(
+                f,
+                "Threshold for {} must be between 0 and 1, got {}",
+                category, threshold
+            ),
+            PolicyError::InvalidDuration {
+                category,
+                duration_secs,
+            } => write!(
+                f,
+                "Timeout for {} must be between 1 and {} seconds, got {}",
+                category, MAX_TIMEOUT_SECS, duration_secs
+            ),
+            PolicyError::StorageError(e) => write!(f, "Storage Error: {}", e),
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for PolicyError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        PolicyError::StorageError(err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleAction {
+    None,
+    Warn,
+    Delete,
+    Timeout,
+    Ban,
+}
+
+/// What happens to a message flagged in one category.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryRule {
+    /// Scores at or above this trigger the action, from 0.0 to 1.0.
+    pub threshold: f64,
+    pub action: RuleAction,
+    /// Timeout length in seconds. Only used by `RuleAction::Timeout`.
+    pub duration_secs: u64,
+}
+
+impl CategoryRule {
+    pub fn new(threshold: f64, action: RuleAction) -> Self {
+        CategoryRule {
+            threshold,
+            action,
+            duration_secs: 0,
+        }
+    }
+
+    pub fn timeout(threshold: f64, duration_secs: u64) -> Self {
+        CategoryRule {
+            threshold,
+            action: RuleAction::Timeout,
+            duration_secs,
+        }
+    }
+
+    pub fn punishment(&self) -> PunishmentAction {
+        match self.action {
+            RuleAction::None => PunishmentAction::None,
+            RuleAction::Warn => PunishmentAction::Warn,
+            RuleAction::Delete => PunishmentAction::Delete,
+            RuleAction::Timeout => PunishmentAction::Timeout(self.duration_secs),
+            RuleAction::Ban => PunishmentAction::Ban,
+        }
+    }
+
+    fn validate(&self, category: &str) -> Result<(), PolicyError> {
+        if !(0.0..=1.0).contains(&self.threshold) {
+            return Err(PolicyError::InvalidThreshold {
+                category: category.to_string(),
+                threshold: self.threshold,
+            });
+        }
+        if self.action == RuleAction::Timeout
+            && !(1..=MAX_TIMEOUT_SECS).contains(&self.duration_secs)
+        {
+            return Err(PolicyError::InvalidDuration {
+                category: category.to_string(),
+                duration_secs: self.duration_secs,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// One rule per `ModerationCategories` field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModerationPolicy {
+    pub harassment: CategoryRule,
+
+    #[serde(rename = "harassment/threatening")]
+    pub harassment_threatening: CategoryRule,
+
+    pub hate: CategoryRule,
+
+    #[serde(rename = "hate/threatening")]
+    pub hate_threatening: CategoryRule,
+
+    #[serde(rename = "self-harm")]
+    pub self_harm: CategoryRule,
+
+    #[serde(rename = "self-harm/instructions")]
+    pub self_harm_instructions: CategoryRule,
+
+    #[serde(rename = "self-harm/intent")]
+    pub self_harm_intent: CategoryRule,
+
+    pub sexual: CategoryRule,
+
+    #[serde(rename = "sexual/minors")]
+    pub sexual_minors: CategoryRule,
+
+    pub violence: CategoryRule,
+
+    #[serde(rename = "violence/graphic")]
+    pub violence_graphic: CategoryRule,
+
+    /// Rules for categories without a field, e.g. ones OpenAI added later,
+    /// by OpenAI's name.
+    #[serde(default)]
+    pub other: BTreeMap<String, CategoryRule>,
+}
+
+/// The rule for a category nobody has set one for: it never acts.
+const NO_RULE: CategoryRule = CategoryRule {
+    threshold: 1.0,
+    action: RuleAction::None,
+    duration_secs: 0,
+};
+
+impl Default for ModerationPolicy {
+    fn default() -> Self {
+        ModerationPolicy {
+            harassment: CategoryRule::new(0.950, RuleAction::Warn),
+            harassment_threatening: CategoryRule::new(0.970, RuleAction::Ban),
+            hate: CategoryRule::timeout(0.55, 60),
+            hate_threatening: CategoryRule::new(0.960, RuleAction::Ban),
+            self_harm: CategoryRule::new(0.980, RuleAction::Delete),
+            self_harm_instructions: CategoryRule::new(0.970, RuleAction::Delete),
+            self_harm_intent: CategoryRule::timeout(0.950, 120),
+            sexual: CategoryRule::new(0.88, RuleAction::Delete),
+            sexual_minors: CategoryRule::new(0.50, RuleAction::Ban),
+            violence: CategoryRule::timeout(0.95, 30),
+            violence_graphic: CategoryRule::new(0.990, RuleAction::Delete),
+            other: BTreeMap::new(),
+        }
+    }
+}
+
+impl ModerationPolicy {
+    /// The rule for `category`. Categories in `other` without a rule get
+    /// one that never acts.
+    pub fn rule(&self, category: &ModerationCategory) -> &CategoryRule {
+        match category {
+            ModerationCategory::Harassment => &self.harassment,
+            ModerationCategory::HarassmentThreatening => &self.harassment_threatening,
+            ModerationCategory::Hate => &self.hate,
+            ModerationCategory::HateThreatening => &self.hate_threatening,
+            ModerationCategory::SelfHarm => &self.self_harm,
+            ModerationCategory::SelfHarmInstructions => &self.self_harm_instructions,
+            ModerationCategory::SelfHarmIntent => &self.self_harm_intent,
+            ModerationCategory::Sexual => &self.sexual,
+            ModerationCategory::SexualMinors => &self.sexual_minors,
+            ModerationCategory::Violence => &self.violence,
+            ModerationCategory::ViolenceGraphic => &self.violence_graphic,
+            ModerationCategory::Other(name) => self.other.get(name).unwrap_or(&NO_RULE),
+        }
+    }
+
+    /// The rule for `category`, adding one that never acts to `other` if
+    /// the category has none.
+    pub fn rule_mut(&mut self, category: &ModerationCategory) -> &mut CategoryRule {
+        match category {
+            ModerationCategory::Harassment => &mut self.harassment,
+            ModerationCategory::HarassmentThreatening => &mut self.harassment_threatening,
+            ModerationCategory::Hate => &mut self.hate,
+            ModerationCategory::HateThreatening => &mut self.hate_threatening,
+            ModerationCategory::SelfHarm => &mut self.self_harm,
+            ModerationCategory::SelfHarmInstructions => &mut self.self_harm_instructions,
+            ModerationCategory::SelfHarmIntent => &mut self.self_harm_intent,
+            ModerationCategory::Sexual => &mut self.sexual,
+            ModerationCategory::SexualMinors => &mut self.sexual_minors,
+            ModerationCategory::Violence => &mut self.violence,
+            ModerationCategory::ViolenceGraphic => &mut self.violence_graphic,
+            ModerationCategory::Other(name) => self.other.entry(name.clone()).or_insert(NO_RULE),
+        }
+    }
+
+    /// Every rule with its category, those in `other` last.
+    pub fn rules(&self) -> Vec<(ModerationCategory, &CategoryRule)> {
+        let other = self
+            .other
+            .iter()
+            .map(|(name, rule)| (ModerationCategory::from(name.as_str()), rule));
+        ModerationCategory::ALL
+            .into_iter()
+            .map(|category| {
+                let rule = self.rule(&category);
+                (category, rule)
+            })
+            .chain(other)
+            .collect()
+    }
+
+    /// What a message scoring `score` in `category` earns, `None` below the
+    /// threshold.
+    pub fn punishment_for(&self, category: &ModerationCategory, score: f64) -> PunishmentAction {
+        let rule = self.rule(category);
+        if moderation::round_to_decimal_places(score) >= rule.threshold {
+            rule.punishment()
+        } else {
+            PunishmentAction::None
+        }
+    }
+
+    /// Every category whose score reaches its rule's threshold, whether or
+    /// not the provider flagged it.
+    pub fn reached(&self, scores: &ModerationScores) -> Vec<ModerationCategory> {
+        self.rules()
+            .into_iter()
+            .filter(|(category, rule)| {
+                moderation::round_to_decimal_places(scores.get(category)) >= rule.threshold
+            })
+            .map(|(category, _)| category)
+            .collect()
+    }
+
+    /// Decides on a message flagged in `flagged` categories by looking at
+    /// all of them together: the message gets the harshest punishment any
+    /// of them earns, and is recorded under that category. Categories
+    /// earning the same punishment, or none at all, are ranked by `order`.
+    /// `None` if nothing is flagged.
+    pub fn decide(
+        &self,
+        flagged: &[ModerationCategory],
+        scores: &ModerationScores,
+        order: &SeverityOrder,
+    ) -> Option<Offence> {
+        let mut categories = flagged.to_vec();
+        categories.sort_by_key(|category| order.rank(category));
+        categories.dedup();
+
+        let category = categories
+            .iter()
+            .min_by_key(|category| {
+                let punishment = self.punishment_for(category, scores.get(category));
+                (
+                    std::cmp::Reverse(punishment.severity()),
+                    order.rank(category),
+                )
+            })?
+            .clone();
+        let score = scores.get(&category);
+        Some(Offence {
+            threshold: self.rule(&category).threshold,
+            punishment: self.punishment_for(&category, score),
+            category,
+            categories,
+            score,
+        })
+    }
+
+    /// The category closest to punishing a message that scored below every
+    /// threshold, if its score is within `margin` of the threshold. Such a
+    /// message is a candidate for a moderator to review. Categories whose
+    /// rule does nothing are never borderline.
+    pub fn borderline(
+        &self,
+        scores: &ModerationScores,
+        margin: f64,
+    ) -> Option<(ModerationCategory, &CategoryRule)> {
+        let score = |category: &ModerationCategory| {
+            moderation::round_to_decimal_places(scores.get(category))
+        };
+        if self.rules().iter().any(|(category, rule)| {
+            rule.action != RuleAction::None && score(category) >= rule.threshold
+        }) {
+            return None;
+        }
+        self.rules()
+            .into_iter()
+            .filter(|(category, rule)| {
+                rule.action != RuleAction::None && score(category) >= rule.threshold - margin
+            })
+            .min_by(|(a, rule_a), (b, rule_b)| {
+                let gap_a = rule_a.threshold - score(a);
+                let gap_b = rule_b.threshold - score(b);
+                gap_a.total_cmp(&gap_b)
+            })
+    }
+
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        self.rules()
+            .iter()
+            .try_for_each(|(category, rule)| rule.validate(category.as_str()))
+    }
+}
+
+/// The decision on a flagged message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Offence {
+    /// The category the message is punished and recorded for.
+    pub category: ModerationCategory,
+    /// Every flagged category, most severe first.
+    pub categories: Vec<ModerationCategory>,
+    pub score: f64,
+    pub threshold: f64,
+    pub punishment: PunishmentAction,
+}
+
+/// Which flagged category counts when several earn the same punishment,
+/// most severe first. Categories left out rank after the listed ones, in
+/// `ModerationCategory::ALL` order and then those without a field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeverityOrder(Vec<ModerationCategory>);
+
+impl SeverityOrder {
+    pub fn new(order: Vec<ModerationCategory>) -> Self {
+        SeverityOrder(order)
+    }
+
+    /// The category's place in the order, lower is more severe.
+    pub fn rank(&self, category: &ModerationCategory) -> usize {
+        match self.0.iter().position(|c| c == category) {
+            Some(rank) => rank,
+            None => {
+                let fallback = ModerationCategory::ALL.iter().position(|c| c == category);
+                self.0.len() + fallback.unwrap_or(ModerationCategory::ALL.len())
+            }
+        }
+    }
+
+    pub fn categories(&self) -> &[ModerationCategory] {
+        &self.0
+    }
+}
+
+impl Default for SeverityOrder {
+    fn default() -> Self {
+        SeverityOrder(vec![
+            ModerationCategory::SexualMinors,
+            ModerationCategory::Hate,
+            ModerationCategory::SelfHarm,
+            ModerationCategory::SelfHarmIntent,
+            ModerationCategory::HateThreatening,
+            ModerationCategory::SelfHarmInstructions,
+            ModerationCategory::HarassmentThreatening,
+            ModerationCategory::Sexual,
+            ModerationCategory::ViolenceGraphic,
+            ModerationCategory::Violence,
+            ModerationCategory::Harassment,
+        ])
+    }
+}
+
+/// The policies as saved.
+///
+/// The other moderation stores use `app_bin`'s bincode, but policies are
+/// settings a streamer tunes by hand and they gain a rule whenever OpenAI
+/// adds a category. Bincode cannot fill in a field a file lacks, so a new
+/// rule would make every saved policy unreadable; JSON with
+/// `#[serde(default)]` and a version can.
+#[derive(Serialize, Deserialize)]
+struct PolicyFile {
+    version: u32,
+    policies: HashMap<String, ModerationPolicy>,
+}
+
+impl PolicyFile {
+    /// Reads policies saved at `path`, refusing ones saved by a newer
+    /// version.
+    fn read(path: &Path) -> Result<Self, PolicyError> {
+        let bytes = fs::read(path).map_err(|e| PolicyError::StorageError(e.to_string()))?;
+        let file: PolicyFile =
+            serde_json::from_slice(&bytes).map_err(|e| PolicyError::StorageError(e.to_string()))?;
+        if file.version > POLICY_VERSION {
+            return Err(PolicyError::StorageError(format!(
+                "Policies were saved by a newer version ({})",
+                file.version
+            )));
+        }
+        Ok(file)
+    }
+
+    fn write(&self, path: &Path) -> Result<(), PolicyError> {
+        app_bin::ensure_directory_exists(path)?;
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| PolicyError::StorageError(e.to_string()))?;
+        fs::write(path, json).map_err(|e| PolicyError::StorageError(e.to_string()))
+    }
+}
+
+/// The policies of every channel, shared between the bot and the app.
+///
+/// Cloning is cheap and every clone sees the same policies. Channels
+/// without a saved policy use `ModerationPolicy::default()`.
+#[derive(Clone, Default)]
+pub struct PolicyStore {
+    policies: Arc<RwLock<HashMap<String, ModerationPolicy>>>,
+    /// Where policies are saved, `None` to keep them in memory only.
+    path: Option<PathBuf>,
+}
+
+impl PolicyStore {
+    pub fn in_memory() -> Self {
+        PolicyStore::default()
+    }
+
+    /// Loads the policies saved in the app's config directory.
+    pub fn load() -> Result<Self, PolicyError> {
+        let path = app_bin::get_file_path(POLICY_FILE, FileCategory::Config.as_str())?;
+        Self::load_from(path)
+    }
+
+    /// Loads policies saved at `path`, starting empty if there is no file.
+    pub fn load_from(path: PathBuf) -> Result<Self, PolicyError> {
+        let policies = if path.exists() {
+            PolicyFile::read(&path)?.policies
+        } else {
+            HashMap::new()
+        };
+        Ok(PolicyStore {
+            policies: Arc::new(RwLock::new(policies)),
+            path: Some(path),
+        })
+    }
+
+    pub fn get(&self, channel: &str) -> ModerationPolicy {
+        self.policies
+            .read()
+            .unwrap()
+            .get(&normalize_channel(channel))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Validates and saves a channel's policy. It applies to the next
+    /// message moderated in the channel.
+    pub fn set(&self, channel: &str, policy: ModerationPolicy) -> Result<(), PolicyError> {
+        policy.validate()?;
+        self.update(|policies| {
+            policies.insert(normalize_channel(channel), policy);
+        })
+    }
+
+    /// Goes back to the default policy for a channel.
+    pub fn reset(&self, channel: &str) -> Result<(), PolicyError> {
+        self.update(|policies| {
+            policies.remove(&normalize_channel(channel));
+        })
+    }
+
+    /// Channels with a saved policy.
+    pub fn channels(&self) -> Vec<String> {
+        let mut channels: Vec<String> = self.policies.read().unwrap().keys().cloned().collect();
+        channels.sort();
+        channels
+    }
+
+    /// Applies `change` and saves the result, leaving the policies
+    /// untouched if saving fails.
+    fn update<F>(&self, change: F) -> Result<(), PolicyError>
+    where
+        F: FnOnce(&mut HashMap<String, ModerationPolicy>),
+    {
+        let mut policies = self.policies.write().unwrap();
+        let mut updated = PolicyFile {
+            version: POLICY_VERSION,
+            policies: policies.clone(),
+        };
+        change(&mut updated.policies);
+        if let Some(path) = &self.path {
+            updated.write(path)?;
+        }
+        *policies = updated.policies;
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! Classifying messages off the chat loop.
+//!
+//! The bot hands every message that needs the moderation provider to a
+//! `ModerationPool` and carries on, so commands answer without waiting on
+//! the provider. A fixed number of workers classify messages concurrently,
+//! each under a timeout, and send back an outcome carrying the message it
+//! belongs to. The bot acts on the outcome whenever it arrives, e.g. deletes
+//! the message by its id.
+//!
+//! Messages wait in a bounded queue while every worker is busy. Once the
+//! queue is full, `submit` waits for room, slowing the chat loop down
+//! rather than letting the backlog grow without limit.
+
+use super::normalize::NormalizedText;
+use super::provider::{ModerationProvider, ModerationResult};
+use crate::openai::moderation::ModerationError;
+use crate::twitch::twitch_api::TwitchMessage;
+use colored::Colorize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+
+/// How many messages are classified at once and how long each may take.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolConfig {
+    pub workers: usize,
+    /// Messages waiting for a worker before `submit` waits for room.
+    pub queue_capacity: usize,
+    pub timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            workers: 8,
+            queue_capacity: 256,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The provider's answer for one message.
+#[derive(Debug)]
+pub struct ModerationOutcome {
+    pub message: TwitchMessage,
+    pub normalized: NormalizedText,
+    pub result: Result<ModerationResult, ModerationError>,
+    /// From a worker picking the message up to the answer.
+    pub elapsed: Duration,
+}
+
+struct Job {
+    message: TwitchMessage,
+    normalized: NormalizedText,
+}
+
+/// Workers classifying messages with one provider. Dropping the pool stops
+/// them once the queued messages are done.
+pub struct ModerationPool {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl ModerationPool {
+    /// Starts the workers, which send every outcome to `outcomes`. Must be
+    /// called from within a Tokio runtime.
+    pub fn spawn(
+        provider: Arc<dyn ModerationProvider>,
+        config: PoolConfig,
+        outcomes: mpsc::UnboundedSender<ModerationOutcome>,
+    ) -> Self {
+        let (jobs, mut queue) = mpsc::channel::<Job>(config.queue_capacity.max(1));
+        let workers = Arc::new(Semaphore::new(config.workers.max(1)));
+
+        tokio::spawn(async move {
+            while let Some(job) = queue.recv().await {
+                let permit = match workers.clone().acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                let provider = provider.clone();
+                let outcomes = outcomes.clone();
+                let timeout = config.timeout;
+                tokio::spawn(async move {
+                    let started = Instant::now();
+                    let result =
+                        tokio::time::timeout(timeout, provider.classify_message(&job.normalized))
+                            .await
+                            .unwrap_or(Err(ModerationError::TimedOut));
+                    drop(permit);
+                    let _ = outcomes.send(ModerationOutcome {
+                        message: job.message,
+                        normalized: job.normalized,
+                        result,
+                        elapsed: started.elapsed(),
+                    });
+                });
+            }
+        });
+
+        ModerationPool { jobs }
+    }
+
+    /// Queues a message for classification, waiting for room while the
+    /// queue is full. Returns `false` if the workers have stopped.
+    pub async fn submit(&self, message: TwitchMessage, normalized: NormalizedText) -> bool {
+        let job = Job {
+            message,
+            normalized,
+        };
+        let job = match self.jobs.try_send(job) {
+            Ok(()) => return true,
+            Err(mpsc::error::TrySendError::Closed(_)) => return false,
+            Err(mpsc::error::TrySendError::Full(job)) => job,
+        };
+        println!(
+            "{}: #{} {}",
+            "Moderation Backlog Full".bright_yellow().bold(),
+            job.message.channel,
+            job.message.id
+        );
+        self.jobs.send(job).await.is_ok()
+    }
+}
@@ -0,0 +1,133 @@
+//! Where moderation scores come from.
+//!
+//! The bot asks a `ModerationProvider` to classify each message and applies
+//! the channel's policy to the result. Providers can be swapped, e.g. for
+//! the offline `LocalClassifier`, or chained with `FallbackProvider` so a
+//! failing provider does not stop moderation.
+
+use super::batcher::{BatchConfig, ModerationBatcher, ModerationMetrics};
+use super::local::LocalClassifier;
+use super::normalize::NormalizedText;
+use crate::openai::moderation::{
+    ModerationCategories, ModerationError, ModerationScores, OpenAiConfig, OpenAiProvider,
+};
+use async_trait::async_trait;
+use colored::*;
+use std::time::Duration;
+
+/// How long `FallbackProvider` waits on its primary by default. Shorter
+/// than `PoolConfig`'s default timeout, so the fallback still gets to
+/// answer before the pool gives up on the message.
+const PRIMARY_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// How a provider rated a message.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    pub categories: ModerationCategories,
+    pub scores: ModerationScores,
+}
+
+#[async_trait]
+pub trait ModerationProvider: Send + Sync {
+    /// A short name for logs, e.g. `"openai"`.
+    fn name(&self) -> &str;
+
+    async fn classify(&self, text: &str) -> Result<ModerationResult, ModerationError>;
+
+    /// Classifies a chat message. By default only its skeleton is looked
+    /// at; providers that can also judge images linked in the message
+    /// override this.
+    async fn classify_message(
+        &self,
+        message: &NormalizedText,
+    ) -> Result<ModerationResult, ModerationError> {
+        self.classify(&message.skeleton).await
+    }
+}
+
+/// Asks `primary` first and `fallback` only when `primary` fails or takes
+/// longer than its timeout.
+pub struct FallbackProvider {
+    primary: Box<dyn ModerationProvider>,
+    fallback: Box<dyn ModerationProvider>,
+    timeout: Duration,
+}
+
+impl FallbackProvider {
+    pub fn new<P, F>(primary: P, fallback: F) -> Self
+    where
+        P: ModerationProvider + 'static,
+        F: ModerationProvider + 'static,
+    {
+        FallbackProvider {
+            primary: Box::new(primary),
+            fallback: Box::new(fallback),
+            timeout: PRIMARY_TIMEOUT,
+        }
+    }
+
+    /// Gives up on the primary after `timeout`. Keep it below the pool's
+    /// timeout, or a stalled primary leaves the message unmoderated.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[async_trait]
+impl ModerationProvider for FallbackProvider {
+    fn name(&self) -> &str {
+        self.primary.name()
+    }
+
+    async fn classify(&self, text: &str) -> Result<ModerationResult, ModerationError> {
+        let primary = tokio::time::timeout(self.timeout, self.primary.classify(text));
+        match primary.await.unwrap_or(Err(ModerationError::TimedOut)) {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                println!(
+                    "{} {}: {}, using {}",
+                    "Moderation provider failed".bright_red().bold(),
+                    self.primary.name(),
+                    e,
+                    self.fallback.name()
+                );
+                self.fallback.classify(text).await
+            }
+        }
+    }
+
+    async fn classify_message(
+        &self,
+        message: &NormalizedText,
+    ) -> Result<ModerationResult, ModerationError> {
+        let primary = tokio::time::timeout(self.timeout, self.primary.classify_message(message));
+        match primary.await.unwrap_or(Err(ModerationError::TimedOut)) {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                println!(
+                    "{} {}: {}, using {}",
+                    "Moderation provider failed".bright_red().bold(),
+                    self.primary.name(),
+                    e,
+                    self.fallback.name()
+                );
+                self.fallback.classify_message(message).await
+            }
+        }
+    }
+}
+
+/// Batched OpenAI, counting into `metrics`, with the local classifier as
+/// fallback when `OPEN_AI_KEY` is set, otherwise the local classifier alone.
+pub fn from_env(metrics: &ModerationMetrics) -> Box<dyn ModerationProvider> {
+    match OpenAiConfig::try_from_env() {
+        Some(config) => Box::new(FallbackProvider::new(
+            ModerationBatcher::new(OpenAiProvider::new(config), BatchConfig::default())
+                .with_metrics(metrics.clone()),
+            LocalClassifier::new(),
+        )),
+        None => Box::new(LocalClassifier::new()),
+    }
+}
@@ -0,0 +1,302 @@
+//! Messages waiting for a moderator's decision.
+//!
+//! A message that scores just below a category's threshold is not punished
+//! automatically. Instead the bot queues it here with the action the policy
+//! would have taken, and a moderator approves that action, picks another or
+//! dismisses the message from the desktop app. Every decision is recorded
+//! in the audit log as a manual one. The queue is saved on every change, so
+//! pending messages survive a restart.
+
+use super::audit::{ActionResult, AuditEntry, AuditError, AuditLog, DecisionOrigin};
+use super::policy::MAX_TIMEOUT_SECS;
+use crate::file_sys::app_bin::{self, FileCategory};
+use crate::openai::moderation::{
+    execute_punishment, FlaggedMessage, ModerationScores, PunishmentAction,
+};
+use crate::twitch::helix::HelixClient;
+use crate::twitch::twitch_api::normalize_channel;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+const REVIEW_FILE: &str = "moderation_review_queue.bin";
+
+#[derive(Debug)]
+pub enum ReviewError {
+    /// No pending message has this id, e.g. another moderator already
+    /// decided on it.
+    NotFound(u64),
+    /// An override timeout shorter than a second or longer than two weeks.
+    InvalidDuration(u64),
+    AuditError(AuditError),
+    StorageError(String),
+}
+
+impl std::fmt::Display for ReviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReviewError::NotFound(id) => write!(f, "No message {} waiting for review", id),
+            ReviewError::InvalidDuration(duration_secs) => write!(
+                f,
+                "Timeouts must be between 1 and {} seconds, got {}",
+                MAX_TIMEOUT_SECS, duration_secs
+            ),
+            ReviewError::AuditError(e) => write!(f, "Audit Error: {}", e),
+            ReviewError::StorageError(e) => write!(f, "Storage Error: {}", e),
+        }
+    }
+}
+
+impl From<AuditError> for ReviewError {
+    fn from(err: AuditError) -> Self {
+        ReviewError::AuditError(err)
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for ReviewError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        ReviewError::StorageError(err.to_string())
+    }
+}
+
+/// A message waiting for review.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewItem {
+    pub id: u64,
+    pub channel: String,
+    /// The broadcaster's user id, which punishments are issued in.
+    pub room_id: String,
+    /// The message, with the borderline category and its score.
+    pub message: FlaggedMessage,
+    pub scores: ModerationScores,
+    /// The threshold the score fell short of.
+    pub threshold: Option<f64>,
+    /// What the policy does to messages over the threshold.
+    pub suggested: PunishmentAction,
+    /// Unix time in seconds.
+    pub queued_at: i64,
+}
+
+/// A moderator's answer to a queued message.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReviewDecision {
+    /// Carry out the suggested action.
+    Approve,
+    /// Carry out this action instead.
+    Override(PunishmentAction),
+    /// Leave the message alone.
+    Dismiss,
+}
+
+impl ReviewDecision {
+    pub fn action(&self, item: &ReviewItem) -> PunishmentAction {
+        match self {
+            ReviewDecision::Approve => item.suggested,
+            ReviewDecision::Override(action) => *action,
+            ReviewDecision::Dismiss => PunishmentAction::None,
+        }
+    }
+
+    /// Checks that an override is an action Twitch accepts.
+    pub fn validate(&self) -> Result<(), ReviewError> {
+        match self {
+            ReviewDecision::Override(PunishmentAction::Timeout(duration_secs))
+                if !(1..=MAX_TIMEOUT_SECS).contains(duration_secs) =>
+            {
+                Err(ReviewError::InvalidDuration(*duration_secs))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct QueueState {
+    next_id: u64,
+    items: Vec<ReviewItem>,
+}
+
+/// The review queue, shared between the bot and the app.
+///
+/// Cloning is cheap and every clone sees the same queue.
+#[derive(Clone, Default)]
+pub struct ReviewQueue {
+    state: Arc<RwLock<QueueState>>,
+    /// Where the queue is saved, `None` to keep it in memory only.
+    path: Option<PathBuf>,
+}
+
+impl ReviewQueue {
+    pub fn in_memory() -> Self {
+        ReviewQueue::default()
+    }
+
+    /// Loads the queue saved in the app's data directory.
+    pub fn load() -> Result<Self, ReviewError> {
+        let path = app_bin::get_file_path(REVIEW_FILE, FileCategory::App.as_str())?;
+        Self::load_from(path)
+    }
+
+    /// Loads the queue saved at `path`, starting empty if there is no file.
+    pub fn load_from(path: PathBuf) -> Result<Self, ReviewError> {
+        let state = if path.exists() {
+            app_bin::read_from_path(&path)?
+        } else {
+            QueueState::default()
+        };
+        Ok(ReviewQueue {
+            state: Arc::new(RwLock::new(state)),
+            path: Some(path),
+        })
+    }
+
+    /// Queues a message and returns its id.
+    pub fn push(
+        &self,
+        channel: &str,
+        room_id: &str,
+        message: FlaggedMessage,
+        scores: ModerationScores,
+        threshold: Option<f64>,
+        suggested: PunishmentAction,
+    ) -> Result<u64, ReviewError> {
+        let mut id = 0;
+        self.update(|state| {
+            id = state.next_id;
+            state.next_id += 1;
+            state.items.push(ReviewItem {
+                id,
+                channel: normalize_channel(channel),
+                room_id: room_id.to_string(),
+                message,
+                scores,
+                threshold,
+                suggested,
+                queued_at: Utc::now().timestamp(),
+            });
+        })?;
+        Ok(id)
+    }
+
+    /// Messages waiting for review, oldest first, in one channel or in all
+    /// of them.
+    pub fn pending(&self, channel: Option<&str>) -> Vec<ReviewItem> {
+        let channel = channel.map(normalize_channel);
+        self.state
+            .read()
+            .unwrap()
+            .items
+            .iter()
+            .filter(|item| channel.as_ref().is_none_or(|c| item.channel == *c))
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<ReviewItem> {
+        self.state
+            .read()
+            .unwrap()
+            .items
+            .iter()
+            .find(|item| item.id == id)
+            .cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.read().unwrap().items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Carries out a moderator's decision on a queued message through
+    /// `helix`, records it in `audit` and takes the message off the queue.
+    /// Dismissing needs no client. Returns the audit entry, whose result
+    /// says whether the action went through; a message whose action failed
+    /// goes back on the queue to be tried again. An invalid override is
+    /// refused before anything is sent, leaving the message queued.
+    ///
+    /// The decision is a moderator's own, so an approved action is carried
+    /// out even in channels in shadow mode.
+    pub async fn resolve(
+        &self,
+        id: u64,
+        decision: ReviewDecision,
+        helix: Option<&HelixClient>,
+        audit: &AuditLog,
+    ) -> Result<AuditEntry, ReviewError> {
+        decision.validate()?;
+        // Taken off before acting, so no other moderator can decide on it
+        // at the same time.
+        let item = self.take(id)?;
+        let action = decision.action(&item);
+
+        let result = if action == PunishmentAction::None {
+            ActionResult::NoAction
+        } else {
+            match helix {
+                None => ActionResult::Failed("No moderator credentials".to_string()),
+                Some(helix) => {
+                    match execute_punishment(helix, &item.room_id, &item.message, action).await {
+                        Ok(()) => ActionResult::Applied,
+                        Err(e) => ActionResult::Failed(e.to_string()),
+                    }
+                }
+            }
+        };
+
+        let entry = AuditEntry::new(
+            &item.channel,
+            DecisionOrigin::Manual,
+            item.message.clone(),
+            item.scores.clone(),
+            item.threshold,
+            action,
+            result,
+        );
+        let recorded = audit.record(entry.clone());
+        if recorded.is_err() || matches!(entry.result, ActionResult::Failed(_)) {
+            self.put_back(item)?;
+        }
+        recorded?;
+        Ok(entry)
+    }
+
+    /// Removes a queued message and returns it.
+    fn take(&self, id: u64) -> Result<ReviewItem, ReviewError> {
+        let mut taken = None;
+        self.update(|state| {
+            if let Some(index) = state.items.iter().position(|item| item.id == id) {
+                taken = Some(state.items.remove(index));
+            }
+        })?;
+        taken.ok_or(ReviewError::NotFound(id))
+    }
+
+    /// Returns a taken message to its place in the queue.
+    fn put_back(&self, item: ReviewItem) -> Result<(), ReviewError> {
+        self.update(|state| {
+            let index = state.items.partition_point(|queued| queued.id < item.id);
+            state.items.insert(index, item);
+        })
+    }
+
+    /// Applies `change` and saves the result, leaving the queue untouched
+    /// if saving fails.
+    fn update<F>(&self, change: F) -> Result<(), ReviewError>
+    where
+        F: FnOnce(&mut QueueState),
+    {
+        let mut state = self.state.write().unwrap();
+        let mut updated = state.clone();
+        change(&mut updated);
+        if let Some(path) = &self.path {
+            app_bin::write_to_path(&updated, path)?;
+        }
+        *state = updated;
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+//! Comparing shadow moderation with what the human mods did.
+//!
+//! In shadow mode the bot records the action it would have taken without
+//! carrying it out, and records the bans, timeouts and deletions moderators
+//! make as manual entries in the audit log. A `ShadowReport` pairs the two
+//! up to show how a policy would have fared before it goes live.
+
+use super::audit::{ActionResult, AuditEntry, DecisionOrigin};
+use serde::{Deserialize, Serialize};
+use std::mem::discriminant;
+
+/// How far apart a shadow decision and a moderator's action may be to count
+/// as the same incident.
+pub const DEFAULT_MATCH_WINDOW_SECS: i64 = 300;
+
+/// A shadow decision and the moderator action for the same incident.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowMatch {
+    pub shadow: AuditEntry,
+    pub human: AuditEntry,
+    /// Whether both were the same kind of action, e.g. both timeouts.
+    pub same_action: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowReport {
+    pub matched: Vec<ShadowMatch>,
+    /// Shadow actions no moderator took, possible false positives.
+    pub shadow_only: Vec<AuditEntry>,
+    /// Moderator actions the bot would not have taken, possible misses.
+    pub human_only: Vec<AuditEntry>,
+}
+
+impl ShadowReport {
+    /// Pairs each shadow decision with the first unpaired moderator action
+    /// against the same user in the same channel within `window_secs`.
+    pub fn build(entries: &[AuditEntry], window_secs: i64) -> Self {
+        let mut humans: Vec<Option<&AuditEntry>> = entries
+            .iter()
+            .filter(|entry| entry.origin == DecisionOrigin::Manual)
+            .map(Some)
+            .collect();
+        let mut report = ShadowReport::default();
+
+        let shadows = entries
+            .iter()
+            .filter(|entry| entry.result == ActionResult::Shadow);
+        for shadow in shadows {
+            let human = humans.iter_mut().find(|human| {
+                human.is_some_and(|human| {
+                    same_incident(shadow, human)
+                        && (human.timestamp - shadow.timestamp).abs() <= window_secs
+                })
+            });
+            match human.and_then(Option::take) {
+                Some(human) => report.matched.push(ShadowMatch {
+                    shadow: shadow.clone(),
+                    human: human.clone(),
+                    same_action: discriminant(&shadow.action) == discriminant(&human.action),
+                }),
+                None => report.shadow_only.push(shadow.clone()),
+            }
+        }
+
+        report.human_only = humans.into_iter().flatten().cloned().collect();
+        report
+    }
+
+    /// The share of shadow actions a moderator also took.
+    pub fn precision(&self) -> Option<f64> {
+        ratio(self.matched.len(), self.shadow_only.len())
+    }
+
+    /// The share of moderator actions the bot would also have taken.
+    pub fn recall(&self) -> Option<f64> {
+        ratio(self.matched.len(), self.human_only.len())
+    }
+}
+
+/// Same channel and user. Deletions only carry the login, so either the
+/// user id or the login has to agree.
+fn same_incident(shadow: &AuditEntry, human: &AuditEntry) -> bool {
+    let same_user = (!human.message.user_id.is_empty()
+        && human.message.user_id == shadow.message.user_id)
+        || human
+            .message
+            .username
+            .eq_ignore_ascii_case(&shadow.message.username);
+    shadow.channel == human.channel && same_user
+}
+
+fn ratio(matched: usize, unmatched: usize) -> Option<f64> {
+    match matched + unmatched {
+        0 => None,
+        total => Some(matched as f64 / total as f64),
+    }
+}
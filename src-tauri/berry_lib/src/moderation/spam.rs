@@ -0,0 +1,542 @@
+//! Deterministic filters for classic chat spam.
+//!
+//! The moderation provider judges what a message says, not how it is
+//! written, so shouting, symbol and zalgo walls, emote floods, copypasta,
+//! walls of text and links get through it. These filters run locally before
+//! the provider is asked. Every filter has its own thresholds and
+//! punishment, and the first one a message trips decides what happens.
+
+use super::normalize::{is_combining_mark, skeleton};
+use super::policy::RuleAction;
+use super::strikes::LadderStep;
+use crate::openai::moderation::PunishmentAction;
+use crate::twitch::twitch_api::TwitchMessage;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// How many recent messages a channel keeps for the repetition filter.
+const MAX_RECENT_MESSAGES: usize = 1000;
+
+/// Top-level domains recognised in links written without a scheme, such
+/// as `example.com`. Links starting with `http://` or `https://` are
+/// recognised whatever their domain.
+const LINK_TLDS: &[&str] = &[
+    "com", "net", "org", "tv", "gg", "io", "ly", "co", "me", "be", "xyz", "ru", "de", "uk", "info",
+    "biz", "app", "dev", "link", "live", "shop", "site", "online", "fr", "us", "ca", "au", "to",
+    "cc", "gl",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpamKind {
+    Caps,
+    Symbols,
+    Emotes,
+    Repetition,
+    Length,
+    Links,
+}
+
+impl SpamKind {
+    /// The category recorded for strikes and in the audit log.
+    pub fn category(&self) -> &'static str {
+        match self {
+            SpamKind::Caps => "spam/caps",
+            SpamKind::Symbols => "spam/symbols",
+            SpamKind::Emotes => "spam/emotes",
+            SpamKind::Repetition => "spam/repetition",
+            SpamKind::Length => "spam/length",
+            SpamKind::Links => "spam/links",
+        }
+    }
+}
+
+/// Messages written mostly in capitals.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapsFilter {
+    pub enabled: bool,
+    /// Messages with fewer letters than this are never caps spam.
+    pub min_letters: usize,
+    /// The share of capital letters, from 0.0 to 1.0, above which a
+    /// message is spam. Emotes are not counted.
+    pub max_ratio: f64,
+    pub punishment: LadderStep,
+}
+
+impl Default for CapsFilter {
+    fn default() -> Self {
+        CapsFilter {
+            enabled: true,
+            min_letters: 15,
+            max_ratio: 0.7,
+            punishment: LadderStep::new(RuleAction::Delete),
+        }
+    }
+}
+
+/// Walls of symbols and zalgo text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolFilter {
+    pub enabled: bool,
+    /// Messages with fewer visible characters than this are never symbol
+    /// spam.
+    pub min_chars: usize,
+    /// The share of characters that are neither letters nor digits, from
+    /// 0.0 to 1.0, above which a message is spam.
+    pub max_ratio: f64,
+    /// The most combining marks a message may stack, which is what zalgo
+    /// text is made of.
+    pub max_combining_marks: usize,
+    pub punishment: LadderStep,
+}
+
+impl Default for SymbolFilter {
+    fn default() -> Self {
+        SymbolFilter {
+            enabled: true,
+            min_chars: 10,
+            max_ratio: 0.5,
+            max_combining_marks: 10,
+            punishment: LadderStep::new(RuleAction::Delete),
+        }
+    }
+}
+
+/// Floods of emotes, counted from the message's emote tag.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmoteFilter {
+    pub enabled: bool,
+    /// The most emotes one message may hold.
+    pub max_emotes: usize,
+    pub punishment: LadderStep,
+}
+
+impl Default for EmoteFilter {
+    fn default() -> Self {
+        EmoteFilter {
+            enabled: true,
+            max_emotes: 15,
+            punishment: LadderStep::new(RuleAction::Delete),
+        }
+    }
+}
+
+/// The same message sent again and again, by one chatter or by many.
+/// Messages are compared by their normalized skeleton, so case, spacing,
+/// lookalike letters and leetspeak do not make a copy look new.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepetitionFilter {
+    pub enabled: bool,
+    /// How far back identical messages are counted.
+    pub window_secs: u64,
+    /// How many identical messages one chatter may send within the window.
+    pub max_per_user: usize,
+    /// How many chatters may send the same message within the window.
+    pub max_users: usize,
+    /// Messages shorter than this are never copypasta, so a chat full of
+    /// "gg" is left alone.
+    pub min_chars_across_users: usize,
+    pub punishment: LadderStep,
+}
+
+impl Default for RepetitionFilter {
+    fn default() -> Self {
+        RepetitionFilter {
+            enabled: true,
+            window_secs: 30,
+            max_per_user: 2,
+            max_users: 4,
+            min_chars_across_users: 20,
+            punishment: LadderStep::timeout(60),
+        }
+    }
+}
+
+/// Walls of text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LengthFilter {
+    pub enabled: bool,
+    pub max_chars: usize,
+    pub punishment: LadderStep,
+}
+
+impl Default for LengthFilter {
+    fn default() -> Self {
+        LengthFilter {
+            enabled: true,
+            max_chars: 350,
+            punishment: LadderStep::new(RuleAction::Delete),
+        }
+    }
+}
+
+/// Links to domains outside the allowlist. Moderators can `!permit` a
+/// chatter to post one anyway.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkFilter {
+    pub enabled: bool,
+    /// Domains that may always be linked, subdomains included.
+    pub allowed_domains: Vec<String>,
+    /// How long a `!permit` lasts.
+    pub permit_secs: u64,
+    pub punishment: LadderStep,
+}
+
+impl Default for LinkFilter {
+    fn default() -> Self {
+        LinkFilter {
+            enabled: true,
+            allowed_domains: vec!["twitch.tv".to_string()],
+            permit_secs: 60,
+            punishment: LadderStep::new(RuleAction::Delete),
+        }
+    }
+}
+
+impl LinkFilter {
+    pub fn is_allowed(&self, domain: &str) -> bool {
+        let domain = domain.to_lowercase();
+        self.allowed_domains.iter().any(|allowed| {
+            let allowed = allowed.trim_start_matches("www.").to_lowercase();
+            domain == allowed || domain.ends_with(&format!(".{}", allowed))
+        })
+    }
+}
+
+/// Every spam filter of one channel.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SpamFilters {
+    pub caps: CapsFilter,
+    pub symbols: SymbolFilter,
+    pub emotes: EmoteFilter,
+    pub repetition: RepetitionFilter,
+    pub length: LengthFilter,
+    pub links: LinkFilter,
+}
+
+/// The filter a message tripped and what to do about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpamVerdict {
+    pub kind: SpamKind,
+    /// What was measured, e.g. the share of capitals or the number of
+    /// emotes.
+    pub score: f64,
+    /// The filter's limit the score went past.
+    pub threshold: f64,
+    pub punishment: PunishmentAction,
+}
+
+impl SpamVerdict {
+    fn new(kind: SpamKind, score: f64, threshold: f64, punishment: &LadderStep) -> Self {
+        SpamVerdict {
+            kind,
+            score,
+            threshold,
+            punishment: punishment.punishment(),
+        }
+    }
+}
+
+impl SpamFilters {
+    /// Filters that let everything through.
+    pub fn disabled() -> Self {
+        let mut filters = SpamFilters::default();
+        filters.caps.enabled = false;
+        filters.symbols.enabled = false;
+        filters.emotes.enabled = false;
+        filters.repetition.enabled = false;
+        filters.length.enabled = false;
+        filters.links.enabled = false;
+        filters
+    }
+
+    /// Runs the message through every enabled filter and remembers it for
+    /// the repetition filter.
+    pub fn check(&self, message: &TwitchMessage, tracker: &mut SpamTracker) -> Option<SpamVerdict> {
+        self.check_at(message, tracker, Utc::now())
+    }
+
+    pub fn check_at(
+        &self,
+        message: &TwitchMessage,
+        tracker: &mut SpamTracker,
+        at: DateTime<Utc>,
+    ) -> Option<SpamVerdict> {
+        let repetition = self.check_repetition(message, tracker, at.timestamp());
+        let text = text_without_emotes(message);
+
+        self.check_length(message)
+            .or_else(|| self.check_caps(&text))
+            .or_else(|| self.check_symbols(&text))
+            .or_else(|| self.check_emotes(message))
+            .or_else(|| self.check_links(message, tracker, at.timestamp()))
+            .or(repetition)
+    }
+
+    fn check_length(&self, message: &TwitchMessage) -> Option<SpamVerdict> {
+        let filter = &self.length;
+        let chars = message.text.chars().count();
+        (filter.enabled && chars > filter.max_chars).then(|| {
+            SpamVerdict::new(
+                SpamKind::Length,
+                chars as f64,
+                filter.max_chars as f64,
+                &filter.punishment,
+            )
+        })
+    }
+
+    fn check_caps(&self, text: &str) -> Option<SpamVerdict> {
+        let filter = &self.caps;
+        let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+        if !filter.enabled || letters == 0 || letters < filter.min_letters {
+            return None;
+        }
+        let capitals = text.chars().filter(|c| c.is_uppercase()).count();
+        let ratio = capitals as f64 / letters as f64;
+        (ratio > filter.max_ratio)
+            .then(|| SpamVerdict::new(SpamKind::Caps, ratio, filter.max_ratio, &filter.punishment))
+    }
+
+    fn check_symbols(&self, text: &str) -> Option<SpamVerdict> {
+        let filter = &self.symbols;
+        if !filter.enabled {
+            return None;
+        }
+
+        let combining = text.chars().filter(|c| is_combining_mark(*c)).count();
+        if combining > filter.max_combining_marks {
+            return Some(SpamVerdict::new(
+                SpamKind::Symbols,
+                combining as f64,
+                filter.max_combining_marks as f64,
+                &filter.punishment,
+            ));
+        }
+
+        let visible: Vec<char> = text
+            .chars()
+            .filter(|c| !c.is_whitespace() && !is_combining_mark(*c))
+            .collect();
+        if visible.is_empty() || visible.len() < filter.min_chars {
+            return None;
+        }
+        let symbols = visible.iter().filter(|c| !c.is_alphanumeric()).count();
+        let ratio = symbols as f64 / visible.len() as f64;
+        (ratio > filter.max_ratio).then(|| {
+            SpamVerdict::new(
+                SpamKind::Symbols,
+                ratio,
+                filter.max_ratio,
+                &filter.punishment,
+            )
+        })
+    }
+
+    fn check_emotes(&self, message: &TwitchMessage) -> Option<SpamVerdict> {
+        let filter = &self.emotes;
+        let emotes: usize = message.emotes.iter().map(|emote| emote.ranges.len()).sum();
+        (filter.enabled && emotes > filter.max_emotes).then(|| {
+            SpamVerdict::new(
+                SpamKind::Emotes,
+                emotes as f64,
+                filter.max_emotes as f64,
+                &filter.punishment,
+            )
+        })
+    }
+
+    fn check_links(
+        &self,
+        message: &TwitchMessage,
+        tracker: &mut SpamTracker,
+        now: i64,
+    ) -> Option<SpamVerdict> {
+        let filter = &self.links;
+        if !filter.enabled {
+            return None;
+        }
+        let blocked = find_links(&message.text)
+            .into_iter()
+            .filter(|domain| !filter.is_allowed(domain))
+            .count();
+        if blocked == 0 || tracker.take_permit(&message.sender, now) {
+            return None;
+        }
+        Some(SpamVerdict::new(
+            SpamKind::Links,
+            blocked as f64,
+            0.0,
+            &filter.punishment,
+        ))
+    }
+
+    /// Counts earlier copies of the message, then remembers it.
+    fn check_repetition(
+        &self,
+        message: &TwitchMessage,
+        tracker: &mut SpamTracker,
+        now: i64,
+    ) -> Option<SpamVerdict> {
+        let filter = &self.repetition;
+        if !filter.enabled {
+            return None;
+        }
+
+        let text = skeleton(&message.text);
+        tracker.prune(now - filter.window_secs as i64);
+        let copies: Vec<&RecentMessage> = tracker
+            .recent
+            .iter()
+            .filter(|recent| recent.text == text)
+            .collect();
+        let own = copies
+            .iter()
+            .filter(|recent| recent.user_id == message.user_id)
+            .count()
+            + 1;
+        let mut users: HashSet<&str> = copies
+            .iter()
+            .map(|recent| recent.user_id.as_str())
+            .collect();
+        users.insert(&message.user_id);
+        let users = users.len();
+
+        let verdict = if own > filter.max_per_user {
+            Some(SpamVerdict::new(
+                SpamKind::Repetition,
+                own as f64,
+                filter.max_per_user as f64,
+                &filter.punishment,
+            ))
+        } else if users > filter.max_users && text.chars().count() >= filter.min_chars_across_users
+        {
+            Some(SpamVerdict::new(
+                SpamKind::Repetition,
+                users as f64,
+                filter.max_users as f64,
+                &filter.punishment,
+            ))
+        } else {
+            None
+        };
+
+        tracker.remember(RecentMessage {
+            timestamp: now,
+            user_id: message.user_id.clone(),
+            text,
+        });
+        verdict
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RecentMessage {
+    /// Unix time in seconds.
+    timestamp: i64,
+    user_id: String,
+    /// The message's normalized skeleton.
+    text: String,
+}
+
+/// What the filters remember about one channel: recent messages for the
+/// repetition filter and the link permits moderators handed out.
+#[derive(Debug, Default)]
+pub struct SpamTracker {
+    recent: VecDeque<RecentMessage>,
+    /// Login to the Unix time the permit runs out.
+    permits: HashMap<String, i64>,
+}
+
+impl SpamTracker {
+    pub fn new() -> Self {
+        SpamTracker::default()
+    }
+
+    /// Lets the chatter post one link until `until`.
+    pub fn permit(&mut self, login: &str, until: DateTime<Utc>) {
+        let login = login.trim_start_matches('@').to_lowercase();
+        self.permits.insert(login, until.timestamp());
+    }
+
+    /// Whether the chatter holds a permit that has not run out. The permit
+    /// is used up either way.
+    fn take_permit(&mut self, login: &str, now: i64) -> bool {
+        self.permits
+            .remove(&login.to_lowercase())
+            .is_some_and(|until| now <= until)
+    }
+
+    fn prune(&mut self, before: i64) {
+        while self
+            .recent
+            .front()
+            .is_some_and(|recent| recent.timestamp < before)
+        {
+            self.recent.pop_front();
+        }
+    }
+
+    fn remember(&mut self, message: RecentMessage) {
+        if self.recent.len() >= MAX_RECENT_MESSAGES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(message);
+    }
+}
+
+/// The domains of links in `text`, lowercase.
+pub fn find_links(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .filter_map(|word| {
+            let word = word.trim_matches(|c: char| !c.is_alphanumeric() && c != '/');
+            let lower = word.to_lowercase();
+            let (has_scheme, rest) = match lower.split_once("://") {
+                Some((scheme, rest)) if scheme == "http" || scheme == "https" => (true, rest),
+                _ => (false, lower.as_str()),
+            };
+            let host = rest
+                .split(['/', '?', '#', ':'])
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('.');
+
+            let labels: Vec<&str> = host.split('.').collect();
+            let valid = labels.len() >= 2
+                && labels.iter().all(|label| {
+                    !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+                });
+            let tld = labels.last().copied().unwrap_or_default();
+            (valid && (has_scheme || LINK_TLDS.contains(&tld))).then(|| host.to_string())
+        })
+        .collect()
+}
+
+/// The message text with the characters of its emotes left out.
+fn text_without_emotes(message: &TwitchMessage) -> String {
+    if message.emotes.is_empty() {
+        return message.text.clone();
+    }
+    let covered = |position: usize| {
+        message.emotes.iter().any(|emote| {
+            emote
+                .ranges
+                .iter()
+                .any(|(start, end)| (*start..=*end).contains(&position))
+        })
+    };
+    message
+        .text
+        .chars()
+        .enumerate()
+        .filter(|(position, _)| !covered(*position))
+        .map(|(_, c)| c)
+        .collect()
+}
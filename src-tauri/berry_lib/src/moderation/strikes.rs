@@ -0,0 +1,350 @@
+//! Strikes against chatters who keep breaking the rules.
+//!
+//! Every punished message adds a strike to the sender's record in that
+//! channel, keyed by their Twitch user id. The more active strikes a user
+//! has, the further up the ladder their punishment goes. Strikes older than
+//! the decay period stop counting and are dropped.
+
+use super::policy::{RuleAction, MAX_TIMEOUT_SECS};
+use crate::file_sys::app_bin::{self, FileCategory};
+use crate::openai::moderation::{ModerationCategory, PunishmentAction};
+use crate::twitch::twitch_api::normalize_channel;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+const STRIKE_FILE: &str = "moderation_strikes.bin";
+
+/// One week.
+const DEFAULT_DECAY_SECS: u64 = 604_800;
+
+#[derive(Debug)]
+pub enum StrikeError {
+    EmptyLadder,
+    /// A ladder timeout shorter than a second or longer than two weeks.
+    InvalidDuration(u64),
+    StorageError(String),
+}
+
+impl std::fmt::Display for StrikeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StrikeError::EmptyLadder => write!(f, "The strike ladder needs at least one step"),
+            StrikeError::InvalidDuration(duration_secs) => write!(
+                f,
+                "Timeouts must be between 1 and {} seconds, got {}",
+                MAX_TIMEOUT_SECS, duration_secs
+            ),
+            StrikeError::StorageError(e) => write!(f, "Storage Error: {}", e),
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for StrikeError {
+    fn from(err: Box<dyn std::error::Error>) -> Self {
+        StrikeError::StorageError(err.to_string())
+    }
+}
+
+/// The punishment for one strike count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LadderStep {
+    pub action: RuleAction,
+    /// Timeout length in seconds. Only used by `RuleAction::Timeout`.
+    pub duration_secs: u64,
+}
+
+impl LadderStep {
+    pub fn new(action: RuleAction) -> Self {
+        LadderStep {
+            action,
+            duration_secs: 0,
+        }
+    }
+
+    pub fn timeout(duration_secs: u64) -> Self {
+        LadderStep {
+            action: RuleAction::Timeout,
+            duration_secs,
+        }
+    }
+
+    pub fn punishment(&self) -> PunishmentAction {
+        match self.action {
+            RuleAction::None => PunishmentAction::None,
+            RuleAction::Warn => PunishmentAction::Warn,
+            RuleAction::Delete => PunishmentAction::Delete,
+            RuleAction::Timeout => PunishmentAction::Timeout(self.duration_secs),
+            RuleAction::Ban => PunishmentAction::Ban,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrikeConfig {
+    /// The punishment for the first, second, ... active strike. Counts past
+    /// the end use the last step.
+    pub ladder: Vec<LadderStep>,
+    /// How long a strike counts for, `None` to keep strikes forever.
+    pub decay_secs: Option<u64>,
+}
+
+impl Default for StrikeConfig {
+    fn default() -> Self {
+        StrikeConfig {
+            ladder: vec![
+                LadderStep::new(RuleAction::Warn),
+                LadderStep::timeout(600),
+                LadderStep::timeout(3600),
+                LadderStep::new(RuleAction::Ban),
+            ],
+            decay_secs: Some(DEFAULT_DECAY_SECS),
+        }
+    }
+}
+
+impl StrikeConfig {
+    /// The ladder's punishment for a user with `count` active strikes.
+    pub fn step(&self, count: usize) -> PunishmentAction {
+        match count {
+            0 => PunishmentAction::None,
+            count => self
+                .ladder
+                .get(count - 1)
+                .or(self.ladder.last())
+                .map(LadderStep::punishment)
+                .unwrap_or(PunishmentAction::None),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), StrikeError> {
+        if self.ladder.is_empty() {
+            return Err(StrikeError::EmptyLadder);
+        }
+        for step in &self.ladder {
+            if step.action == RuleAction::Timeout
+                && !(1..=MAX_TIMEOUT_SECS).contains(&step.duration_secs)
+            {
+                return Err(StrikeError::InvalidDuration(step.duration_secs));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Strike {
+    /// The moderation category the message was punished for.
+    pub category: ModerationCategory,
+    /// Unix time in seconds.
+    pub timestamp: i64,
+}
+
+/// A user's strikes in one channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserStrikes {
+    pub user_id: String,
+    /// The login at the time of the latest strike.
+    pub username: String,
+    pub strikes: Vec<Strike>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Ledger {
+    config: StrikeConfig,
+    /// Channel, then user id.
+    channels: HashMap<String, HashMap<String, UserStrikes>>,
+}
+
+impl Ledger {
+    /// Drops strikes that have decayed by `now`, and users left without any.
+    fn prune(&mut self, now: i64) {
+        let decay_secs = match self.config.decay_secs {
+            Some(decay_secs) => decay_secs as i64,
+            None => return,
+        };
+        for users in self.channels.values_mut() {
+            for user in users.values_mut() {
+                user.strikes
+                    .retain(|strike| now - strike.timestamp < decay_secs);
+            }
+            users.retain(|_, user| !user.strikes.is_empty());
+        }
+        self.channels.retain(|_, users| !users.is_empty());
+    }
+}
+
+/// Strikes of every channel, shared between the bot and the app.
+///
+/// Cloning is cheap and every clone sees the same strikes.
+#[derive(Clone, Default)]
+pub struct StrikeLedger {
+    ledger: Arc<RwLock<Ledger>>,
+    /// Where strikes are saved, `None` to keep them in memory only.
+    path: Option<PathBuf>,
+}
+
+impl StrikeLedger {
+    pub fn in_memory() -> Self {
+        StrikeLedger::default()
+    }
+
+    /// Loads the strikes saved in the app's config directory.
+    pub fn load() -> Result<Self, StrikeError> {
+        let path = app_bin::get_file_path(STRIKE_FILE, FileCategory::Config.as_str())?;
+        Self::load_from(path)
+    }
+
+    /// Loads strikes saved at `path`, starting empty if there is no file.
+    pub fn load_from(path: PathBuf) -> Result<Self, StrikeError> {
+        let ledger = if path.exists() {
+            app_bin::read_from_path(&path)?
+        } else {
+            Ledger::default()
+        };
+        Ok(StrikeLedger {
+            ledger: Arc::new(RwLock::new(ledger)),
+            path: Some(path),
+        })
+    }
+
+    pub fn config(&self) -> StrikeConfig {
+        self.ledger.read().unwrap().config.clone()
+    }
+
+    /// Validates and saves a new ladder and decay. Strikes already given
+    /// are kept.
+    pub fn set_config(&self, config: StrikeConfig) -> Result<(), StrikeError> {
+        config.validate()?;
+        self.update(|ledger| ledger.config = config)
+    }
+
+    /// Adds a strike and returns how many active strikes the user now has.
+    pub fn record(
+        &self,
+        channel: &str,
+        user_id: &str,
+        username: &str,
+        category: &ModerationCategory,
+    ) -> Result<usize, StrikeError> {
+        self.record_at(channel, user_id, username, category, Utc::now())
+    }
+
+    pub fn record_at(
+        &self,
+        channel: &str,
+        user_id: &str,
+        username: &str,
+        category: &ModerationCategory,
+        at: DateTime<Utc>,
+    ) -> Result<usize, StrikeError> {
+        let mut count = 0;
+        self.update(|ledger| {
+            ledger.prune(at.timestamp());
+            let user = ledger
+                .channels
+                .entry(normalize_channel(channel))
+                .or_default()
+                .entry(user_id.to_string())
+                .or_insert_with(|| UserStrikes {
+                    user_id: user_id.to_string(),
+                    username: String::new(),
+                    strikes: Vec::new(),
+                });
+            user.username = username.to_lowercase();
+            user.strikes.push(Strike {
+                category: category.clone(),
+                timestamp: at.timestamp(),
+            });
+            count = user.strikes.len();
+        })?;
+        Ok(count)
+    }
+
+    /// The user's active strikes in the channel.
+    pub fn active(&self, channel: &str, user_id: &str) -> usize {
+        self.active_at(channel, user_id, Utc::now())
+    }
+
+    pub fn active_at(&self, channel: &str, user_id: &str, at: DateTime<Utc>) -> usize {
+        self.users_at(channel, at)
+            .into_iter()
+            .find(|user| user.user_id == user_id)
+            .map_or(0, |user| user.strikes.len())
+    }
+
+    /// Users with active strikes in the channel.
+    pub fn users(&self, channel: &str) -> Vec<UserStrikes> {
+        self.users_at(channel, Utc::now())
+    }
+
+    pub fn users_at(&self, channel: &str, at: DateTime<Utc>) -> Vec<UserStrikes> {
+        let mut ledger = self.ledger.read().unwrap().clone();
+        ledger.prune(at.timestamp());
+        let mut users: Vec<UserStrikes> = ledger
+            .channels
+            .remove(&normalize_channel(channel))
+            .map(|users| users.into_values().collect())
+            .unwrap_or_default();
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        users
+    }
+
+    /// The user id of a login with strikes in the channel.
+    pub fn find_user(&self, channel: &str, username: &str) -> Option<String> {
+        let username = username.trim_start_matches('@').to_lowercase();
+        self.ledger
+            .read()
+            .unwrap()
+            .channels
+            .get(&normalize_channel(channel))?
+            .values()
+            .find(|user| user.username == username)
+            .map(|user| user.user_id.clone())
+    }
+
+    /// Forgets all of a user's strikes in the channel. Returns how many
+    /// were cleared.
+    pub fn clear(&self, channel: &str, user_id: &str) -> Result<usize, StrikeError> {
+        let mut cleared = 0;
+        self.update(|ledger| {
+            if let Some(users) = ledger.channels.get_mut(&normalize_channel(channel)) {
+                cleared = users.remove(user_id).map_or(0, |user| user.strikes.len());
+            }
+        })?;
+        Ok(cleared)
+    }
+
+    /// The harsher of the policy's punishment and the ladder step for
+    /// `count` active strikes.
+    pub fn escalate(&self, punishment: PunishmentAction, count: usize) -> PunishmentAction {
+        let step = self.config().step(count);
+        if step.severity() > punishment.severity() {
+            step
+        } else {
+            punishment
+        }
+    }
+
+    /// Applies `change` and saves the result, leaving the ledger untouched
+    /// if saving fails.
+    fn update<F>(&self, change: F) -> Result<(), StrikeError>
+    where
+        F: FnOnce(&mut Ledger),
+    {
+        let mut ledger = self.ledger.write().unwrap();
+        let mut updated = ledger.clone();
+        change(&mut updated);
+        if let Some(path) = &self.path {
+            app_bin::write_to_path(&updated, path)?;
+        }
+        *ledger = updated;
+        Ok(())
+    }
+}
@@ -1,10 +1,16 @@
+use crate::moderation::normalize::NormalizedText;
+use crate::moderation::provider::{ModerationProvider, ModerationResult};
+use crate::twitch::helix::{HelixClient, HelixError};
 use colored::*;
 use reqwest;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::time::Duration;
 
-enum PunishmentAction {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PunishmentAction {
+    /// Times the user out for this many seconds.
     Timeout(u64),
     Ban,
     Delete,
@@ -12,18 +18,17 @@ enum PunishmentAction {
     None,
 }
 
-struct DefaultThresholds {
-    harassment: f64,
-    harassment_threatening: f64,
-    hate: f64,
-    hate_threatening: f64,
-    self_harm: f64,
-    self_harm_instructions: f64,
-    self_harm_intent: f64,
-    sexual: f64,
-    sexual_minors: f64,
-    violence: f64,
-    violence_graphic: f64,
+impl PunishmentAction {
+    /// Orders punishments from lightest to harshest, timeouts by length.
+    pub fn severity(&self) -> (u8, u64) {
+        match self {
+            PunishmentAction::None => (0, 0),
+            PunishmentAction::Warn => (1, 0),
+            PunishmentAction::Delete => (2, 0),
+            PunishmentAction::Timeout(duration_secs) => (3, *duration_secs),
+            PunishmentAction::Ban => (4, 0),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,22 +38,31 @@ pub struct ModerationResponse {
     pub results: Vec<OpenAiModRes>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FlaggedMessage {
     pub username: String,
     pub user_id: String,
+    pub message_id: String,
     pub text: String,
-    pub category: String,
+    pub category: ModerationCategory,
     pub score: f64,
 }
 
 impl FlaggedMessage {
-    pub fn new(username: &str, user_id: &str, text: &str, category: &str, score: f64) -> Self {
+    pub fn new(
+        username: &str,
+        user_id: &str,
+        message_id: &str,
+        text: &str,
+        category: ModerationCategory,
+        score: f64,
+    ) -> Self {
         FlaggedMessage {
             username: String::from(username),
             user_id: String::from(user_id),
+            message_id: String::from(message_id),
             text: String::from(text),
-            category: String::from(category),
+            category,
             score,
         }
     }
@@ -57,11 +71,118 @@ impl FlaggedMessage {
 #[derive(Debug, Deserialize)]
 pub struct OpenAiModRes {
     pub flagged: bool,
-    pub categories: ModerationCategories,
-    pub category_scores: ModerationScores,
+    /// Flags by OpenAI's category name. Kept as a map, so categories added
+    /// to the API later do not break parsing.
+    pub categories: HashMap<String, bool>,
+    pub category_scores: HashMap<String, f64>,
+    /// Whether each category was judged on the text, the image or both.
+    /// Only the omni models send this.
+    #[serde(default)]
+    pub category_applied_input_types: HashMap<String, Vec<String>>,
+}
+
+impl From<OpenAiModRes> for ModerationResult {
+    fn from(result: OpenAiModRes) -> Self {
+        ModerationResult {
+            flagged: result.flagged,
+            categories: ModerationCategories::from_names(result.categories),
+            scores: ModerationScores::from_names(result.category_scores),
+        }
+    }
+}
+
+/// A category of OpenAI's moderation endpoint, serialized with OpenAI's
+/// name for it, e.g. `"hate/threatening"`. Categories this version does
+/// not know, e.g. `"illicit"` from the omni models, are kept by name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ModerationCategory {
+    Harassment,
+    HarassmentThreatening,
+    Hate,
+    HateThreatening,
+    SelfHarm,
+    SelfHarmInstructions,
+    SelfHarmIntent,
+    Sexual,
+    SexualMinors,
+    Violence,
+    ViolenceGraphic,
+    Other(String),
+}
+
+impl ModerationCategory {
+    /// Every category with its own field, in the order OpenAI lists them.
+    pub const ALL: [ModerationCategory; 11] = [
+        ModerationCategory::Harassment,
+        ModerationCategory::HarassmentThreatening,
+        ModerationCategory::Hate,
+        ModerationCategory::HateThreatening,
+        ModerationCategory::SelfHarm,
+        ModerationCategory::SelfHarmInstructions,
+        ModerationCategory::SelfHarmIntent,
+        ModerationCategory::Sexual,
+        ModerationCategory::SexualMinors,
+        ModerationCategory::Violence,
+        ModerationCategory::ViolenceGraphic,
+    ];
+
+    /// The name OpenAI uses for the category.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ModerationCategory::Harassment => "harassment",
+            ModerationCategory::HarassmentThreatening => "harassment/threatening",
+            ModerationCategory::Hate => "hate",
+            ModerationCategory::HateThreatening => "hate/threatening",
+            ModerationCategory::SelfHarm => "self-harm",
+            ModerationCategory::SelfHarmInstructions => "self-harm/instructions",
+            ModerationCategory::SelfHarmIntent => "self-harm/intent",
+            ModerationCategory::Sexual => "sexual",
+            ModerationCategory::SexualMinors => "sexual/minors",
+            ModerationCategory::Violence => "violence",
+            ModerationCategory::ViolenceGraphic => "violence/graphic",
+            ModerationCategory::Other(name) => name,
+        }
+    }
+
+    /// The category in `ALL` OpenAI calls `name`, `None` for anything
+    /// else, e.g. the spam categories.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.as_str() == name)
+    }
+}
+
+impl From<&str> for ModerationCategory {
+    fn from(name: &str) -> Self {
+        Self::from_name(name).unwrap_or_else(|| ModerationCategory::Other(name.to_string()))
+    }
+}
+
+impl From<String> for ModerationCategory {
+    fn from(name: String) -> Self {
+        Self::from_name(&name).unwrap_or(ModerationCategory::Other(name))
+    }
+}
+
+impl From<ModerationCategory> for String {
+    fn from(category: ModerationCategory) -> Self {
+        match category {
+            ModerationCategory::Other(name) => name,
+            category => category.as_str().to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ModerationCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct ModerationCategories {
     pub harassment: bool,
 
@@ -91,26 +212,75 @@ pub struct ModerationCategories {
 
     #[serde(rename = "violence/graphic")]
     pub violence_graphic: bool,
+
+    /// Categories without a field, by OpenAI's name.
+    pub other: BTreeMap<String, bool>,
 }
 
 impl ModerationCategories {
-    pub fn iterate_and_filter_true(&self) -> Vec<String> {
-        let serialized = serde_json::to_value(self).unwrap();
-        let mut true_fields = Vec::new();
-
-        if let Value::Object(map) = serialized {
-            for (key, value) in map {
-                if let Value::Bool(true) = value {
-                    true_fields.push(key);
-                }
-            }
+    /// Flags from OpenAI's names, keeping unknown categories in `other`.
+    pub fn from_names(flags: impl IntoIterator<Item = (String, bool)>) -> Self {
+        let mut categories = ModerationCategories::default();
+        for (name, flagged) in flags {
+            categories.set(&ModerationCategory::from(name), flagged);
+        }
+        categories
+    }
+
+    /// Whether `category` is flagged.
+    pub fn get(&self, category: &ModerationCategory) -> bool {
+        match category {
+            ModerationCategory::Harassment => self.harassment,
+            ModerationCategory::HarassmentThreatening => self.harassment_threatening,
+            ModerationCategory::Hate => self.hate,
+            ModerationCategory::HateThreatening => self.hate_threatening,
+            ModerationCategory::SelfHarm => self.self_harm,
+            ModerationCategory::SelfHarmInstructions => self.self_harm_instructions,
+            ModerationCategory::SelfHarmIntent => self.self_harm_intent,
+            ModerationCategory::Sexual => self.sexual,
+            ModerationCategory::SexualMinors => self.sexual_minors,
+            ModerationCategory::Violence => self.violence,
+            ModerationCategory::ViolenceGraphic => self.violence_graphic,
+            ModerationCategory::Other(name) => self.other.get(name).copied().unwrap_or_default(),
         }
+    }
 
-        true_fields
+    pub fn set(&mut self, category: &ModerationCategory, flagged: bool) {
+        let flag = match category {
+            ModerationCategory::Harassment => &mut self.harassment,
+            ModerationCategory::HarassmentThreatening => &mut self.harassment_threatening,
+            ModerationCategory::Hate => &mut self.hate,
+            ModerationCategory::HateThreatening => &mut self.hate_threatening,
+            ModerationCategory::SelfHarm => &mut self.self_harm,
+            ModerationCategory::SelfHarmInstructions => &mut self.self_harm_instructions,
+            ModerationCategory::SelfHarmIntent => &mut self.self_harm_intent,
+            ModerationCategory::Sexual => &mut self.sexual,
+            ModerationCategory::SexualMinors => &mut self.sexual_minors,
+            ModerationCategory::Violence => &mut self.violence,
+            ModerationCategory::ViolenceGraphic => &mut self.violence_graphic,
+            ModerationCategory::Other(name) => self.other.entry(name.clone()).or_default(),
+        };
+        *flag = flagged;
+    }
+
+    /// Every flagged category, in `ModerationCategory::ALL` order followed
+    /// by the flagged ones in `other`.
+    pub fn flagged(&self) -> Vec<ModerationCategory> {
+        let other = self
+            .other
+            .iter()
+            .filter(|(_, flagged)| **flagged)
+            .map(|(name, _)| ModerationCategory::from(name.as_str()));
+        ModerationCategory::ALL
+            .into_iter()
+            .filter(|category| self.get(category))
+            .chain(other)
+            .collect()
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct ModerationScores {
     pub harassment: f64,
 
@@ -139,36 +309,124 @@ pub struct ModerationScores {
 
     #[serde(rename = "violence/graphic")]
     pub violence_graphic: f64,
+
+    /// Categories without a field, by OpenAI's name.
+    pub other: BTreeMap<String, f64>,
 }
 
 impl ModerationScores {
-    pub fn get_score(&self, field: &str) -> f64 {
-        match field {
-            "harassment" => self.harassment,
-            "harassment/threatening" => self.harassment_threatening,
-            "hate" => self.hate,
-            "hate/threatening" => self.hate_threatening,
-            "self-harm" => self.self_harm,
-            "self-harm/instructions" => self.self_harm_instructions,
-            "self-harm/intent" => self.self_harm_intent,
-            "sexual" => self.sexual,
-            "sexual/minors" => self.sexual_minors,
-            "violence" => self.violence,
-            "violence/graphic" => self.violence_graphic,
-            _ => 0.0,
+    /// Scores from OpenAI's names, keeping unknown categories in `other`.
+    pub fn from_names(scores: impl IntoIterator<Item = (String, f64)>) -> Self {
+        let mut result = ModerationScores::default();
+        for (name, score) in scores {
+            result.set(&ModerationCategory::from(name), score);
+        }
+        result
+    }
+
+    pub fn get(&self, category: &ModerationCategory) -> f64 {
+        match category {
+            ModerationCategory::Harassment => self.harassment,
+            ModerationCategory::HarassmentThreatening => self.harassment_threatening,
+            ModerationCategory::Hate => self.hate,
+            ModerationCategory::HateThreatening => self.hate_threatening,
+            ModerationCategory::SelfHarm => self.self_harm,
+            ModerationCategory::SelfHarmInstructions => self.self_harm_instructions,
+            ModerationCategory::SelfHarmIntent => self.self_harm_intent,
+            ModerationCategory::Sexual => self.sexual,
+            ModerationCategory::SexualMinors => self.sexual_minors,
+            ModerationCategory::Violence => self.violence,
+            ModerationCategory::ViolenceGraphic => self.violence_graphic,
+            ModerationCategory::Other(name) => self.other.get(name).copied().unwrap_or_default(),
         }
     }
+
+    pub fn set(&mut self, category: &ModerationCategory, score: f64) {
+        let value = match category {
+            ModerationCategory::Harassment => &mut self.harassment,
+            ModerationCategory::HarassmentThreatening => &mut self.harassment_threatening,
+            ModerationCategory::Hate => &mut self.hate,
+            ModerationCategory::HateThreatening => &mut self.hate_threatening,
+            ModerationCategory::SelfHarm => &mut self.self_harm,
+            ModerationCategory::SelfHarmInstructions => &mut self.self_harm_instructions,
+            ModerationCategory::SelfHarmIntent => &mut self.self_harm_intent,
+            ModerationCategory::Sexual => &mut self.sexual,
+            ModerationCategory::SexualMinors => &mut self.sexual_minors,
+            ModerationCategory::Violence => &mut self.violence,
+            ModerationCategory::ViolenceGraphic => &mut self.violence_graphic,
+            ModerationCategory::Other(name) => self.other.entry(name.clone()).or_default(),
+        };
+        *value = score;
+    }
+
+    /// Every score, in `ModerationCategory::ALL` order followed by `other`.
+    pub fn all(&self) -> Vec<(ModerationCategory, f64)> {
+        let other = self
+            .other
+            .iter()
+            .map(|(name, score)| (ModerationCategory::from(name.as_str()), *score));
+        ModerationCategory::ALL
+            .into_iter()
+            .map(|category| {
+                let score = self.get(&category);
+                (category, score)
+            })
+            .chain(other)
+            .collect()
+    }
+}
+
+/// The body of a moderation request. `input` is one text, several texts
+/// classified separately, or the parts of one multimodal input.
+#[derive(Serialize)]
+struct ModerationRequest<'a, I> {
+    input: I,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<&'a str>,
+}
+
+/// Part of a multimodal input, for the omni models.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputPart<'a> {
+    Text { text: &'a str },
+    ImageUrl { image_url: ImageUrl<'a> },
 }
 
 #[derive(Serialize)]
-struct ModerationRequest {
-    input: String,
+struct ImageUrl<'a> {
+    url: &'a str,
+}
+
+/// File extensions of links sent to OpenAI as images.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+
+/// Links in `text` that point straight at an image, e.g.
+/// `https://i.imgur.com/abc.png`, in the order they appear.
+pub fn find_image_urls(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .filter(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .filter(|word| {
+            let path = word.split(['?', '#']).next().unwrap_or_default();
+            let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+            let has_path = path
+                .split_once("://")
+                .is_some_and(|(_, rest)| rest.contains('/'));
+            has_path && extension.is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+        })
+        .map(|word| word.to_string())
+        .collect()
 }
 
+#[derive(Debug)]
 pub enum ModerationError {
     IoError(std::io::Error),
     ApiError,
     ConnectionError,
+    /// No provider is configured, e.g. OpenAI without `OPEN_AI_KEY`.
+    Unavailable,
+    /// The provider took longer than the bot waits for a decision.
+    TimedOut,
 }
 
 impl std::fmt::Display for ModerationError {
@@ -177,6 +435,8 @@ impl std::fmt::Display for ModerationError {
             ModerationError::IoError(e) => write!(f, "IO Error: {}", e),
             ModerationError::ApiError => write!(f, "API Error"),
             ModerationError::ConnectionError => write!(f, "Connection Error"),
+            ModerationError::Unavailable => write!(f, "No moderation provider available"),
+            ModerationError::TimedOut => write!(f, "Timed Out"),
         }
     }
 }
@@ -187,193 +447,234 @@ impl From<std::io::Error> for ModerationError {
     }
 }
 
-pub struct OpenAiApiModeration {
-    api_key: String,
-    input: String,
+const MODERATION_URL: &str = "https://api.openai.com/v1/moderations";
+
+/// How long a moderation request may take by default. Shorter than the
+/// bot waits for a decision, so a fallback provider still gets to answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long connecting to the endpoint may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The key moderation requests are sent with and where they go.
+#[derive(Debug, Clone)]
+pub struct OpenAiConfig {
+    pub api_key: String,
+    pub endpoint: String,
+    /// The moderation model, e.g. `"omni-moderation-latest"`. `None` leaves
+    /// the choice to OpenAI.
+    pub model: Option<String>,
+    /// Whether images linked in a message are sent along with its text.
+    /// Only the omni models accept images.
+    pub image_inputs: bool,
+    /// How long a request may take before it fails, connecting included.
+    pub timeout: Duration,
 }
 
-impl OpenAiApiModeration {
-    pub fn new(input: &str) -> Self {
-        OpenAiApiModeration {
-            api_key: env::var("OPEN_AI_KEY").expect("Failed to get Open AI Key"),
-            input: input.to_string(),
+impl OpenAiConfig {
+    pub fn new(api_key: &str, endpoint: &str) -> Self {
+        OpenAiConfig {
+            api_key: api_key.to_string(),
+            endpoint: endpoint.to_string(),
+            model: None,
+            image_inputs: false,
+            timeout: REQUEST_TIMEOUT,
         }
     }
 
-    pub async fn handle_input_check(&self) -> Result<ModerationResponse, ModerationError> {
-        let client = reqwest::Client::new();
-        let endpoint = "https://api.openai.com/v1/moderations";
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
 
-        let request_body = ModerationRequest {
-            input: self.input.clone(),
-        };
+    pub fn with_image_inputs(mut self, image_inputs: bool) -> Self {
+        self.image_inputs = image_inputs;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Reads the key from `OPEN_AI_KEY` and uses OpenAI's endpoint.
+    pub fn from_env() -> Self {
+        Self::try_from_env().expect("Failed to get Open AI Key")
+    }
+
+    /// Like `from_env`, but `None` when `OPEN_AI_KEY` is unset. The model
+    /// is read from `OPEN_AI_MODERATION_MODEL` and image inputs are turned
+    /// on by setting `OPEN_AI_MODERATION_IMAGES` to `true`.
+    pub fn try_from_env() -> Option<Self> {
+        let api_key = env::var("OPEN_AI_KEY").ok()?;
+        let mut config = OpenAiConfig::new(&api_key, MODERATION_URL);
+        if let Ok(model) = env::var("OPEN_AI_MODERATION_MODEL") {
+            config = config.with_model(&model);
+        }
+        let images = env::var("OPEN_AI_MODERATION_IMAGES").unwrap_or_default();
+        Some(config.with_image_inputs(images.eq_ignore_ascii_case("true")))
+    }
+}
+
+/// Classifies messages with OpenAI's moderation endpoint.
+///
+/// Cloning is cheap and clones share one HTTP client and its connections.
+#[derive(Clone)]
+pub struct OpenAiProvider {
+    config: OpenAiConfig,
+    client: reqwest::Client,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: OpenAiConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT.min(config.timeout))
+            .timeout(config.timeout)
+            .build()
+            .expect("Failed to build HTTP client");
+        OpenAiProvider { config, client }
+    }
 
-        let response = client
-            .post(endpoint)
+    /// Images linked in `text` that are sent along with it, none unless
+    /// image inputs are turned on.
+    pub fn image_urls(&self, text: &str) -> Vec<String> {
+        if self.config.image_inputs {
+            find_image_urls(text)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Classifies several messages in one request. Results are in the
+    /// order of `texts`.
+    pub async fn classify_batch(
+        &self,
+        texts: &[String],
+    ) -> Result<Vec<ModerationResult>, ModerationError> {
+        let response = self.send(texts).await?;
+        if response.results.len() != texts.len() {
+            return Err(ModerationError::ApiError);
+        }
+        Ok(response
+            .results
+            .into_iter()
+            .map(ModerationResult::from)
+            .collect())
+    }
+
+    /// Classifies `text` together with the image at `image_url` as one
+    /// multimodal input.
+    pub async fn classify_with_image(
+        &self,
+        text: &str,
+        image_url: &str,
+    ) -> Result<ModerationResult, ModerationError> {
+        let parts = [
+            InputPart::Text { text },
+            InputPart::ImageUrl {
+                image_url: ImageUrl { url: image_url },
+            },
+        ];
+        let response = self.send(&parts[..]).await?;
+        let result = response
+            .results
+            .into_iter()
+            .next()
+            .ok_or(ModerationError::ApiError)?;
+        Ok(result.into())
+    }
+
+    async fn send<I: Serialize>(&self, input: I) -> Result<ModerationResponse, ModerationError> {
+        let request = ModerationRequest {
+            input,
+            model: self.config.model.as_deref(),
+        };
+        let response = self
+            .client
+            .post(&self.config.endpoint)
             .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .json(&request_body)
+            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .json(&request)
             .send()
             .await
             .map_err(|_| ModerationError::ConnectionError)?;
 
-        let moderation_response: ModerationResponse = response.json().await.map_err(|e| {
-            println!("{} {}", "Error Parsing Response".red(), e); // !REMOVE
+        response.json().await.map_err(|e| {
+            println!("{} {}", "Error Parsing Response".red(), e);
             ModerationError::ApiError
-        })?;
-
-        Ok(moderation_response)
-    }
-
-    pub fn moderate_input(&self, mod_results: FlaggedMessage) {
-        let default_thresholds = DefaultThresholds {
-            harassment: 0.950,
-            harassment_threatening: 0.970,
-            hate: 0.55,
-            hate_threatening: 0.960,
-            self_harm: 0.980,
-            self_harm_instructions: 0.970,
-            self_harm_intent: 0.950,
-            sexual: 0.88,
-            sexual_minors: 0.50,
-            violence: 0.95,
-            violence_graphic: 0.990,
-        };
+        })
+    }
+}
 
-        println!("{}", "Moderating Input".bright_red().bold().underline()); // !REMOVE
-        println!(
-            "{}",
-            "Moderation Results: ".bright_purple().bold().underline()
-        ); // !REMOVE
-
-        let (threshold, punishment) = match &mod_results.category[..] {
-            "harassment" => (default_thresholds.harassment, PunishmentAction::Warn),
-            "harassment/threatening" => (
-                default_thresholds.harassment_threatening,
-                PunishmentAction::Ban,
-            ),
-            "hate" => (default_thresholds.hate, PunishmentAction::Timeout(60)),
-            "hate/threatening" => (default_thresholds.hate_threatening, PunishmentAction::Ban),
-            "self-harm" => (default_thresholds.self_harm, PunishmentAction::Delete),
-            "self-harm/instructions" => (
-                default_thresholds.self_harm_instructions,
-                PunishmentAction::Delete,
-            ),
-            "self-harm/intent" => (
-                default_thresholds.self_harm_intent,
-                PunishmentAction::Timeout(120),
-            ),
-            "sexual" => (default_thresholds.sexual, PunishmentAction::Delete),
-            "sexual/minors" => (default_thresholds.sexual_minors, PunishmentAction::Ban),
-            "violence" => (default_thresholds.violence, PunishmentAction::Timeout(30)),
-            "violence/graphic" => (
-                default_thresholds.violence_graphic,
-                PunishmentAction::Delete,
-            ),
-            _ => (0.0, PunishmentAction::None),
-        };
+#[async_trait::async_trait]
+impl ModerationProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        "openai"
+    }
 
-        let rounded_score = round_to_decimal_places(mod_results.score);
-
-        if rounded_score >= threshold {
-            println!(
-                "{}: {} {} {}",
-                mod_results.category.bright_yellow().bold(),
-                "Flagged".bright_red().bold(),
-                mod_results.username,
-                mod_results.user_id
-            );
-            println!("{}: {}", "Score".bright_yellow().bold(), rounded_score);
-
-            match punishment {
-                PunishmentAction::Timeout(duration) => {
-                    println!(
-                        "{}: {} {} {}",
-                        "Punishment Issues".bright_cyan().bold().underline(),
-                        mod_results.username,
-                        mod_results.text.on_bright_green(),
-                        mod_results.category.red()
-                    );
-
-                    println!("{}: {} seconds", "Timeout".bright_cyan().bold(), duration);
-                }
-                PunishmentAction::Ban => {
-                    println!(
-                        "{}: {} {} {}",
-                        "Punishment Issues".bright_cyan().bold().underline(),
-                        mod_results.username,
-                        mod_results.text,
-                        mod_results.category
-                    );
-
-                    println!(
-                        "{}: {}",
-                        "Punishment".bright_cyan().bold(),
-                        "Ban".bright_red().bold()
-                    );
-                }
-                PunishmentAction::Delete => {
-                    println!(
-                        "{}: {} {} {}",
-                        "Punishment Issues".bright_cyan().bold().underline(),
-                        mod_results.username,
-                        mod_results.text,
-                        mod_results.category
-                    );
-
-                    println!(
-                        "{}: {}",
-                        "Punishment".bright_cyan().bold(),
-                        "Delete".bright_red().bold()
-                    );
-                }
-                PunishmentAction::Warn => {
-                    println!(
-                        "{}: {} {} {}",
-                        "Punishment Issues".bright_cyan().bold().underline(),
-                        mod_results.username,
-                        mod_results.text,
-                        mod_results.category
-                    );
-
-                    println!(
-                        "{}: {}",
-                        "Punishment".bright_cyan().bold(),
-                        "Warn".bright_yellow().bold()
-                    );
-                }
-                PunishmentAction::None => {
-                    println!(
-                        "{}: {} {} {}",
-                        "Punishment Issues".bright_cyan().bold().underline(),
-                        mod_results.username,
-                        mod_results.text,
-                        mod_results.category
-                    );
-
-                    println!(
-                        "{}: {}",
-                        "Punishment".bright_cyan().bold(),
-                        "None".bright_green().bold()
-                    );
-                }
-            }
-        } else {
-            println!(
-                "{}: {}",
-                "No Offence Found".bright_yellow().bold(),
-                "Not Flagged".bright_green().bold()
-            );
+    async fn classify(&self, text: &str) -> Result<ModerationResult, ModerationError> {
+        let response = self.send(text).await?;
+        let result = response
+            .results
+            .into_iter()
+            .next()
+            .ok_or(ModerationError::ApiError)?;
+        Ok(result.into())
+    }
 
-            println!("{}: {}", "Score".bright_yellow().bold(), rounded_score);
+    /// Sends the first image linked in the message along with its
+    /// skeleton when image inputs are on. OpenAI takes one image per
+    /// input.
+    async fn classify_message(
+        &self,
+        message: &NormalizedText,
+    ) -> Result<ModerationResult, ModerationError> {
+        match self.image_urls(&message.original).first() {
+            Some(image_url) => self.classify_with_image(&message.skeleton, image_url).await,
+            None => self.classify(&message.skeleton).await,
+        }
+    }
+}
 
-            println!("{}", "=====================================================".bright_yellow().bold());
-            println!("{}", "=====================================================".bright_yellow().bold());
+/// Carries out a punishment for a flagged message through Helix.
+pub async fn execute_punishment(
+    helix: &HelixClient,
+    broadcaster_id: &str,
+    flagged: &FlaggedMessage,
+    punishment: PunishmentAction,
+) -> Result<(), HelixError> {
+    let reason = format!("Automated moderation: {}", flagged.category);
+    match punishment {
+        PunishmentAction::Timeout(seconds) => {
+            helix
+                .timeout_user(
+                    broadcaster_id,
+                    &flagged.user_id,
+                    Duration::from_secs(seconds),
+                    &reason,
+                )
+                .await
+        }
+        PunishmentAction::Ban => {
+            helix
+                .ban_user(broadcaster_id, &flagged.user_id, &reason)
+                .await
+        }
+        PunishmentAction::Delete => {
+            helix
+                .delete_message(broadcaster_id, &flagged.message_id)
+                .await
+        }
+        PunishmentAction::Warn => {
+            helix
+                .warn_user(broadcaster_id, &flagged.user_id, &reason)
+                .await
         }
+        PunishmentAction::None => Ok(()),
     }
 }
 
-fn round_to_decimal_places(value: f64) -> f64 {
+pub(crate) fn round_to_decimal_places(value: f64) -> f64 {
     let multiplier = 10_u64.pow(3) as f64;
     (value * multiplier).round() / multiplier
 }
@@ -1,189 +1,1011 @@
-use crate::openai::moderation::FlaggedMessage;
+use crate::moderation::audit::{ActionResult, AuditEntry, AuditLog, DecisionOrigin};
+use crate::moderation::batcher::ModerationMetrics;
+use crate::moderation::blocked_terms::{BlockedTermStore, BLOCKED_TERM_CATEGORY};
+use crate::moderation::normalize::NormalizedText;
+use crate::moderation::policy::PolicyStore;
+use crate::moderation::pool::{ModerationOutcome, ModerationPool, PoolConfig};
+use crate::moderation::provider::{self, ModerationProvider};
+use crate::moderation::review::ReviewQueue;
+use crate::moderation::strikes::StrikeLedger;
+use crate::openai::moderation::{
+    execute_punishment, FlaggedMessage, ModerationCategory, ModerationScores, OpenAiConfig,
+    OpenAiProvider, PunishmentAction,
+};
+use chrono::Utc;
 use colored::Colorize;
 
 // bot.rs
-use super::commands::CommandHandler;
+use super::channel::{ChannelContext, ChannelSettings, PermissionLevel};
+use super::chat_event::{ChatEvent, ClearChatAction, UserNoticeKind};
 use super::commands::CustomCommand;
-use super::twitch_api::{TwitchChatAPI, TwitchError, TwitchMessage};
+use super::helix::HelixClient;
+use super::identity::BotAccounts;
+use super::outgoing::DeliveryMode;
+use super::supervisor::{Backoff, ConnectionState, ConnectionSupervisor};
+use super::twitch_api::{
+    normalize_channel, ChatEndpoint, TwitchChatAPI, TwitchError, TwitchMessage,
+};
 use super::twitch_endpoint;
-use crate::openai;
-use std::io::ErrorKind;
 use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
 
-pub struct Bot<'a> {
-    api: TwitchChatAPI<'a>,
-    command_handler: CommandHandler,
+/// Requests sent to a running bot from outside its event loop.
+pub enum BotControl {
+    Join(String, Box<ChannelSettings>),
+    Part(String),
 }
 
-impl<'a> Bot<'a> {
-    pub fn new(access_token: &'a str, channel: &'a str) -> Result<Self, TwitchError> {
-        let api = TwitchChatAPI::new(access_token, channel)?;
+/// A cloneable handle for joining and leaving channels while the bot runs.
+#[derive(Clone)]
+pub struct BotHandle {
+    control_tx: mpsc::UnboundedSender<BotControl>,
+}
 
-        let command_handler = CommandHandler::new(|| match get_custom_commands() {
-            Ok(command) => command,
-            Err(e) => {
-                println!("Error Getting Commands {e}");
-                vec![]
-            }
-        });
+impl BotHandle {
+    pub fn join(&self, channel: &str, settings: ChannelSettings) {
+        let _ = self
+            .control_tx
+            .send(BotControl::Join(channel.to_string(), Box::new(settings)));
+    }
+
+    pub fn part(&self, channel: &str) {
+        let _ = self.control_tx.send(BotControl::Part(channel.to_string()));
+    }
+}
+
+pub struct Bot {
+    accounts: BotAccounts,
+    /// Chosen from the environment on first use unless set explicitly.
+    provider: Option<Arc<dyn ModerationProvider>>,
+    /// Built from the moderator account on first use unless set explicitly.
+    helix: Option<HelixClient>,
+    /// Per-channel moderation policies, read for every flagged message.
+    policies: PolicyStore,
+    /// Strikes per user, raising the punishment for repeat offenders.
+    strikes: StrikeLedger,
+    /// Where every moderation decision is recorded.
+    audit: AuditLog,
+    /// Per-channel blocked terms, checked before the moderation provider.
+    blocked_terms: BlockedTermStore,
+    /// Messages scoring just below a threshold, left for a moderator.
+    review: ReviewQueue,
+    /// What the batched OpenAI provider chosen from the environment has
+    /// cost.
+    metrics: ModerationMetrics,
+    pool_config: PoolConfig,
+    /// Classifies messages off the chat loop, started on first use.
+    pool: Option<ModerationPool>,
+    outcome_tx: mpsc::UnboundedSender<ModerationOutcome>,
+    outcome_rx: mpsc::UnboundedReceiver<ModerationOutcome>,
+    connection: ConnectionSupervisor,
+    channels: HashMap<String, ChannelContext>,
+    control_tx: mpsc::UnboundedSender<BotControl>,
+    control_rx: mpsc::UnboundedReceiver<BotControl>,
+}
+
+impl Bot {
+    pub fn new(accounts: BotAccounts, channels: &[&str]) -> Result<Self, TwitchError> {
+        Self::with_endpoint(accounts, channels, ChatEndpoint::default())
+    }
+
+    pub fn with_endpoint(
+        accounts: BotAccounts,
+        channels: &[&str],
+        endpoint: ChatEndpoint,
+    ) -> Result<Self, TwitchError> {
+        let api = TwitchChatAPI::with_endpoint(accounts.chat().clone(), channels, endpoint)?;
+
+        let channels = api
+            .channels()
+            .iter()
+            .map(|name| (name.clone(), new_channel_context(name, ChannelSettings::default())))
+            .collect();
+
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let (outcome_tx, outcome_rx) = mpsc::unbounded_channel();
         Ok(Bot {
-            api,
-            command_handler,
+            accounts,
+            provider: None,
+            helix: None,
+            policies: PolicyStore::in_memory(),
+            strikes: StrikeLedger::in_memory(),
+            audit: AuditLog::in_memory(),
+            blocked_terms: BlockedTermStore::in_memory(),
+            review: ReviewQueue::in_memory(),
+            metrics: ModerationMetrics::new(),
+            pool_config: PoolConfig::default(),
+            pool: None,
+            outcome_tx,
+            outcome_rx,
+            connection: ConnectionSupervisor::new(api, Backoff::default()),
+            channels,
+            control_tx,
+            control_rx,
         })
     }
 
-    // ...
+    /// Moderates with OpenAI alone, using this key and endpoint.
+    pub fn with_openai(self, config: OpenAiConfig) -> Self {
+        self.with_provider(OpenAiProvider::new(config))
+    }
+
+    /// Classifies messages with this provider.
+    pub fn with_provider<P: ModerationProvider + 'static>(mut self, provider: P) -> Self {
+        self.provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Takes moderation actions through this client.
+    pub fn with_helix(mut self, helix: HelixClient) -> Self {
+        self.helix = Some(helix);
+        self
+    }
+
+    /// Moderates with the policies in this store. Changes made to the store
+    /// while the bot runs apply to the next message.
+    pub fn with_policies(mut self, policies: PolicyStore) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    pub fn policies(&self) -> &PolicyStore {
+        &self.policies
+    }
+
+    /// Records strikes in this ledger, e.g. one shared with the app.
+    pub fn with_strikes(mut self, strikes: StrikeLedger) -> Self {
+        self.strikes = strikes;
+        self
+    }
+
+    pub fn strikes(&self) -> &StrikeLedger {
+        &self.strikes
+    }
+
+    /// Records moderation decisions in this log, e.g. one shared with the
+    /// app.
+    pub fn with_audit(mut self, audit: AuditLog) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Reads blocked terms from this store, e.g. one shared with the app.
+    pub fn with_blocked_terms(mut self, blocked_terms: BlockedTermStore) -> Self {
+        self.blocked_terms = blocked_terms;
+        self
+    }
+
+    pub fn blocked_terms(&self) -> &BlockedTermStore {
+        &self.blocked_terms
+    }
+
+    /// Queues borderline messages in this queue, e.g. one shared with the
+    /// app.
+    pub fn with_review_queue(mut self, review: ReviewQueue) -> Self {
+        self.review = review;
+        self
+    }
+
+    pub fn review_queue(&self) -> &ReviewQueue {
+        &self.review
+    }
+
+    /// Counts the cost of the provider chosen from the environment into
+    /// these metrics, e.g. ones shared with the app.
+    pub fn with_metrics(mut self, metrics: ModerationMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn metrics(&self) -> &ModerationMetrics {
+        &self.metrics
+    }
+
+    /// Sets how many messages are classified at once and how long the
+    /// provider may take for each.
+    pub fn with_moderation_pool(mut self, config: PoolConfig) -> Self {
+        self.pool_config = config;
+        self
+    }
+
+    /// Replaces the reconnect backoff.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.connection.set_backoff(backoff);
+        self
+    }
+
+    pub fn accounts(&self) -> &BotAccounts {
+        &self.accounts
+    }
+
+    /// Follows the chat connection's state, e.g. to show it in the app
+    /// while the bot runs.
+    pub fn connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.connection.subscribe()
+    }
+
+    pub fn handle(&self) -> BotHandle {
+        BotHandle {
+            control_tx: self.control_tx.clone(),
+        }
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&ChannelContext> {
+        self.channels.get(&normalize_channel(name))
+    }
+
+    pub fn channel_mut(&mut self, name: &str) -> Option<&mut ChannelContext> {
+        self.channels.get_mut(&normalize_channel(name))
+    }
+
+    /// Joins a channel over the existing connection with its own settings.
+    pub async fn join_channel(
+        &mut self,
+        channel: &str,
+        settings: ChannelSettings,
+    ) -> Result<(), TwitchError> {
+        self.connection.api_mut().join(channel).await?;
+        let name = normalize_channel(channel);
+        self.channels
+            .insert(name.clone(), new_channel_context(&name, settings));
+        Ok(())
+    }
+
+    pub async fn part_channel(&mut self, channel: &str) -> Result<(), TwitchError> {
+        self.connection.api_mut().part(channel).await?;
+        self.channels.remove(&normalize_channel(channel));
+        Ok(())
+    }
 
     pub async fn run(&mut self) -> Result<(), TwitchError> {
-        self.api.connect()?;
+        self.connection.connect().await?;
         loop {
-            match self.api.read_message() {
-                Ok(Some(message)) => self.handle_message(&message).await,
-                Ok(None) => {}
-                Err(TwitchError::IOError(ref e)) if e.kind() == ErrorKind::WouldBlock => {
-                    std::thread::sleep(std::time::Duration::from_millis(500));
+            tokio::select! {
+                // Only the wait sits in the select; the reads, writes and
+                // reconnects it wakes up for run to completion below.
+                wakeup = self.connection.wait() => {
+                    if let Some(event) = self.connection.process(wakeup).await? {
+                        self.handle_event(event).await;
+                    }
                 }
-                Err(e) => return Err(e),
+                Some(control) = self.control_rx.recv() => self.handle_control(control).await,
+                Some(outcome) = self.outcome_rx.recv() => self.handle_outcome(outcome).await,
+            }
+        }
+    }
+
+    async fn handle_control(&mut self, control: BotControl) {
+        let result = match control {
+            BotControl::Join(channel, settings) => self.join_channel(&channel, *settings).await,
+            BotControl::Part(channel) => self.part_channel(&channel).await,
+        };
+        if let Err(e) = result {
+            eprintln!("Error changing channels: {:?}", e);
+        }
+    }
+
+    async fn handle_event(&mut self, event: ChatEvent) {
+        self.audit_manual_action(&event);
+        match event {
+            ChatEvent::Message(message) => self.handle_message(&message).await,
+            ChatEvent::ClearChat(clear) => match clear.action {
+                ClearChatAction::ClearAll => {
+                    println!("{} #{}", "Chat Cleared".bright_blue().bold(), clear.channel)
+                }
+                ClearChatAction::Ban { login, .. } => {
+                    println!("{}: {}", "User Banned".bright_red().bold(), login)
+                }
+                ClearChatAction::Timeout {
+                    login, duration, ..
+                } => println!(
+                    "{}: {} ({}s)",
+                    "User Timed Out".bright_red().bold(),
+                    login,
+                    duration.as_secs()
+                ),
+            },
+            ChatEvent::ClearMsg(clear) => println!(
+                "{}: {} {}",
+                "Message Deleted".bright_red().bold(),
+                clear.login,
+                clear.text
+            ),
+            ChatEvent::UserNotice(notice) => self.handle_user_notice(&notice.kind),
+            ChatEvent::RoomState(state) => {
+                println!("{}: {:?}", "Room State".bright_blue().bold(), state)
+            }
+            ChatEvent::UserState(state) => println!(
+                "{}: #{} mod={}",
+                "User State".bright_blue().bold(),
+                state.channel,
+                state.moderator
+            ),
+            ChatEvent::GlobalUserState(state) => println!(
+                "{}: {} ({})",
+                "Logged In As".bright_green().bold(),
+                state.display_name,
+                state.user_id
+            ),
+            ChatEvent::Notice(notice) => {
+                println!("{}: {}", "Notice".bright_yellow().bold(), notice.text)
+            }
+            ChatEvent::Whisper(whisper) => println!(
+                "{} {}: {}",
+                "Whisper From".bright_magenta().bold(),
+                whisper.from_login,
+                whisper.text
+            ),
+            ChatEvent::Join(member) => {
+                println!("{}: {} #{}", "Join".dimmed(), member.login, member.channel)
+            }
+            ChatEvent::Part(member) => {
+                println!("{}: {} #{}", "Part".dimmed(), member.login, member.channel)
+            }
+            ChatEvent::HostTarget(host) => println!(
+                "{}: {:?}",
+                "Host Target".bright_blue().bold(),
+                host.target
+            ),
+            ChatEvent::Reconnect => {
+                println!("{}", "Reconnected At Server's Request".bright_yellow().bold())
+            }
+        }
+    }
+
+    fn handle_user_notice(&mut self, kind: &UserNoticeKind) {
+        match kind {
+            UserNoticeKind::Sub(sub) | UserNoticeKind::Resub(sub) => println!(
+                "{}: {} {:?} ({} months)",
+                "Subscription".bright_magenta().bold(),
+                sub.subscriber,
+                sub.tier,
+                sub.months
+            ),
+            UserNoticeKind::SubGift(sub) => println!(
+                "{}: {} -> {} {:?}",
+                "Gift Sub".bright_magenta().bold(),
+                sub.gifted_by.as_deref().unwrap_or("anonymous"),
+                sub.subscriber,
+                sub.tier
+            ),
+            UserNoticeKind::GiftBomb(gift) => println!(
+                "{}: {} gifted {} {:?} subs",
+                "Gift Bomb".bright_magenta().bold(),
+                gift.gifted_by,
+                gift.count,
+                gift.tier
+            ),
+            UserNoticeKind::Raid(raid) => println!(
+                "{}: {} with {} viewers",
+                "Raid".bright_magenta().bold(),
+                raid.from_display_name,
+                raid.viewers
+            ),
+            UserNoticeKind::Announcement { .. } => {
+                println!("{}", "Announcement".bright_magenta().bold())
+            }
+            UserNoticeKind::Other(msg_id) => {
+                println!("{}: {}", "User Notice".bright_magenta().bold(), msg_id)
             }
         }
     }
 
     async fn handle_message(&mut self, message: &TwitchMessage) {
+        // A read-only connection can neither moderate nor answer commands.
+        if self.connection.api().is_read_only() {
+            return;
+        }
+
+        let moderate = match self.channels.get(&message.channel) {
+            Some(context) => {
+                let settings = &context.settings;
+                settings.moderation_enabled
+                    && !settings.exemptions.is_exempt(
+                        &message.user_id,
+                        &message.sender,
+                        &message.badges,
+                    )
+            }
+            None => return,
+        };
+
+        if moderate && !self.passes_spam_filters(message).await {
+            return;
+        }
+
+        // Filters and the provider see the skeleton, logs keep the original.
+        let normalized = NormalizedText::new(&message.text);
+
+        if moderate && !self.passes_blocked_terms(message, &normalized).await {
+            return;
+        }
 
-        let moderation = openai::moderation::OpenAiApiModeration::new(&message.text);
+        // Commands answer right away, the provider's decision follows.
+        if !self.handle_moderator_command(message).await {
+            self.handle_command(message).await;
+        }
+
+        if moderate && !self.pool().submit(message.clone(), normalized).await {
+            eprintln!("Error Handling Moderation: moderation workers stopped");
+        }
+    }
+
+    /// The pool classifying messages, started with the provider on first
+    /// use.
+    fn pool(&mut self) -> &ModerationPool {
+        if self.pool.is_none() {
+            let provider = self
+                .provider
+                .get_or_insert_with(|| Arc::from(provider::from_env(&self.metrics)))
+                .clone();
+            self.pool = Some(ModerationPool::spawn(
+                provider,
+                self.pool_config.clone(),
+                self.outcome_tx.clone(),
+            ));
+        }
+        self.pool.as_ref().unwrap()
+    }
 
-        match moderation.handle_input_check().await {
+    /// Acts on the provider's decision for a message, which may have been
+    /// sent a while ago. Punishments refer to the message by its id.
+    async fn handle_outcome(&mut self, outcome: ModerationOutcome) {
+        let message = &outcome.message;
+        match outcome.result {
             Ok(res) => {
 
-                let is_flagged = res.results[0].flagged;
-                let categories = &res.results[0].categories;
-                let scores = &res.results[0].category_scores;
+                let scores = &res.scores;
 
-                if is_flagged {
+                // The channel's thresholds decide, not the provider's own
+                // flag: a stricter channel acts on messages it left alone.
+                // A flagged message below every threshold is still decided
+                // on, so that letting it through is audited.
+                let policy = self.policies.get(&message.channel);
+                let mut reached = policy.reached(scores);
+                if reached.is_empty() && res.flagged {
+                    reached = res.categories.flagged();
+                }
+                if !reached.is_empty() {
 
                     println!("{}", "=====================================================".bright_yellow().bold());
                     println!("{}", "=====================================================".bright_yellow().bold());
 
-                    println!("{}", "Message is FLAGGED".bright_red().bold());
-                    let true_fields = categories.iterate_and_filter_true();
+                    if res.flagged {
+                        println!("{}", "Message is FLAGGED".bright_red().bold());
+                    }
 
-                    println!("{}: {:?}", "Moderation Scores".bright_yellow().bold(), res.results[0].category_scores);
+                    println!("{}: {:?}", "Moderation Scores".bright_yellow().bold(), res.scores);
 
                     println!(
                         "{}: {:?}",
                         "True Fields".bright_yellow().bold(),
-                        true_fields
+                        res.categories.flagged()
                     );
 
-
-                    let offence = match determine_offence(true_fields) {
+                    let severity_order = self
+                        .channel(&message.channel)
+                        .map(|context| context.settings.severity_order.clone())
+                        .unwrap_or_default();
+                    let offence = match policy.decide(&reached, scores, &severity_order) {
                         Some(offence) => offence,
-                        None => {
-                            "No Offence Found".to_string();
-                            return
-                        },
+                        None => return,
                     };
 
 
                     let offender_name = &message.sender;
-                    let score = scores.get_score(&offence);
+                    let score = offence.score;
                     let user_text = &message.text;
 
                     println!(
                         "{} {}: {} {} {} {}",
                         "OFFENCE".red().bold().underline(),
                         offender_name,
-                        offence,
+                        offence.category,
                         score,
                         "USER TEXT".bright_purple().bold().underline(),
                         user_text
                     );
 
-                    let offender_twitch_id = match twitch_endpoint::get_user_twitch_id(
-                        &offender_name,
-                        &self.api,
-                    )
-                    .await
-                    {
-                        Ok(id) => id,
-                        Err(e) => {
-                            println!(
-                                "{} {e}",
-                                "ERROR GETTING TWITCH TOKEN: ".bright_red().bold().underline()
-                            );
-                            return;
+                    let offender_twitch_id = if message.user_id.is_empty() {
+                        let access_token = self.accounts.api_token().unwrap_or_default();
+                        match twitch_endpoint::get_user_twitch_id(offender_name, access_token)
+                            .await
+                        {
+                            Ok(id) => id,
+                            Err(e) => {
+                                println!(
+                                    "{} {e}",
+                                    "ERROR GETTING TWITCH TOKEN: ".bright_red().bold().underline()
+                                );
+                                return;
+                            }
                         }
+                    } else {
+                        message.user_id.clone()
                     };
-                    
+
                     let flagged_message = FlaggedMessage::new(
-                        &offender_name,
+                        offender_name,
                         &offender_twitch_id,
-                        &user_text,
-                        &offence,
+                        &message.id,
+                        user_text,
+                        offence.category.clone(),
                         score,
                     );
 
-                    moderation.moderate_input(flagged_message);
+                    self.enforce(
+                        message,
+                        flagged_message,
+                        scores.clone(),
+                        Some(offence.threshold),
+                        offence.punishment,
+                        true,
+                    )
+                    .await;
+                    if offence.punishment != PunishmentAction::None {
+                        return;
+                    }
+                }
+                self.queue_for_review(message, scores);
+            }
+            Err(e) => {
+                eprintln!("Error Handling Moderation: {} {e}", message.id);
+            }
+        }
+    }
 
-                    return;
+    /// Queues a message that no category punished for a moderator to review
+    /// if it scored within the channel's review margin of a threshold.
+    fn queue_for_review(&self, message: &TwitchMessage, scores: &ModerationScores) {
+        let margin = match self.channel(&message.channel) {
+            Some(context) if context.settings.review_margin > 0.0 => context.settings.review_margin,
+            _ => return,
+        };
+        let policy = self.policies.get(&message.channel);
+        let (category, rule) = match policy.borderline(scores, margin) {
+            Some(borderline) => borderline,
+            None => return,
+        };
+
+        let score = scores.get(&category);
+        println!(
+            "{} {}: {} {} {} {}",
+            "QUEUED FOR REVIEW".bright_yellow().bold().underline(),
+            message.sender,
+            category,
+            score,
+            "USER TEXT".bright_purple().bold().underline(),
+            message.text
+        );
+        let flagged_message = FlaggedMessage::new(
+            &message.sender,
+            &message.user_id,
+            &message.id,
+            &message.text,
+            category,
+            score,
+        );
+        if let Err(e) = self.review.push(
+            &message.channel,
+            &message.room_id,
+            flagged_message,
+            scores.clone(),
+            Some(rule.threshold),
+            rule.punishment(),
+        ) {
+            eprintln!("Error Queueing For Review: {e}");
+        }
+    }
+
+    /// Carries out the punishment for a flagged message and records the
+    /// decision in the audit log. With `strike` set the punishment is raised
+    /// by the sender's strikes. In shadow mode the decision is only recorded.
+    async fn enforce(
+        &mut self,
+        message: &TwitchMessage,
+        flagged_message: FlaggedMessage,
+        scores: ModerationScores,
+        threshold: Option<f64>,
+        punishment: PunishmentAction,
+        strike: bool,
+    ) {
+        let shadow = self
+            .channel(&message.channel)
+            .is_some_and(|context| context.settings.shadow_mode);
+        let action = if strike {
+            self.escalate(message, &flagged_message, punishment, shadow)
+        } else {
+            punishment
+        };
+
+        let result = if action == PunishmentAction::None {
+            ActionResult::NoAction
+        } else if shadow {
+            println!("{}: {:?}", "Shadow Action".bright_cyan().bold(), action);
+            ActionResult::Shadow
+        } else {
+            match self.helix() {
+                None => {
+                    println!(
+                        "{}",
+                        "NO MODERATOR CREDENTIALS, CANNOT PUNISH"
+                            .bright_red()
+                            .bold()
+                    );
+                    ActionResult::Failed("No moderator credentials".to_string())
                 }
-                if let Some(command) = self.command_handler.get_command(&message.text) {
-             
-                    let response = command.execute(&message);
-                    if let Err(e) = self.api.send_message(&response) {
-                        eprintln!("Error sending message: {:?}", e);
+                Some(helix) => {
+                    match execute_punishment(&helix, &message.room_id, &flagged_message, action)
+                        .await
+                    {
+                        Ok(()) => {
+                            println!("{}: {:?}", "Action Taken".bright_cyan().bold(), action);
+                            ActionResult::Applied
+                        }
+                        Err(e) => {
+                            eprintln!("Error Punishing User: {e}");
+                            ActionResult::Failed(e.to_string())
+                        }
                     }
-                } else {
-                    ()
                 }
             }
+        };
+
+        let entry = AuditEntry::new(
+            &message.channel,
+            DecisionOrigin::Automatic,
+            flagged_message,
+            scores,
+            threshold,
+            action,
+            result,
+        );
+        if let Err(e) = self.audit.record(entry) {
+            eprintln!("Error Writing Audit Log: {e}");
+        }
+    }
+
+    /// Runs the message through the channel's spam filters. Returns `false`
+    /// when one of them caught it, after punishing the sender. Spam earns
+    /// the filter's own punishment and no strike, so a chatty user is not
+    /// walked up the ladder to a ban.
+    async fn passes_spam_filters(&mut self, message: &TwitchMessage) -> bool {
+        let verdict = match self.channels.get_mut(&message.channel) {
+            Some(context) => context
+                .settings
+                .spam_filters
+                .check(message, &mut context.spam),
+            None => return true,
+        };
+        let verdict = match verdict {
+            Some(verdict) => verdict,
+            None => return true,
+        };
+
+        let category = verdict.kind.category();
+        println!(
+            "{} {}: {} {} {} {}",
+            "SPAM".red().bold().underline(),
+            message.sender,
+            category,
+            verdict.score,
+            "USER TEXT".bright_purple().bold().underline(),
+            message.text
+        );
+
+        let flagged_message = FlaggedMessage::new(
+            &message.sender,
+            &message.user_id,
+            &message.id,
+            &message.text,
+            ModerationCategory::from(category),
+            verdict.score,
+        );
+        self.enforce(
+            message,
+            flagged_message,
+            ModerationScores::default(),
+            Some(verdict.threshold),
+            verdict.punishment,
+            false,
+        )
+        .await;
+        false
+    }
+
+    /// Checks the message against the channel's blocked terms. Returns
+    /// `false` when it contains one, after punishing the sender.
+    async fn passes_blocked_terms(
+        &mut self,
+        message: &TwitchMessage,
+        normalized: &NormalizedText,
+    ) -> bool {
+        let term = match self
+            .blocked_terms
+            .find_normalized(&message.channel, normalized)
+        {
+            Some(term) => term,
+            None => return true,
+        };
+
+        println!(
+            "{} {}: {} {} {}",
+            "BLOCKED TERM".red().bold().underline(),
+            message.sender,
+            term.pattern,
+            "USER TEXT".bright_purple().bold().underline(),
+            message.text
+        );
+
+        let flagged_message = FlaggedMessage::new(
+            &message.sender,
+            &message.user_id,
+            &message.id,
+            &message.text,
+            ModerationCategory::from(BLOCKED_TERM_CATEGORY),
+            1.0,
+        );
+        self.enforce(
+            message,
+            flagged_message,
+            ModerationScores::default(),
+            None,
+            term.punishment.punishment(),
+            true,
+        )
+        .await;
+        false
+    }
+
+    /// Records bans, timeouts and deletions in shadow mode channels as
+    /// manual decisions, to compare against the shadow decisions. The bot
+    /// takes no actions there, so every one of them was made by a human.
+    fn audit_manual_action(&self, event: &ChatEvent) {
+        // Moderators give no category for their actions.
+        let category = ModerationCategory::from("");
+        let (channel, flagged, action) = match event {
+            ChatEvent::ClearChat(clear) => match &clear.action {
+                ClearChatAction::ClearAll => return,
+                ClearChatAction::Ban { login, user_id } => (
+                    &clear.channel,
+                    FlaggedMessage::new(login, user_id, "", "", category, 0.0),
+                    PunishmentAction::Ban,
+                ),
+                ClearChatAction::Timeout {
+                    login,
+                    user_id,
+                    duration,
+                } => (
+                    &clear.channel,
+                    FlaggedMessage::new(login, user_id, "", "", category, 0.0),
+                    PunishmentAction::Timeout(duration.as_secs()),
+                ),
+            },
+            ChatEvent::ClearMsg(clear) => (
+                &clear.channel,
+                FlaggedMessage::new(
+                    &clear.login,
+                    "",
+                    &clear.target_msg_id,
+                    &clear.text,
+                    category,
+                    0.0,
+                ),
+                PunishmentAction::Delete,
+            ),
+            _ => return,
+        };
+
+        if !self
+            .channel(channel)
+            .is_some_and(|context| context.settings.shadow_mode)
+        {
+            return;
+        }
+
+        let entry = AuditEntry::new(
+            channel,
+            DecisionOrigin::Manual,
+            flagged,
+            Default::default(),
+            None,
+            action,
+            ActionResult::Applied,
+        );
+        if let Err(e) = self.audit.record(entry) {
+            eprintln!("Error Writing Audit Log: {e}");
+        }
+    }
+
+    /// Adds a strike for a punished message and raises the punishment to
+    /// the ladder step for the user's strike count. Messages the policy lets
+    /// through do not count. In shadow mode the strike is only counted, not
+    /// recorded.
+    fn escalate(
+        &self,
+        message: &TwitchMessage,
+        flagged: &FlaggedMessage,
+        punishment: PunishmentAction,
+        shadow: bool,
+    ) -> PunishmentAction {
+        if punishment == PunishmentAction::None {
+            return punishment;
+        }
+        if shadow {
+            let count = self.strikes.active(&message.channel, &flagged.user_id) + 1;
+            return self.strikes.escalate(punishment, count);
+        }
+        match self.strikes.record(
+            &message.channel,
+            &flagged.user_id,
+            &flagged.username,
+            &flagged.category,
+        ) {
+            Ok(count) => {
+                println!(
+                    "{}: {} {}",
+                    "Strikes".bright_yellow().bold(),
+                    flagged.username,
+                    count
+                );
+                self.strikes.escalate(punishment, count)
+            }
             Err(e) => {
-                eprintln!("Error Handling Moderation: {e}");
+                eprintln!("Error Recording Strike: {e}");
+                punishment
             }
         }
     }
 
-    pub fn disconnect(&mut self) -> Result<(), TwitchError> {
-        self.api.disconnect()
+    /// Moderator commands: `!strikes <user>` shows a user's active strikes,
+    /// `!clearstrikes <user>` forgets them and `!permit <user>` lets them
+    /// post one link past the link filter. Returns whether the message was
+    /// one of them.
+    async fn handle_moderator_command(&mut self, message: &TwitchMessage) -> bool {
+        let mut words = message.text.split_whitespace();
+        let command = words.next().unwrap_or_default().to_lowercase();
+        if !["!strikes", "!clearstrikes", "!permit"].contains(&command.as_str()) {
+            return false;
+        }
+        if PermissionLevel::of(&message.badges) < PermissionLevel::Moderator {
+            return false;
+        }
+
+        let response = match words.next() {
+            None => format!("Usage: {} <user>", command),
+            Some(name) => {
+                let username = name.trim_start_matches('@').to_lowercase();
+                if command == "!permit" {
+                    self.permit(&message.channel, &username)
+                } else {
+                    self.strike_response(&message.channel, &command, &username)
+                }
+            }
+        };
+
+        if let Err(e) = self
+            .connection
+            .api_mut()
+            .deliver(
+                &message.channel,
+                &response,
+                DeliveryMode::Reply,
+                Some(&message.id),
+            )
+            .await
+        {
+            eprintln!("Error sending message: {:?}", e);
+        }
+        true
+    }
+
+    fn strike_response(&self, channel: &str, command: &str, username: &str) -> String {
+        match self.strikes.find_user(channel, username) {
+            None => format!("{} has no strikes", username),
+            Some(user_id) if command == "!clearstrikes" => {
+                match self.strikes.clear(channel, &user_id) {
+                    Ok(cleared) => format!("Cleared {} strikes for {}", cleared, username),
+                    Err(e) => format!("Could not clear strikes: {}", e),
+                }
+            }
+            Some(user_id) => format!(
+                "{} has {} active strikes",
+                username,
+                self.strikes.active(channel, &user_id)
+            ),
+        }
+    }
+
+    fn permit(&mut self, channel: &str, username: &str) -> String {
+        let context = match self.channels.get_mut(channel) {
+            Some(context) => context,
+            None => return format!("Could not permit {}", username),
+        };
+        let permit_secs = context.settings.spam_filters.links.permit_secs;
+        let window = match chrono::TimeDelta::try_seconds(permit_secs as i64) {
+            Some(window) => window,
+            None => return format!("Could not permit {}", username),
+        };
+        context.spam.permit(username, Utc::now() + window);
+        format!(
+            "{} may post a link in the next {} seconds",
+            username, permit_secs
+        )
+    }
+
+    /// The client punishments go through, built from the moderator account
+    /// and `TWITCH_CLIENT_ID` unless one was set.
+    fn helix(&mut self) -> Option<HelixClient> {
+        if self.helix.is_none() {
+            let moderator = self.accounts.moderator()?;
+            let client_id = std::env::var("TWITCH_CLIENT_ID").ok()?;
+            self.helix = Some(HelixClient::new(
+                &client_id,
+                moderator.access_token()?,
+                moderator.user_id()?,
+            ));
+        }
+        self.helix.clone()
+    }
+
+    async fn handle_command(&mut self, message: &TwitchMessage) {
+        let context = match self.channels.get(&message.channel) {
+            Some(context) if context.settings.commands_enabled => context,
+            _ => return,
+        };
+
+        let command = match context.command_handler.get_command(&message.text) {
+            Some(command) => command,
+            None => return,
+        };
+
+        if !context.settings.can_use(&command.get_name(), &message.badges) {
+            println!(
+                "{}: {} !{}",
+                "Command Not Permitted".bright_yellow().bold(),
+                message.sender,
+                command.get_name()
+            );
+            return;
+        }
+
+        let response = command.execute(message);
+        let mode = command.delivery_mode();
+        if let Err(e) = self
+            .connection
+            .api_mut()
+            .deliver(&message.channel, &response, mode, Some(&message.id))
+            .await
+        {
+            eprintln!("Error sending message: {:?}", e);
+        }
+    }
+
+    pub async fn disconnect(&mut self) -> Result<(), TwitchError> {
+        self.connection.disconnect().await
     }
 }
 
+fn new_channel_context(name: &str, settings: ChannelSettings) -> ChannelContext {
+    ChannelContext::new(name, settings, || match get_custom_commands() {
+        Ok(command) => command,
+        Err(e) => {
+            println!("Error Getting Commands {e}");
+            vec![]
+        }
+    })
+}
+
 fn get_custom_commands() -> Result<Vec<CustomCommand>, Box<dyn std::error::Error>> {
     Ok(vec![CustomCommand {
         name: "hello".to_string(),
         action: "!hello".to_string(),
         callback: Box::new(|message| format!("Hello from Rust! {}", message.text)),
+        delivery_mode: DeliveryMode::Reply,
     }])
 }
-
-
-fn determine_offence(categories: Vec<String>) -> Option<String> {
-    let severities: HashMap<&str, i32> = HashMap::from([
-        ("sexual_minors", 1),
-        ("hate", 2),
-        ("self_harm", 3),
-        ("self_harm_intent", 4),
-        ("hate_threatening", 5),
-        ("self_harm_instructions", 6),
-        ("harassment_threatening", 7),
-        ("sexual", 8),
-        ("violence_graphic", 9),
-        ("violence", 10),
-        ("harassment", 11),
-    ]);
-
-    let cat = categories
-        .into_iter()
-        .filter_map(|category| severities.get(&category.as_str()).copied())
-        .min()
-        .map(|severity| {
-            severities
-                .iter()
-                .find(|&(_, s)| *s == severity)
-                .map(|(category, _)| category.to_string())
-                .unwrap_or_else(|| "No Offence Found".to_string())
-        })
-        .unwrap_or_else(|| "No Offence Found".to_string());
-
-    Some(cat)
-}
\ No newline at end of file
@@ -0,0 +1,118 @@
+//! Per-channel state for a bot that sits in several channels over one
+//! connection. Every joined channel gets its own command set, moderation
+//! switch and command permissions.
+
+use super::commands::{CommandHandler, CustomCommand};
+use crate::moderation::exemptions::ExemptionRules;
+use crate::moderation::policy::SeverityOrder;
+use crate::moderation::spam::{SpamFilters, SpamTracker};
+use super::tags::Badges;
+use std::collections::HashMap;
+
+/// Who may use something in a channel, from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum PermissionLevel {
+    #[default]
+    Everyone,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+}
+
+impl PermissionLevel {
+    /// The highest level the badges grant.
+    pub fn of(badges: &Badges) -> Self {
+        if badges.broadcaster {
+            PermissionLevel::Broadcaster
+        } else if badges.moderator {
+            PermissionLevel::Moderator
+        } else if badges.vip {
+            PermissionLevel::Vip
+        } else if badges.is_subscriber() {
+            PermissionLevel::Subscriber
+        } else {
+            PermissionLevel::Everyone
+        }
+    }
+}
+
+/// Settings that differ from channel to channel.
+#[derive(Debug, Clone)]
+pub struct ChannelSettings {
+    /// Whether chat messages are moderated in this channel.
+    pub moderation_enabled: bool,
+    /// Whether moderation only records what it would do, so a policy can be
+    /// trialled without punishing anyone.
+    pub shadow_mode: bool,
+    /// Chatters whose messages are not moderated.
+    pub exemptions: ExemptionRules,
+    /// Local filters for caps, symbols, emotes, repetition, length and
+    /// links, run before the moderation provider.
+    pub spam_filters: SpamFilters,
+    /// How far below a category's threshold a score may be for the message
+    /// to be queued for a moderator to review. Zero turns the review queue
+    /// off.
+    pub review_margin: f64,
+    /// Which category a message flagged in several is recorded under when
+    /// they earn the same punishment.
+    pub severity_order: SeverityOrder,
+    /// Whether commands are answered in this channel.
+    pub commands_enabled: bool,
+    /// The level required for commands not listed in `command_permissions`.
+    pub default_permission: PermissionLevel,
+    /// Required level per command name (without the `!`).
+    pub command_permissions: HashMap<String, PermissionLevel>,
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        ChannelSettings {
+            moderation_enabled: true,
+            shadow_mode: false,
+            exemptions: ExemptionRules::default(),
+            spam_filters: SpamFilters::default(),
+            review_margin: 0.1,
+            severity_order: SeverityOrder::default(),
+            commands_enabled: true,
+            default_permission: PermissionLevel::Everyone,
+            command_permissions: HashMap::new(),
+        }
+    }
+}
+
+impl ChannelSettings {
+    pub fn required_permission(&self, command_name: &str) -> PermissionLevel {
+        self.command_permissions
+            .get(command_name)
+            .copied()
+            .unwrap_or(self.default_permission)
+    }
+
+    pub fn can_use(&self, command_name: &str, badges: &Badges) -> bool {
+        PermissionLevel::of(badges) >= self.required_permission(command_name)
+    }
+}
+
+/// Everything the bot keeps for one joined channel.
+pub struct ChannelContext {
+    pub name: String,
+    pub command_handler: CommandHandler,
+    pub settings: ChannelSettings,
+    /// Recent messages and link permits for the spam filters.
+    pub spam: SpamTracker,
+}
+
+impl ChannelContext {
+    pub fn new<F>(name: &str, settings: ChannelSettings, get_custom_commands: F) -> Self
+    where
+        F: FnOnce() -> Vec<CustomCommand> + Send + 'static,
+    {
+        ChannelContext {
+            name: name.to_string(),
+            command_handler: CommandHandler::new(get_custom_commands),
+            settings,
+            spam: SpamTracker::new(),
+        }
+    }
+}
@@ -0,0 +1,473 @@
+//! Typed events for every command the Twitch chat server sends once the
+//! `twitch.tv/tags`, `twitch.tv/commands` and `twitch.tv/membership`
+//! capabilities are granted.
+
+use super::irc_message::IrcMessage;
+use super::tags::{parse_timestamp, Badges};
+use super::twitch_api::{TwitchError, TwitchMessage};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub enum ChatEvent {
+    /// A regular chat message (PRIVMSG).
+    Message(TwitchMessage),
+    ClearChat(ClearChat),
+    ClearMsg(ClearMsg),
+    UserNotice(UserNotice),
+    RoomState(RoomState),
+    UserState(UserState),
+    GlobalUserState(GlobalUserState),
+    Notice(Notice),
+    Whisper(Whisper),
+    Join(Membership),
+    Part(Membership),
+    HostTarget(HostTarget),
+    /// The server is about to restart and the bot should reconnect.
+    Reconnect,
+}
+
+impl ChatEvent {
+    /// The channel the event happened in, if it is tied to one.
+    pub fn channel(&self) -> Option<&str> {
+        match self {
+            ChatEvent::Message(message) => Some(&message.channel),
+            ChatEvent::ClearChat(clear) => Some(&clear.channel),
+            ChatEvent::ClearMsg(clear) => Some(&clear.channel),
+            ChatEvent::UserNotice(notice) => Some(&notice.channel),
+            ChatEvent::RoomState(state) => Some(&state.channel),
+            ChatEvent::UserState(state) => Some(&state.channel),
+            ChatEvent::Notice(notice) => notice.channel.as_deref(),
+            ChatEvent::Join(member) | ChatEvent::Part(member) => Some(&member.channel),
+            ChatEvent::HostTarget(host) => Some(&host.channel),
+            ChatEvent::GlobalUserState(_) | ChatEvent::Whisper(_) | ChatEvent::Reconnect => None,
+        }
+    }
+
+    /// Converts a parsed line into an event.
+    ///
+    /// Returns `Ok(None)` for lines with no event of their own, such as
+    /// numerics, `CAP` acknowledgements and `PING`.
+    pub fn from_irc(irc: &IrcMessage) -> Result<Option<ChatEvent>, TwitchError> {
+        let event = match irc.command.as_str() {
+            "PRIVMSG" => ChatEvent::Message(TwitchMessage::try_from(irc)?),
+            "CLEARCHAT" => ChatEvent::ClearChat(ClearChat::from_irc(irc)?),
+            "CLEARMSG" => ChatEvent::ClearMsg(ClearMsg::from_irc(irc)?),
+            "USERNOTICE" => ChatEvent::UserNotice(UserNotice::from_irc(irc)?),
+            "ROOMSTATE" => ChatEvent::RoomState(RoomState::from_irc(irc)?),
+            "USERSTATE" => ChatEvent::UserState(UserState::from_irc(irc)?),
+            "GLOBALUSERSTATE" => ChatEvent::GlobalUserState(GlobalUserState::from_irc(irc)),
+            "NOTICE" => ChatEvent::Notice(Notice::from_irc(irc)),
+            "WHISPER" => ChatEvent::Whisper(Whisper::from_irc(irc)?),
+            "JOIN" => ChatEvent::Join(Membership::from_irc(irc)?),
+            "PART" => ChatEvent::Part(Membership::from_irc(irc)?),
+            "HOSTTARGET" => ChatEvent::HostTarget(HostTarget::from_irc(irc)?),
+            "RECONNECT" => ChatEvent::Reconnect,
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+}
+
+/// Reads the `#channel` parameter every channel-scoped command carries.
+fn channel_param(irc: &IrcMessage) -> Result<String, TwitchError> {
+    irc.param(0)
+        .and_then(|c| c.strip_prefix('#'))
+        .map(String::from)
+        .ok_or(TwitchError::MessageParseError)
+}
+
+fn tag_string(irc: &IrcMessage, key: &str) -> String {
+    irc.tag(key).unwrap_or_default().to_string()
+}
+
+fn tag_number<T: std::str::FromStr>(irc: &IrcMessage, key: &str) -> Option<T> {
+    irc.tag(key).and_then(|v| v.parse().ok())
+}
+
+fn tag_flag(irc: &IrcMessage, key: &str) -> Option<bool> {
+    irc.tag(key).map(|v| v == "1")
+}
+
+/// What a CLEARCHAT did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClearChatAction {
+    /// All messages in the channel were cleared.
+    ClearAll,
+    Ban { login: String, user_id: String },
+    Timeout {
+        login: String,
+        user_id: String,
+        duration: Duration,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ClearChat {
+    pub channel: String,
+    pub action: ClearChatAction,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl ClearChat {
+    fn from_irc(irc: &IrcMessage) -> Result<Self, TwitchError> {
+        let action = match irc.trailing() {
+            None => ClearChatAction::ClearAll,
+            Some(login) => {
+                let login = login.to_string();
+                let user_id = tag_string(irc, "target-user-id");
+                match tag_number::<u64>(irc, "ban-duration") {
+                    Some(seconds) => ClearChatAction::Timeout {
+                        login,
+                        user_id,
+                        duration: Duration::from_secs(seconds),
+                    },
+                    None => ClearChatAction::Ban { login, user_id },
+                }
+            }
+        };
+
+        Ok(ClearChat {
+            channel: channel_param(irc)?,
+            action,
+            sent_at: parse_timestamp(irc.tag("tmi-sent-ts").unwrap_or_default()),
+        })
+    }
+}
+
+/// A single message removed by a moderator.
+#[derive(Debug, Clone)]
+pub struct ClearMsg {
+    pub channel: String,
+    pub login: String,
+    pub target_msg_id: String,
+    pub text: String,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl ClearMsg {
+    fn from_irc(irc: &IrcMessage) -> Result<Self, TwitchError> {
+        Ok(ClearMsg {
+            channel: channel_param(irc)?,
+            login: tag_string(irc, "login"),
+            target_msg_id: tag_string(irc, "target-msg-id"),
+            text: irc.trailing().unwrap_or_default().to_string(),
+            sent_at: parse_timestamp(irc.tag("tmi-sent-ts").unwrap_or_default()),
+        })
+    }
+}
+
+/// Subscription tiers, from cheapest to most expensive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SubTier {
+    Prime,
+    Tier1,
+    Tier2,
+    Tier3,
+}
+
+impl SubTier {
+    fn from_plan(plan: &str) -> Self {
+        match plan {
+            "Prime" => SubTier::Prime,
+            "2000" => SubTier::Tier2,
+            "3000" => SubTier::Tier3,
+            _ => SubTier::Tier1,
+        }
+    }
+}
+
+/// A new subscription, resubscription or gifted subscription.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubEvent {
+    pub tier: SubTier,
+    /// Cumulative months subscribed, including this one.
+    pub months: u32,
+    /// The current streak, if the subscriber chose to share it.
+    pub streak_months: Option<u32>,
+    /// The login of whoever received the subscription.
+    pub subscriber: String,
+    /// The login of the gifter for gifted subscriptions.
+    pub gifted_by: Option<String>,
+}
+
+/// Several subscriptions gifted to random chatters at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GiftBombEvent {
+    pub tier: SubTier,
+    pub count: u32,
+    pub gifted_by: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RaidEvent {
+    pub from_login: String,
+    pub from_display_name: String,
+    pub viewers: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserNoticeKind {
+    Sub(SubEvent),
+    Resub(SubEvent),
+    SubGift(SubEvent),
+    GiftBomb(GiftBombEvent),
+    Raid(RaidEvent),
+    Announcement { color: String },
+    /// Any other `msg-id`, kept as is.
+    Other(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct UserNotice {
+    pub channel: String,
+    pub kind: UserNoticeKind,
+    pub id: String,
+    pub login: String,
+    pub user_id: String,
+    pub display_name: String,
+    pub badges: Badges,
+    /// The text Twitch shows for the event, e.g. "x subscribed for 6 months!".
+    pub system_msg: String,
+    /// The message the user attached, if any.
+    pub text: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+impl UserNotice {
+    fn from_irc(irc: &IrcMessage) -> Result<Self, TwitchError> {
+        let login = tag_string(irc, "login");
+        let msg_id = tag_string(irc, "msg-id");
+        let tier = SubTier::from_plan(irc.tag("msg-param-sub-plan").unwrap_or_default());
+
+        let sub_event = |subscriber: String, gifted_by: Option<String>| SubEvent {
+            tier,
+            months: tag_number(irc, "msg-param-cumulative-months")
+                .or_else(|| tag_number(irc, "msg-param-months"))
+                .unwrap_or(1),
+            streak_months: tag_number(irc, "msg-param-streak-months").filter(|m| *m > 0),
+            subscriber,
+            gifted_by,
+        };
+
+        let kind = match msg_id.as_str() {
+            "sub" => UserNoticeKind::Sub(sub_event(login.clone(), None)),
+            "resub" => UserNoticeKind::Resub(sub_event(login.clone(), None)),
+            "subgift" | "anonsubgift" => {
+                let recipient = tag_string(irc, "msg-param-recipient-user-name");
+                let gifter = Some(login.clone()).filter(|_| msg_id == "subgift");
+                UserNoticeKind::SubGift(sub_event(recipient, gifter))
+            }
+            "submysterygift" => UserNoticeKind::GiftBomb(GiftBombEvent {
+                tier,
+                count: tag_number(irc, "msg-param-mass-gift-count").unwrap_or(1),
+                gifted_by: login.clone(),
+            }),
+            "raid" => UserNoticeKind::Raid(RaidEvent {
+                from_login: tag_string(irc, "msg-param-login"),
+                from_display_name: tag_string(irc, "msg-param-displayName"),
+                viewers: tag_number(irc, "msg-param-viewerCount").unwrap_or(0),
+            }),
+            "announcement" => UserNoticeKind::Announcement {
+                color: tag_string(irc, "msg-param-color"),
+            },
+            other => UserNoticeKind::Other(other.to_string()),
+        };
+
+        Ok(UserNotice {
+            channel: channel_param(irc)?,
+            kind,
+            id: tag_string(irc, "id"),
+            login,
+            user_id: tag_string(irc, "user-id"),
+            display_name: tag_string(irc, "display-name"),
+            badges: Badges::parse(
+                irc.tag("badges").unwrap_or_default(),
+                irc.tag("badge-info").unwrap_or_default(),
+            ),
+            system_msg: tag_string(irc, "system-msg"),
+            text: irc.trailing().map(String::from),
+            sent_at: parse_timestamp(irc.tag("tmi-sent-ts").unwrap_or_default()),
+        })
+    }
+}
+
+/// Chat settings of a channel. Twitch sends every field on join and only
+/// the changed ones afterwards, so unset fields mean "unchanged".
+#[derive(Debug, Clone, Default)]
+pub struct RoomState {
+    pub channel: String,
+    pub room_id: String,
+    pub emote_only: Option<bool>,
+    /// Minutes a chatter must follow before talking; `-1` when disabled.
+    pub followers_only: Option<i64>,
+    pub r9k: Option<bool>,
+    /// Seconds between messages from the same chatter.
+    pub slow: Option<u64>,
+    pub subs_only: Option<bool>,
+}
+
+impl RoomState {
+    fn from_irc(irc: &IrcMessage) -> Result<Self, TwitchError> {
+        Ok(RoomState {
+            channel: channel_param(irc)?,
+            room_id: tag_string(irc, "room-id"),
+            emote_only: tag_flag(irc, "emote-only"),
+            followers_only: tag_number(irc, "followers-only"),
+            r9k: tag_flag(irc, "r9k"),
+            slow: tag_number(irc, "slow"),
+            subs_only: tag_flag(irc, "subs-only"),
+        })
+    }
+}
+
+/// The bot's own state in a channel, sent on join and after it chats.
+#[derive(Debug, Clone, Default)]
+pub struct UserState {
+    pub channel: String,
+    pub display_name: String,
+    pub color: Option<String>,
+    pub badges: Badges,
+    /// Whether the bot is a moderator in the channel.
+    pub moderator: bool,
+    pub emote_sets: Vec<String>,
+}
+
+impl UserState {
+    fn from_irc(irc: &IrcMessage) -> Result<Self, TwitchError> {
+        let badges = Badges::parse(
+            irc.tag("badges").unwrap_or_default(),
+            irc.tag("badge-info").unwrap_or_default(),
+        );
+        Ok(UserState {
+            channel: channel_param(irc)?,
+            display_name: tag_string(irc, "display-name"),
+            color: irc.tag("color").filter(|c| !c.is_empty()).map(String::from),
+            moderator: tag_flag(irc, "mod").unwrap_or(false) || badges.moderator,
+            badges,
+            emote_sets: split_emote_sets(irc),
+        })
+    }
+
+    /// Whether the bot has moderator-level chat limits in this channel.
+    pub fn is_elevated(&self) -> bool {
+        self.moderator || self.badges.broadcaster
+    }
+}
+
+/// The bot's account-wide state, sent once after authenticating.
+#[derive(Debug, Clone, Default)]
+pub struct GlobalUserState {
+    pub user_id: String,
+    pub display_name: String,
+    pub color: Option<String>,
+    pub badges: Badges,
+    pub emote_sets: Vec<String>,
+}
+
+impl GlobalUserState {
+    fn from_irc(irc: &IrcMessage) -> Self {
+        GlobalUserState {
+            user_id: tag_string(irc, "user-id"),
+            display_name: tag_string(irc, "display-name"),
+            color: irc.tag("color").filter(|c| !c.is_empty()).map(String::from),
+            badges: Badges::parse(
+                irc.tag("badges").unwrap_or_default(),
+                irc.tag("badge-info").unwrap_or_default(),
+            ),
+            emote_sets: split_emote_sets(irc),
+        }
+    }
+}
+
+fn split_emote_sets(irc: &IrcMessage) -> Vec<String> {
+    irc.tag("emote-sets")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// A server notice, e.g. the result of a command or a failed login.
+#[derive(Debug, Clone)]
+pub struct Notice {
+    /// `None` for notices not tied to a channel (`NOTICE *`).
+    pub channel: Option<String>,
+    pub msg_id: Option<String>,
+    pub text: String,
+}
+
+impl Notice {
+    fn from_irc(irc: &IrcMessage) -> Self {
+        Notice {
+            channel: channel_param(irc).ok(),
+            msg_id: irc.tag("msg-id").map(String::from),
+            text: irc.trailing().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Whisper {
+    pub from_login: String,
+    pub from_user_id: String,
+    pub display_name: String,
+    pub to_login: String,
+    pub message_id: String,
+    pub thread_id: String,
+    pub badges: Badges,
+    pub text: String,
+}
+
+impl Whisper {
+    fn from_irc(irc: &IrcMessage) -> Result<Self, TwitchError> {
+        Ok(Whisper {
+            from_login: irc.nick().ok_or(TwitchError::MessageParseError)?.to_string(),
+            from_user_id: tag_string(irc, "user-id"),
+            display_name: tag_string(irc, "display-name"),
+            to_login: irc.param(0).unwrap_or_default().to_string(),
+            message_id: tag_string(irc, "message-id"),
+            thread_id: tag_string(irc, "thread-id"),
+            badges: Badges::parse(irc.tag("badges").unwrap_or_default(), ""),
+            text: irc.trailing().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// A chatter joining or leaving a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Membership {
+    pub channel: String,
+    pub login: String,
+}
+
+impl Membership {
+    fn from_irc(irc: &IrcMessage) -> Result<Self, TwitchError> {
+        Ok(Membership {
+            channel: channel_param(irc)?,
+            login: irc.nick().ok_or(TwitchError::MessageParseError)?.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostTarget {
+    pub channel: String,
+    /// The hosted channel, `None` when hosting stopped.
+    pub target: Option<String>,
+    pub viewers: Option<u64>,
+}
+
+impl HostTarget {
+    fn from_irc(irc: &IrcMessage) -> Result<Self, TwitchError> {
+        let mut parts = irc.trailing().unwrap_or_default().split(' ');
+        let target = parts.next().filter(|t| *t != "-" && !t.is_empty());
+        Ok(HostTarget {
+            channel: channel_param(irc)?,
+            target: target.map(String::from),
+            viewers: parts.next().and_then(|v| v.parse().ok()),
+        })
+    }
+}
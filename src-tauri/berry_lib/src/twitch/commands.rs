@@ -1,9 +1,16 @@
+use super::outgoing::DeliveryMode;
 use super::twitch_api::TwitchMessage;
 
 pub trait Command: Send {
     fn execute(&self, message: &TwitchMessage) -> String;
     fn get_name(&self) -> String;
     fn get_action(&self) -> String;
+
+    /// How the response is posted. Replies to the triggering message unless
+    /// the command says otherwise.
+    fn delivery_mode(&self) -> DeliveryMode {
+        DeliveryMode::Reply
+    }
 }
 
 pub struct PingCommand;
@@ -42,6 +49,7 @@ pub struct CustomCommand {
     pub name: String,
     pub action: String,
     pub callback: Box<dyn Fn(&TwitchMessage) -> String + Send>,
+    pub delivery_mode: DeliveryMode,
 }
 
 impl Command for CustomCommand {
@@ -56,6 +64,10 @@ impl Command for CustomCommand {
     fn get_action(&self) -> String {
         self.action.clone()
     }
+
+    fn delivery_mode(&self) -> DeliveryMode {
+        self.delivery_mode
+    }
 }
 
 pub struct CommandHandler {
@@ -79,12 +91,12 @@ impl CommandHandler {
 
     pub fn get_command(&self, message: &str) -> Option<&dyn Command> {
 
-        if message.starts_with('!') {
-            let command_name = &message[1..].to_lowercase();
+        if let Some(command_name) = message.strip_prefix('!') {
+            let command_name = command_name.to_lowercase();
 
             for command in &self.builtin_commands {
     
-                if command.get_name() == command_name.to_string() {
+                if command.get_name() == command_name {
      
                     return Some(command.as_ref());
                 }
@@ -92,14 +104,12 @@ impl CommandHandler {
 
             for command in &self.custom_commands {
 
-                if command.get_name() == command_name.to_string() {
+                if command.get_name() == command_name {
 
                     return Some(command);
                 }
             }
 
-        } else {
-            ()
         }
 
         None
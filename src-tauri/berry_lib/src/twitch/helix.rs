@@ -0,0 +1,298 @@
+//! A small client for the Helix moderation endpoints.
+//!
+//! Every call is made as the moderator the client was built for, so the
+//! token must belong to that account and carry the matching scopes.
+
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+const HELIX_URL: &str = "https://api.twitch.tv/helix";
+
+#[derive(Debug)]
+pub enum HelixError {
+    RequestError(reqwest::Error),
+    JsonError(serde_json::Error),
+    /// The token is invalid or has expired.
+    InvalidToken,
+    /// The token lacks a scope. Holds Twitch's message naming it.
+    MissingScope(String),
+    /// The account is not a moderator in the channel.
+    NotModerator,
+    /// The target cannot be punished, e.g. because they are a moderator or
+    /// the broadcaster. Holds Twitch's message.
+    TargetProtected(String),
+    RateLimited,
+    /// Any other error response.
+    Api {
+        status: u16,
+        message: String,
+    },
+}
+
+impl std::fmt::Display for HelixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HelixError::RequestError(e) => write!(f, "Request Error: {}", e),
+            HelixError::JsonError(e) => write!(f, "JSON Error: {}", e),
+            HelixError::InvalidToken => write!(f, "Invalid or expired token"),
+            HelixError::MissingScope(message) => write!(f, "Missing scope: {}", message),
+            HelixError::NotModerator => write!(f, "Not a moderator in this channel"),
+            HelixError::TargetProtected(message) => write!(f, "Target protected: {}", message),
+            HelixError::RateLimited => write!(f, "Rate limited"),
+            HelixError::Api { status, message } => write!(f, "API Error {}: {}", status, message),
+        }
+    }
+}
+
+impl From<reqwest::Error> for HelixError {
+    fn from(err: reqwest::Error) -> HelixError {
+        HelixError::RequestError(err)
+    }
+}
+
+impl From<serde_json::Error> for HelixError {
+    fn from(err: serde_json::Error) -> HelixError {
+        HelixError::JsonError(err)
+    }
+}
+
+/// The body Helix sends with error responses.
+#[derive(Deserialize, Debug, Default)]
+struct ErrorBody {
+    #[serde(default)]
+    message: String,
+}
+
+impl HelixError {
+    fn from_response(status: StatusCode, body: &str) -> Self {
+        let message = serde_json::from_str::<ErrorBody>(body)
+            .unwrap_or_default()
+            .message;
+        let lowercase = message.to_lowercase();
+
+        match status {
+            StatusCode::UNAUTHORIZED if lowercase.contains("scope") => {
+                HelixError::MissingScope(message)
+            }
+            StatusCode::UNAUTHORIZED => HelixError::InvalidToken,
+            StatusCode::FORBIDDEN => HelixError::NotModerator,
+            StatusCode::BAD_REQUEST
+                if lowercase.contains("may not be") || lowercase.contains("cannot") =>
+            {
+                HelixError::TargetProtected(message)
+            }
+            StatusCode::TOO_MANY_REQUESTS => HelixError::RateLimited,
+            _ => HelixError::Api {
+                status: status.as_u16(),
+                message,
+            },
+        }
+    }
+}
+
+/// A term on a channel's Twitch blocked-terms list.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HelixBlockedTerm {
+    pub id: String,
+    pub text: String,
+}
+
+/// One page of a list endpoint.
+#[derive(Deserialize, Debug)]
+struct Page<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    pagination: Pagination,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Pagination {
+    cursor: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HelixClient {
+    client: Client,
+    base_url: String,
+    client_id: String,
+    access_token: String,
+    /// The account the calls are made as.
+    moderator_id: String,
+}
+
+impl HelixClient {
+    pub fn new(client_id: &str, access_token: &str, moderator_id: &str) -> Self {
+        HelixClient {
+            client: Client::new(),
+            base_url: HELIX_URL.to_string(),
+            client_id: client_id.to_string(),
+            access_token: access_token.to_string(),
+            moderator_id: moderator_id.to_string(),
+        }
+    }
+
+    /// Sends requests somewhere other than Twitch, e.g. a local mock.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn moderator_id(&self) -> &str {
+        &self.moderator_id
+    }
+
+    /// Bans a user from the channel.
+    pub async fn ban_user(
+        &self,
+        broadcaster_id: &str,
+        user_id: &str,
+        reason: &str,
+    ) -> Result<(), HelixError> {
+        let body = json!({ "data": { "user_id": user_id, "reason": reason } });
+        self.send(
+            self.moderation(Method::POST, "/moderation/bans", broadcaster_id)
+                .json(&body),
+        )
+        .await
+    }
+
+    /// Times a user out. Twitch accepts 1 second up to 2 weeks.
+    pub async fn timeout_user(
+        &self,
+        broadcaster_id: &str,
+        user_id: &str,
+        duration: Duration,
+        reason: &str,
+    ) -> Result<(), HelixError> {
+        let body = json!({
+            "data": {
+                "user_id": user_id,
+                "duration": duration.as_secs().max(1),
+                "reason": reason,
+            }
+        });
+        self.send(
+            self.moderation(Method::POST, "/moderation/bans", broadcaster_id)
+                .json(&body),
+        )
+        .await
+    }
+
+    /// Deletes a single chat message by its id.
+    pub async fn delete_message(
+        &self,
+        broadcaster_id: &str,
+        message_id: &str,
+    ) -> Result<(), HelixError> {
+        let request = self
+            .moderation(Method::DELETE, "/moderation/chat", broadcaster_id)
+            .query(&[("message_id", message_id)]);
+        self.send(request).await
+    }
+
+    /// Sends a warning the user has to acknowledge before chatting again.
+    pub async fn warn_user(
+        &self,
+        broadcaster_id: &str,
+        user_id: &str,
+        reason: &str,
+    ) -> Result<(), HelixError> {
+        let body = json!({ "data": { "user_id": user_id, "reason": reason } });
+        let request = self
+            .moderation(Method::POST, "/moderation/warnings", broadcaster_id)
+            .json(&body);
+        self.send(request).await
+    }
+
+    /// Every term on the channel's blocked-terms list, following pages.
+    pub async fn get_blocked_terms(
+        &self,
+        broadcaster_id: &str,
+    ) -> Result<Vec<HelixBlockedTerm>, HelixError> {
+        let mut terms = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut request = self
+                .moderation(Method::GET, "/moderation/blocked_terms", broadcaster_id)
+                .query(&[("first", "100")]);
+            if let Some(after) = &cursor {
+                request = request.query(&[("after", after)]);
+            }
+            let page: Page<HelixBlockedTerm> = self.fetch(request).await?;
+            terms.extend(page.data);
+            match page.pagination.cursor {
+                Some(next) if !next.is_empty() => cursor = Some(next),
+                _ => return Ok(terms),
+            }
+        }
+    }
+
+    /// Adds a term to the channel's blocked-terms list. Twitch treats `*`
+    /// as a wildcard.
+    pub async fn add_blocked_term(
+        &self,
+        broadcaster_id: &str,
+        text: &str,
+    ) -> Result<HelixBlockedTerm, HelixError> {
+        let request = self
+            .moderation(Method::POST, "/moderation/blocked_terms", broadcaster_id)
+            .json(&json!({ "text": text }));
+        let page: Page<HelixBlockedTerm> = self.fetch(request).await?;
+        page.data.into_iter().next().ok_or_else(|| HelixError::Api {
+            status: 200,
+            message: "No blocked term in the response".to_string(),
+        })
+    }
+
+    /// Removes a term from the channel's blocked-terms list by its id.
+    pub async fn remove_blocked_term(
+        &self,
+        broadcaster_id: &str,
+        id: &str,
+    ) -> Result<(), HelixError> {
+        let request = self
+            .moderation(Method::DELETE, "/moderation/blocked_terms", broadcaster_id)
+            .query(&[("id", id)]);
+        self.send(request).await
+    }
+
+    /// A request to a moderation endpoint with the broadcaster and
+    /// moderator ids filled in.
+    fn moderation(&self, method: Method, path: &str, broadcaster_id: &str) -> RequestBuilder {
+        self.request(method, path).query(&[
+            ("broadcaster_id", broadcaster_id),
+            ("moderator_id", &self.moderator_id),
+        ])
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.base_url, path))
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .header("Client-Id", &self.client_id)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<(), HelixError> {
+        let res = request.send().await?;
+        let status = res.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = res.text().await?;
+        Err(HelixError::from_response(status, &body))
+    }
+
+    /// Like `send`, but reads the response body as JSON.
+    async fn fetch<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, HelixError> {
+        let res = request.send().await?;
+        let status = res.status();
+        let body = res.text().await?;
+        if status.is_success() {
+            return Ok(serde_json::from_str(&body)?);
+        }
+        Err(HelixError::from_response(status, &body))
+    }
+}
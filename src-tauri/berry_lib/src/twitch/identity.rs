@@ -0,0 +1,187 @@
+//! Who the bot is logged into chat as.
+//!
+//! Twitch ignores the NICK of an OAuth login, but anonymous logins and
+//! everything that compares senders with the bot need the real login name.
+//! It is looked up by validating the access token. A separate bot account
+//! can log into chat while API calls keep using the broadcaster's token, and
+//! channels can be watched read-only with no credentials at all.
+
+use rand::Rng;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+
+const VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+
+#[derive(Debug)]
+pub enum IdentityError {
+    RequestError(reqwest::Error),
+    JsonError(serde_json::Error),
+    /// Twitch rejected the token as invalid or expired.
+    InvalidToken,
+}
+
+impl std::fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            IdentityError::RequestError(e) => write!(f, "Request Error: {}", e),
+            IdentityError::JsonError(e) => write!(f, "JSON Error: {}", e),
+            IdentityError::InvalidToken => write!(f, "Invalid or expired token"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for IdentityError {
+    fn from(err: reqwest::Error) -> IdentityError {
+        IdentityError::RequestError(err)
+    }
+}
+
+impl From<serde_json::Error> for IdentityError {
+    fn from(err: serde_json::Error) -> IdentityError {
+        IdentityError::JsonError(err)
+    }
+}
+
+/// The account behind an access token, as reported by token validation.
+#[derive(Deserialize, Debug, Clone)]
+pub struct TokenInfo {
+    pub client_id: String,
+    pub login: String,
+    pub user_id: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Seconds until the token expires.
+    pub expires_in: u64,
+}
+
+/// Asks Twitch which account an access token belongs to.
+pub async fn validate_token(
+    access_token: &str,
+    client: &Client,
+) -> Result<TokenInfo, IdentityError> {
+    let res = client
+        .get(VALIDATE_URL)
+        .header("Authorization", format!("OAuth {}", access_token))
+        .send()
+        .await?;
+
+    if res.status() == StatusCode::UNAUTHORIZED {
+        return Err(IdentityError::InvalidToken);
+    }
+
+    let body = res.text().await?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// The login used on the chat connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatIdentity {
+    Authenticated {
+        login: String,
+        user_id: String,
+        access_token: String,
+    },
+    /// A `justinfan` login. It can read chat but not send to it.
+    Anonymous { login: String },
+}
+
+impl ChatIdentity {
+    pub fn authenticated(login: &str, user_id: &str, access_token: &str) -> Self {
+        ChatIdentity::Authenticated {
+            login: login.to_lowercase(),
+            user_id: user_id.to_string(),
+            access_token: access_token.to_string(),
+        }
+    }
+
+    /// Validates the token and logs in as the account it belongs to.
+    pub async fn from_token(access_token: &str, client: &Client) -> Result<Self, IdentityError> {
+        let info = validate_token(access_token, client).await?;
+        Ok(ChatIdentity::authenticated(
+            &info.login,
+            &info.user_id,
+            access_token,
+        ))
+    }
+
+    /// A read-only login under a random `justinfan` name.
+    pub fn anonymous() -> Self {
+        let number: u32 = rand::thread_rng().gen_range(1000..100_000);
+        ChatIdentity::Anonymous {
+            login: format!("justinfan{}", number),
+        }
+    }
+
+    pub fn login(&self) -> &str {
+        match self {
+            ChatIdentity::Authenticated { login, .. } => login,
+            ChatIdentity::Anonymous { login } => login,
+        }
+    }
+
+    pub fn user_id(&self) -> Option<&str> {
+        match self {
+            ChatIdentity::Authenticated { user_id, .. } => Some(user_id),
+            ChatIdentity::Anonymous { .. } => None,
+        }
+    }
+
+    pub fn access_token(&self) -> Option<&str> {
+        match self {
+            ChatIdentity::Authenticated { access_token, .. } => Some(access_token),
+            ChatIdentity::Anonymous { .. } => None,
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, ChatIdentity::Anonymous { .. })
+    }
+}
+
+/// The accounts the bot runs with.
+///
+/// The broadcaster account owns the channel and is used for API calls. An
+/// optional bot account takes its place on the chat connection, so
+/// responses show up under the bot's name.
+#[derive(Debug, Clone)]
+pub struct BotAccounts {
+    pub broadcaster: ChatIdentity,
+    pub bot: Option<ChatIdentity>,
+}
+
+impl BotAccounts {
+    pub fn new(broadcaster: ChatIdentity) -> Self {
+        BotAccounts {
+            broadcaster,
+            bot: None,
+        }
+    }
+
+    /// Watches channels read-only without any credentials.
+    pub fn anonymous() -> Self {
+        BotAccounts::new(ChatIdentity::anonymous())
+    }
+
+    pub fn with_bot(mut self, bot: ChatIdentity) -> Self {
+        self.bot = Some(bot);
+        self
+    }
+
+    /// The account that logs into chat: the bot account if there is one.
+    pub fn chat(&self) -> &ChatIdentity {
+        self.bot.as_ref().unwrap_or(&self.broadcaster)
+    }
+
+    /// The account moderation actions are taken as: the broadcaster if
+    /// signed in, otherwise the bot account.
+    pub fn moderator(&self) -> Option<&ChatIdentity> {
+        std::iter::once(&self.broadcaster)
+            .chain(self.bot.as_ref())
+            .find(|identity| !identity.is_read_only())
+    }
+
+    /// The token for API calls, preferring the broadcaster's.
+    pub fn api_token(&self) -> Option<&str> {
+        self.moderator().and_then(|identity| identity.access_token())
+    }
+}
@@ -0,0 +1,166 @@
+//! A parser for the IRCv3 lines the Twitch chat server sends.
+//!
+//! Every line has the shape
+//! `[@tags] [:prefix] COMMAND [middle params...] [:trailing param]`.
+
+use super::twitch_api::TwitchError;
+use std::collections::HashMap;
+
+/// The source of a message, e.g. `nick!user@host` or a bare server name.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IrcPrefix {
+    pub nick: Option<String>,
+    pub user: Option<String>,
+    pub host: Option<String>,
+}
+
+impl IrcPrefix {
+    fn parse(raw: &str) -> Self {
+        let (rest, host) = match raw.split_once('@') {
+            Some((rest, host)) => (rest, Some(host.to_string())),
+            None => (raw, None),
+        };
+        let (nick, user) = match rest.split_once('!') {
+            Some((nick, user)) => (nick, Some(user.to_string())),
+            None => (rest, None),
+        };
+
+        // A prefix without `!` or `@` is either a server name or a bare nick.
+        if host.is_none() && user.is_none() && nick.contains('.') {
+            return IrcPrefix {
+                nick: None,
+                user: None,
+                host: Some(nick.to_string()),
+            };
+        }
+
+        IrcPrefix {
+            nick: Some(nick.to_string()).filter(|n| !n.is_empty()),
+            user,
+            host,
+        }
+    }
+}
+
+/// A single parsed IRC line.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IrcMessage {
+    /// IRCv3 tags with their values already unescaped. Tags sent without a
+    /// value map to an empty string.
+    pub tags: HashMap<String, String>,
+    pub prefix: Option<IrcPrefix>,
+    /// The command or three digit numeric, e.g. `PRIVMSG` or `001`.
+    pub command: String,
+    /// The middle parameters, without the trailing one.
+    pub params: Vec<String>,
+    /// The parameter introduced by ` :`, which may contain spaces.
+    pub trailing: Option<String>,
+}
+
+impl IrcMessage {
+    /// Parses one line, with or without the terminating `\r\n`.
+    pub fn parse(line: &str) -> Result<IrcMessage, TwitchError> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        let mut message = IrcMessage::default();
+
+        if let Some(stripped) = rest.strip_prefix('@') {
+            let (raw_tags, remainder) = stripped
+                .split_once(' ')
+                .ok_or(TwitchError::MessageParseError)?;
+            message.tags = parse_tags(raw_tags);
+            rest = remainder.trim_start_matches(' ');
+        }
+
+        if let Some(stripped) = rest.strip_prefix(':') {
+            let (raw_prefix, remainder) = stripped
+                .split_once(' ')
+                .ok_or(TwitchError::MessageParseError)?;
+            message.prefix = Some(IrcPrefix::parse(raw_prefix));
+            rest = remainder.trim_start_matches(' ');
+        }
+
+        let (command, mut rest) = match rest.split_once(' ') {
+            Some((command, remainder)) => (command, remainder),
+            None => (rest, ""),
+        };
+        if command.is_empty() {
+            return Err(TwitchError::MessageParseError);
+        }
+        message.command = command.to_string();
+
+        loop {
+            rest = rest.trim_start_matches(' ');
+            if rest.is_empty() {
+                break;
+            }
+            if let Some(trailing) = rest.strip_prefix(':') {
+                message.trailing = Some(trailing.to_string());
+                break;
+            }
+            match rest.split_once(' ') {
+                Some((param, remainder)) => {
+                    message.params.push(param.to_string());
+                    rest = remainder;
+                }
+                None => {
+                    message.params.push(rest.to_string());
+                    break;
+                }
+            }
+        }
+
+        Ok(message)
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    pub fn param(&self, index: usize) -> Option<&str> {
+        self.params.get(index).map(String::as_str)
+    }
+
+    pub fn trailing(&self) -> Option<&str> {
+        self.trailing.as_deref()
+    }
+
+    /// The nick from the prefix, if the message came from a user.
+    pub fn nick(&self) -> Option<&str> {
+        self.prefix.as_ref().and_then(|p| p.nick.as_deref())
+    }
+}
+
+fn parse_tags(raw: &str) -> HashMap<String, String> {
+    raw.split(';')
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| match tag.split_once('=') {
+            Some((key, value)) => (key.to_string(), unescape_tag_value(value)),
+            None => (tag.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// Reverses the IRCv3 tag value escaping (`\:` `\s` `\\` `\r` `\n`).
+pub fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('\\') => unescaped.push('\\'),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            // A lone trailing backslash is dropped.
+            None => {}
+        }
+    }
+
+    unescaped
+}
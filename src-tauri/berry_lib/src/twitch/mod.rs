@@ -1,5 +1,14 @@
 pub mod bot;
+pub mod channel;
+pub mod chat_event;
 pub mod commands;
+pub mod helix;
+pub mod identity;
+pub mod irc_message;
+pub mod outgoing;
+pub mod rate_limit;
+pub mod supervisor;
+pub mod tags;
 pub mod twitch_access_token;
 pub mod twitch_api;
 pub mod twitch_endpoint;
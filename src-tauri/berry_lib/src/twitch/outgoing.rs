@@ -0,0 +1,93 @@
+//! Formatting of outgoing chat messages: replies, `/me` actions and
+//! splitting of responses longer than Twitch's 500 character limit.
+
+/// The most characters Twitch accepts in one chat message.
+pub const MAX_MESSAGE_CHARS: usize = 500;
+
+/// How a response is posted in chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeliveryMode {
+    /// Threaded as a reply to the message that triggered it.
+    #[default]
+    Reply,
+    /// A plain chat message.
+    Say,
+    /// A `/me` action, shown in italics in the chatter's color.
+    Action,
+}
+
+/// Builds the raw PRIVMSG line for one message part.
+///
+/// `reply_to` is only used for `DeliveryMode::Reply`; without it the reply
+/// is sent as a plain message.
+pub fn format_privmsg(
+    channel: &str,
+    text: &str,
+    mode: DeliveryMode,
+    reply_to: Option<&str>,
+) -> String {
+    match (mode, reply_to) {
+        (DeliveryMode::Reply, Some(parent)) if !parent.is_empty() => format!(
+            "@reply-parent-msg-id={} PRIVMSG #{} :{}\r\n",
+            parent, channel, text
+        ),
+        (DeliveryMode::Action, _) => {
+            format!("PRIVMSG #{} :\u{1}ACTION {}\u{1}\r\n", channel, text)
+        }
+        _ => format!("PRIVMSG #{} :{}\r\n", channel, text),
+    }
+}
+
+/// Splits `text` into parts of at most `max_chars` characters, breaking on
+/// whitespace where possible. Words longer than a whole part are cut.
+///
+/// Line breaks are not allowed in a chat message, so they count as spaces.
+pub fn split_message(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for word in text.split_whitespace() {
+        let mut word = word;
+        let mut word_len = word.chars().count();
+
+        let needed = if current_len == 0 {
+            word_len
+        } else {
+            current_len + 1 + word_len
+        };
+        if needed <= max_chars {
+            if current_len > 0 {
+                current.push(' ');
+                current_len += 1;
+            }
+            current.push_str(word);
+            current_len += word_len;
+            continue;
+        }
+
+        if current_len > 0 {
+            parts.push(std::mem::take(&mut current));
+        }
+
+        while word_len > max_chars {
+            let cut = word
+                .char_indices()
+                .nth(max_chars)
+                .map(|(i, _)| i)
+                .unwrap_or(word.len());
+            parts.push(word[..cut].to_string());
+            word = &word[cut..];
+            word_len -= max_chars;
+        }
+
+        current.push_str(word);
+        current_len = word_len;
+    }
+
+    if current_len > 0 {
+        parts.push(current);
+    }
+    parts
+}
@@ -0,0 +1,281 @@
+//! Outbound throttling that keeps the bot inside Twitch's chat limits.
+//!
+//! Twitch counts messages per 30 seconds: 20 for a regular account, 100 in
+//! channels where the bot is a moderator or the broadcaster. JOINs have
+//! their own budget, and a message identical to one sent to the same
+//! channel within 30 seconds is rejected. Going over any of these can get
+//! the account globally muted, so `OutboundQueue` holds lines back until
+//! they fit and drops duplicates up front.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A source of the current time, swappable for tests.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+}
+
+impl MockClock {
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// The budgets enforced by `OutboundQueue`.
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    /// Messages per `message_window` when the bot has no elevated role.
+    pub user_messages: usize,
+    /// Messages per `message_window` in channels where the bot is a
+    /// moderator or the broadcaster.
+    pub elevated_messages: usize,
+    pub message_window: Duration,
+    /// JOINs per `join_window`.
+    pub joins: usize,
+    pub join_window: Duration,
+    /// Identical messages to the same channel within this window are dropped.
+    pub duplicate_window: Duration,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            user_messages: 20,
+            elevated_messages: 100,
+            message_window: Duration::from_secs(30),
+            joins: 20,
+            join_window: Duration::from_secs(10),
+            duplicate_window: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Timestamps of recent sends, forgetting those older than the window.
+#[derive(Debug)]
+struct SlidingWindow {
+    window: Duration,
+    sent: VecDeque<Instant>,
+}
+
+impl SlidingWindow {
+    fn new(window: Duration) -> Self {
+        SlidingWindow {
+            window,
+            sent: VecDeque::new(),
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) >= self.window)
+        {
+            self.sent.pop_front();
+        }
+    }
+
+    fn has_room(&self, limit: usize) -> bool {
+        self.sent.len() < limit
+    }
+
+    /// When a send would fit under `limit` again.
+    fn ready_at(&self, limit: usize, now: Instant) -> Instant {
+        if self.sent.len() < limit {
+            return now;
+        }
+        let blocking = self.sent[self.sent.len() - limit];
+        blocking + self.window
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.sent.push_back(now);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Message,
+    Join,
+}
+
+/// A raw IRC line waiting for its budget.
+#[derive(Debug, Clone)]
+pub struct OutboundLine {
+    pub kind: LineKind,
+    pub channel: String,
+    /// The full line including `\r\n`.
+    pub line: String,
+}
+
+pub struct OutboundQueue {
+    clock: Arc<dyn Clock>,
+    limits: RateLimits,
+    queued: VecDeque<OutboundLine>,
+    messages: SlidingWindow,
+    joins: SlidingWindow,
+    /// Channels where USERSTATE said the bot is a moderator or broadcaster.
+    elevated: HashSet<String>,
+    /// `(accepted at, channel, text)` for duplicate suppression.
+    recent: VecDeque<(Instant, String, String)>,
+}
+
+impl OutboundQueue {
+    pub fn new(limits: RateLimits, clock: Arc<dyn Clock>) -> Self {
+        OutboundQueue {
+            clock,
+            messages: SlidingWindow::new(limits.message_window),
+            joins: SlidingWindow::new(limits.join_window),
+            limits,
+            queued: VecDeque::new(),
+            elevated: HashSet::new(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    pub fn set_elevated(&mut self, channel: &str, elevated: bool) {
+        if elevated {
+            self.elevated.insert(channel.to_string());
+        } else {
+            self.elevated.remove(channel);
+        }
+    }
+
+    pub fn is_elevated(&self, channel: &str) -> bool {
+        self.elevated.contains(channel)
+    }
+
+    /// Queues a chat line. `text` is what duplicate suppression compares.
+    ///
+    /// Returns `false` if the same text was already accepted for the channel
+    /// within the duplicate window; the line is dropped in that case.
+    pub fn push_message(&mut self, channel: &str, text: &str, line: String) -> bool {
+        let now = self.clock.now();
+        let window = self.limits.duplicate_window;
+        self.recent
+            .retain(|(accepted, _, _)| now.duration_since(*accepted) < window);
+
+        if self.recent.iter().any(|(_, c, t)| c == channel && t == text) {
+            return false;
+        }
+
+        self.recent
+            .push_back((now, channel.to_string(), text.to_string()));
+        self.queued.push_back(OutboundLine {
+            kind: LineKind::Message,
+            channel: channel.to_string(),
+            line,
+        });
+        true
+    }
+
+    /// Queues a JOIN unless one for the channel is already waiting.
+    pub fn push_join(&mut self, channel: &str, line: String) {
+        let already_queued = self
+            .queued
+            .iter()
+            .any(|q| q.kind == LineKind::Join && q.channel == channel);
+        if !already_queued {
+            self.queued.push_back(OutboundLine {
+                kind: LineKind::Join,
+                channel: channel.to_string(),
+                line,
+            });
+        }
+    }
+
+    /// Drops every queued line for a channel, e.g. after leaving it.
+    pub fn remove_channel(&mut self, channel: &str) {
+        self.queued.retain(|q| q.channel != channel);
+    }
+
+    /// Puts back a line that was popped but could not be written. Its budget
+    /// stays spent, which errs on the side of sending too slowly.
+    pub fn push_front(&mut self, line: OutboundLine) {
+        self.queued.push_front(line);
+    }
+
+    /// Takes the first queued line that fits its budget right now and
+    /// charges the budget for it.
+    pub fn pop_ready(&mut self) -> Option<OutboundLine> {
+        let now = self.clock.now();
+        self.messages.expire(now);
+        self.joins.expire(now);
+
+        let index = self.queued.iter().position(|q| self.fits(q))?;
+        let queued = self.queued.remove(index)?;
+        match queued.kind {
+            LineKind::Message => self.messages.record(now),
+            LineKind::Join => self.joins.record(now),
+        }
+        Some(queued)
+    }
+
+    /// How long until `pop_ready` can return something, `None` if empty.
+    pub fn next_ready_in(&mut self) -> Option<Duration> {
+        let now = self.clock.now();
+        self.messages.expire(now);
+        self.joins.expire(now);
+
+        self.queued
+            .iter()
+            .map(|q| match q.kind {
+                LineKind::Message => self.messages.ready_at(self.message_limit(&q.channel), now),
+                LineKind::Join => self.joins.ready_at(self.limits.joins, now),
+            })
+            .min()
+            .map(|ready_at| ready_at.saturating_duration_since(now))
+    }
+
+    fn fits(&self, queued: &OutboundLine) -> bool {
+        match queued.kind {
+            LineKind::Message => self.messages.has_room(self.message_limit(&queued.channel)),
+            LineKind::Join => self.joins.has_room(self.limits.joins),
+        }
+    }
+
+    fn message_limit(&self, channel: &str) -> usize {
+        if self.is_elevated(channel) {
+            self.limits.elevated_messages
+        } else {
+            self.limits.user_messages
+        }
+    }
+}
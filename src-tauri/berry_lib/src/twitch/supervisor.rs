@@ -0,0 +1,258 @@
+//! Keeps the chat connection alive.
+//!
+//! `ConnectionSupervisor` wraps a `TwitchChatAPI` and reconnects with
+//! jittered exponential backoff whenever the socket drops or the server
+//! sends `RECONNECT`. Reconnecting re-authenticates, rejoins the channel and
+//! replays any chat messages that were still queued.
+
+use super::chat_event::ChatEvent;
+use super::twitch_api::{TwitchChatAPI, TwitchError};
+use colored::*;
+use rand::Rng;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Where the connection is in its lifecycle. Serializable so the desktop
+/// app can show it as it changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    /// Waiting `delay_ms` before reconnect attempt number `attempt`.
+    #[serde(rename_all = "camelCase")]
+    Reconnecting { attempt: u32, delay_ms: u64 },
+    /// Gave up after running out of attempts.
+    Failed { reason: String },
+}
+
+/// Jittered exponential backoff.
+///
+/// The n-th delay is picked uniformly from the upper half of
+/// `min(max, base * 2^n)`, so reconnecting clients do not stampede.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    max_attempts: Option<u32>,
+    attempt: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::new(Duration::from_secs(1), Duration::from_secs(120))
+    }
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Backoff {
+            base,
+            max,
+            max_attempts: None,
+            attempt: 0,
+        }
+    }
+
+    /// Stops retrying after `attempts` failed attempts in a row.
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// The delay before the next attempt, or `None` once attempts run out.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| self.attempt >= max) {
+            return None;
+        }
+        let ceiling = self
+            .base
+            .saturating_mul(2u32.saturating_pow(self.attempt.min(31)))
+            .min(self.max);
+        self.attempt += 1;
+
+        let ceiling_ms = ceiling.as_millis() as u64;
+        let jittered = rand::thread_rng().gen_range(ceiling_ms / 2..=ceiling_ms);
+        Some(Duration::from_millis(jittered))
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// What `ConnectionSupervisor::wait` woke up for.
+#[derive(Debug)]
+pub enum Wakeup {
+    /// A line from the server, or the error that ended the read.
+    Line(Result<String, TwitchError>),
+    /// Rate-limited lines can be written now.
+    Flush,
+}
+
+pub struct ConnectionSupervisor {
+    api: TwitchChatAPI,
+    backoff: Backoff,
+    state_tx: watch::Sender<ConnectionState>,
+}
+
+impl ConnectionSupervisor {
+    pub fn new(api: TwitchChatAPI, backoff: Backoff) -> Self {
+        let (state_tx, _) = watch::channel(ConnectionState::Connecting);
+        ConnectionSupervisor {
+            api,
+            backoff,
+            state_tx,
+        }
+    }
+
+    /// Follows the connection state, starting from the current one.
+    pub fn subscribe(&self) -> watch::Receiver<ConnectionState> {
+        self.state_tx.subscribe()
+    }
+
+    pub fn set_backoff(&mut self, backoff: Backoff) {
+        self.backoff = backoff;
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state_tx.borrow().clone()
+    }
+
+    pub fn api(&self) -> &TwitchChatAPI {
+        &self.api
+    }
+
+    pub fn api_mut(&mut self) -> &mut TwitchChatAPI {
+        &mut self.api
+    }
+
+    /// Connects, retrying with backoff until it succeeds or attempts run out.
+    pub async fn connect(&mut self) -> Result<(), TwitchError> {
+        self.set_state(ConnectionState::Connecting);
+        loop {
+            match self.api.connect().await {
+                Ok(()) => {
+                    self.backoff.reset();
+                    self.set_state(ConnectionState::Connected);
+                    return Ok(());
+                }
+                Err(e) => {
+                    println!("{}: {:?}", "Connection Failed".bright_red().bold(), e);
+                    self.wait_before_retry(e).await?;
+                }
+            }
+        }
+    }
+
+    /// Waits for the next chat event, reconnecting transparently when the
+    /// connection drops or the server asks for it.
+    ///
+    /// `ChatEvent::Reconnect` is still returned after the new connection is
+    /// up so the bot can log it. Not cancel-safe; to wait on the connection
+    /// inside `select!`, use `wait` and `process` instead.
+    pub async fn next_event(&mut self) -> Result<ChatEvent, TwitchError> {
+        loop {
+            let wakeup = self.wait().await;
+            if let Some(event) = self.process(wakeup).await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Waits for a line from the server, or for rate-limited lines to be
+    /// ready to write.
+    ///
+    /// Cancel-safe: nothing is written and no line is lost if the future is
+    /// dropped. Hand the result to `process`.
+    pub async fn wait(&mut self) -> Wakeup {
+        let flush_in = self.api.next_outbound_in();
+        tokio::select! {
+            line = self.api.read_line() => Wakeup::Line(line),
+            _ = sleep_for(flush_in) => Wakeup::Flush,
+        }
+    }
+
+    /// Acts on what `wait` returned: answers `PING`s, writes queued lines
+    /// and reconnects when needed. Returns the chat event the line carried,
+    /// if any.
+    ///
+    /// Not cancel-safe; it must run to completion.
+    pub async fn process(&mut self, wakeup: Wakeup) -> Result<Option<ChatEvent>, TwitchError> {
+        let read = match wakeup {
+            Wakeup::Line(Ok(line)) => self.api.handle_line(&line).await,
+            Wakeup::Line(Err(e)) => Err(e),
+            Wakeup::Flush => self.api.flush_outbound().await.map(|_| None),
+        };
+
+        match read {
+            Ok(Some(ChatEvent::Reconnect)) => {
+                let _ = self.api.disconnect().await;
+                self.reconnect().await?;
+                Ok(Some(ChatEvent::Reconnect))
+            }
+            Ok(event) => Ok(event),
+            Err(TwitchError::MessageParseError) => {
+                println!("{}", "Skipping Unparseable Line".bright_yellow());
+                Ok(None)
+            }
+            Err(e) => {
+                println!("{}: {:?}", "Connection Lost".bright_red().bold(), e);
+                self.reconnect().await?;
+                Ok(None)
+            }
+        }
+    }
+
+    pub async fn disconnect(&mut self) -> Result<(), TwitchError> {
+        self.api.disconnect().await
+    }
+
+    async fn reconnect(&mut self) -> Result<(), TwitchError> {
+        let delay = self.next_delay("reconnect requested".to_string())?;
+        tokio::time::sleep(delay).await;
+        self.connect().await
+    }
+
+    async fn wait_before_retry(&mut self, error: TwitchError) -> Result<(), TwitchError> {
+        let delay = match self.next_delay(format!("{:?}", error)) {
+            Ok(delay) => delay,
+            Err(_) => return Err(error),
+        };
+        tokio::time::sleep(delay).await;
+        Ok(())
+    }
+
+    fn next_delay(&mut self, reason: String) -> Result<Duration, TwitchError> {
+        match self.backoff.next_delay() {
+            Some(delay) => {
+                self.set_state(ConnectionState::Reconnecting {
+                    attempt: self.backoff.attempt(),
+                    delay_ms: delay.as_millis() as u64,
+                });
+                Ok(delay)
+            }
+            None => {
+                self.set_state(ConnectionState::Failed { reason });
+                Err(TwitchError::ConnectionError)
+            }
+        }
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        // Kept even when nobody is watching.
+        self.state_tx.send_replace(state);
+    }
+}
+
+async fn sleep_for(delay: Option<Duration>) {
+    match delay {
+        Some(delay) => tokio::time::sleep(delay).await,
+        None => std::future::pending().await,
+    }
+}
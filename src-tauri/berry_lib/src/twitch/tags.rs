@@ -0,0 +1,137 @@
+//! Typed views over the IRCv3 tags Twitch attaches to chat lines once the
+//! `twitch.tv/tags` capability has been granted.
+
+use super::chat_event::SubTier;
+use chrono::{DateTime, TimeZone, Utc};
+
+/// The badges a chatter is wearing in the channel.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Badges {
+    pub broadcaster: bool,
+    pub moderator: bool,
+    pub vip: bool,
+    /// Subscribed months, `None` when the chatter is not subscribed.
+    pub subscriber: Option<u32>,
+    /// Every badge as `(name, version)`, including ones not broken out above.
+    pub raw: Vec<(String, String)>,
+}
+
+impl Badges {
+    /// Builds the badges from the `badges` and `badge-info` tag values.
+    ///
+    /// `badge-info` carries the exact subscribed months; the `subscriber`
+    /// badge version is only the tier of badge artwork and is used as a
+    /// fallback.
+    pub fn parse(badges: &str, badge_info: &str) -> Self {
+        let raw = parse_badge_list(badges);
+        let info = parse_badge_list(badge_info);
+
+        let has = |name: &str| raw.iter().any(|(n, _)| n == name);
+        let subscriber = if has("subscriber") || has("founder") {
+            info.iter()
+                .find(|(n, _)| n == "subscriber" || n == "founder")
+                .or_else(|| raw.iter().find(|(n, _)| n == "subscriber"))
+                .and_then(|(_, months)| months.parse().ok())
+                .or(Some(0))
+        } else {
+            None
+        };
+
+        Badges {
+            broadcaster: has("broadcaster"),
+            moderator: has("moderator"),
+            vip: has("vip"),
+            subscriber,
+            raw,
+        }
+    }
+
+    pub fn is_subscriber(&self) -> bool {
+        self.subscriber.is_some()
+    }
+
+    /// The tier from the `subscriber` badge version, e.g. `3012` for a
+    /// tier 3 badge. Prime subscriptions show as tier 1.
+    pub fn subscriber_tier(&self) -> Option<SubTier> {
+        if !self.is_subscriber() {
+            return None;
+        }
+        let version: u32 = self
+            .raw
+            .iter()
+            .find(|(name, _)| name == "subscriber")
+            .and_then(|(_, version)| version.parse().ok())
+            .unwrap_or(0);
+        Some(match version {
+            3000.. => SubTier::Tier3,
+            2000.. => SubTier::Tier2,
+            _ => SubTier::Tier1,
+        })
+    }
+}
+
+fn parse_badge_list(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter(|badge| !badge.is_empty())
+        .map(|badge| match badge.split_once('/') {
+            Some((name, version)) => (name.to_string(), version.to_string()),
+            None => (badge.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// An emote used in a message with the character ranges it covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emote {
+    pub id: String,
+    /// Inclusive `(start, end)` character positions in the message text.
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl Emote {
+    /// Parses the `emotes` tag, e.g. `25:0-4,12-16/1902:6-10`.
+    pub fn parse_list(value: &str) -> Vec<Emote> {
+        value
+            .split('/')
+            .filter_map(|entry| {
+                let (id, ranges) = entry.split_once(':')?;
+                let ranges = ranges
+                    .split(',')
+                    .filter_map(|range| {
+                        let (start, end) = range.split_once('-')?;
+                        Some((start.parse().ok()?, end.parse().ok()?))
+                    })
+                    .collect();
+                Some(Emote {
+                    id: id.to_string(),
+                    ranges,
+                })
+            })
+            .collect()
+    }
+
+    /// How many characters of the message this emote takes up.
+    pub fn char_count(&self) -> usize {
+        self.ranges
+            .iter()
+            .map(|(start, end)| end.saturating_sub(*start) + 1)
+            .sum()
+    }
+}
+
+/// The message a chatter replied to, from the `reply-parent-*` tags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplyParent {
+    pub msg_id: String,
+    pub user_id: String,
+    pub user_login: String,
+    pub display_name: String,
+    pub body: String,
+}
+
+/// Converts Twitch's millisecond `tmi-sent-ts` value.
+pub fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    let millis: i64 = value.parse().ok()?;
+    Utc.timestamp_millis_opt(millis).single()
+}
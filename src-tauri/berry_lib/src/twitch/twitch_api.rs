@@ -1,26 +1,105 @@
 // twitch_api.rs
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
+use super::chat_event::ChatEvent;
+use super::identity::ChatIdentity;
+use super::irc_message::IrcMessage;
+use super::outgoing::{format_privmsg, split_message, DeliveryMode, MAX_MESSAGE_CHARS};
+use super::rate_limit::{Clock, OutboundQueue, RateLimits, SystemClock};
+use super::tags::{parse_timestamp, Badges, Emote, ReplyParent};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use tokio::io::{ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio_native_tls::native_tls;
 
+/// The capabilities requested right after connecting, so lines arrive
+/// with tags, Twitch-specific commands and JOIN/PART membership.
+const CAPABILITIES: &str = "twitch.tv/tags twitch.tv/commands twitch.tv/membership";
+
+#[derive(Debug, Clone, Default)]
 pub struct TwitchMessage {
+    /// The channel the message was sent in, without the leading `#`.
+    pub channel: String,
+    /// The broadcaster's user id.
+    pub room_id: String,
+    /// The sender's login name.
     pub sender: String,
     pub text: String,
+    /// The message id, used to delete or reply to it.
+    pub id: String,
+    pub user_id: String,
+    pub display_name: String,
+    /// The chatter's name color as `#RRGGBB`, if they picked one.
+    pub color: Option<String>,
+    pub badges: Badges,
+    pub emotes: Vec<Emote>,
+    /// Bits cheered with this message.
+    pub bits: Option<u64>,
+    /// Whether this is the chatter's first message in the channel.
+    pub first_msg: bool,
+    pub reply_parent: Option<ReplyParent>,
+    /// When the server received the message.
+    pub sent_at: Option<DateTime<Utc>>,
 }
 
-impl Default for TwitchMessage {
-    fn default() -> Self {
-        TwitchMessage {
-            sender: String::new(),
-            text: String::new(),
+impl TryFrom<&IrcMessage> for TwitchMessage {
+    type Error = TwitchError;
+
+    fn try_from(irc: &IrcMessage) -> Result<Self, Self::Error> {
+        if irc.command != "PRIVMSG" {
+            return Err(TwitchError::MessageParseError);
         }
+        let channel = irc
+            .param(0)
+            .and_then(|c| c.strip_prefix('#'))
+            .ok_or(TwitchError::MessageParseError)?;
+        let sender = irc.nick().ok_or(TwitchError::MessageParseError)?;
+        let text = irc.trailing().ok_or(TwitchError::MessageParseError)?;
+
+        let tag = |key: &str| irc.tag(key).unwrap_or_default();
+
+        let reply_parent = irc.tag("reply-parent-msg-id").map(|msg_id| ReplyParent {
+            msg_id: msg_id.to_string(),
+            user_id: tag("reply-parent-user-id").to_string(),
+            user_login: tag("reply-parent-user-login").to_string(),
+            display_name: tag("reply-parent-display-name").to_string(),
+            body: tag("reply-parent-msg-body").to_string(),
+        });
+
+        let display_name = match tag("display-name") {
+            "" => sender.to_string(),
+            name => name.to_string(),
+        };
+
+        Ok(TwitchMessage {
+            channel: channel.to_string(),
+            room_id: tag("room-id").to_string(),
+            sender: sender.to_string(),
+            text: text.trim().to_string(),
+            id: tag("id").to_string(),
+            user_id: tag("user-id").to_string(),
+            display_name,
+            color: irc.tag("color").filter(|c| !c.is_empty()).map(String::from),
+            badges: Badges::parse(tag("badges"), tag("badge-info")),
+            emotes: Emote::parse_list(tag("emotes")),
+            bits: irc.tag("bits").and_then(|b| b.parse().ok()),
+            first_msg: tag("first-msg") == "1",
+            reply_parent,
+            sent_at: parse_timestamp(tag("tmi-sent-ts")),
+        })
     }
 }
 
 #[derive(Debug)]
 pub enum TwitchError {
     IOError(std::io::Error),
+    TlsError(native_tls::Error),
     ConnectionError,
+    ConnectionClosed,
     MessageParseError,
+    /// Sending is not possible on an anonymous, read-only connection.
+    ReadOnly,
 }
 
 impl From<std::io::Error> for TwitchError {
@@ -29,80 +108,338 @@ impl From<std::io::Error> for TwitchError {
     }
 }
 
-pub struct TwitchChatAPI<'a> {
-    access_token: &'a str,
-    channel: &'a str,
-    stream: Option<TcpStream>,
-    reader: Option<BufReader<TcpStream>>,
+impl From<native_tls::Error> for TwitchError {
+    fn from(err: native_tls::Error) -> Self {
+        TwitchError::TlsError(err)
+    }
+}
+
+/// Where the chat connection is opened.
+///
+/// Defaults to Twitch's TLS endpoint. Tests can point this at a local
+/// plaintext or TLS stand-in server instead.
+#[derive(Debug, Clone)]
+pub struct ChatEndpoint {
+    /// The host name, also used for TLS server name verification.
+    pub host: String,
+    /// The port to connect to.
+    pub port: u16,
+    /// Whether to wrap the connection in TLS.
+    pub tls: bool,
+    /// Skips certificate verification. Only meant for local test servers.
+    pub accept_invalid_certs: bool,
+}
+
+impl Default for ChatEndpoint {
+    fn default() -> Self {
+        ChatEndpoint::tls("irc.chat.twitch.tv", 6697)
+    }
+}
+
+impl ChatEndpoint {
+    pub fn tls(host: &str, port: u16) -> Self {
+        ChatEndpoint {
+            host: host.to_string(),
+            port,
+            tls: true,
+            accept_invalid_certs: false,
+        }
+    }
+
+    pub fn plaintext(host: &str, port: u16) -> Self {
+        ChatEndpoint {
+            host: host.to_string(),
+            port,
+            tls: false,
+            accept_invalid_certs: false,
+        }
+    }
 }
 
-impl<'a> TwitchChatAPI<'a> {
-    pub fn new(access_token: &'a str, channel: &'a str) -> Result<Self, TwitchError> {
+/// Any stream the chat connection can run over (plain TCP or TLS).
+trait ChatTransport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ChatTransport for T {}
+
+type ChatReader = Lines<BufReader<ReadHalf<Box<dyn ChatTransport>>>>;
+type ChatWriter = WriteHalf<Box<dyn ChatTransport>>;
+
+pub struct TwitchChatAPI {
+    identity: ChatIdentity,
+    /// Joined channels, rejoined on every `connect`.
+    channels: Vec<String>,
+    endpoint: ChatEndpoint,
+    writer: Option<ChatWriter>,
+    reader: Option<ChatReader>,
+    /// Chat messages and JOINs not yet written to the socket, held back by
+    /// the rate limits. They survive a dropped connection and are replayed
+    /// after the next `connect`.
+    outbound: OutboundQueue,
+}
+
+impl TwitchChatAPI {
+    pub fn new(identity: ChatIdentity, channels: &[&str]) -> Result<Self, TwitchError> {
+        Self::with_endpoint(identity, channels, ChatEndpoint::default())
+    }
+
+    pub fn with_endpoint(
+        identity: ChatIdentity,
+        channels: &[&str],
+        endpoint: ChatEndpoint,
+    ) -> Result<Self, TwitchError> {
         Ok(TwitchChatAPI {
-            access_token,
-            channel,
-            stream: None,
+            identity,
+            channels: channels.iter().map(|c| normalize_channel(c)).collect(),
+            endpoint,
+            writer: None,
             reader: None,
+            outbound: OutboundQueue::new(RateLimits::default(), Arc::new(SystemClock)),
         })
     }
 
-    pub fn get_access_token(&self) -> String {
-        self.access_token.to_string()
+    /// Replaces the outbound rate limits, e.g. with a mock clock in tests.
+    pub fn with_rate_limits(mut self, limits: RateLimits, clock: Arc<dyn Clock>) -> Self {
+        self.outbound = OutboundQueue::new(limits, clock);
+        self
     }
 
-    pub fn connect(&mut self) -> Result<(), TwitchError> {
-        let stream = TcpStream::connect("irc.chat.twitch.tv:6667")
-            .map_err(|_| TwitchError::ConnectionError)?;
-        stream.set_nonblocking(true)?;
+    pub fn identity(&self) -> &ChatIdentity {
+        &self.identity
+    }
 
-        let mut writer = stream.try_clone()?;
-        writer.write_all(format!("PASS oauth:{}\r\n", self.access_token).as_bytes())?;
-        writer.write_all(format!("NICK bot_username\r\n").as_bytes())?;
-        writer.write_all(format!("JOIN #{}\r\n", self.channel).as_bytes())?;
+    pub fn is_read_only(&self) -> bool {
+        self.identity.is_read_only()
+    }
 
-        self.stream = Some(stream);
-        self.reader = Some(BufReader::new(self.stream.as_ref().unwrap().try_clone()?));
-        println!("Connected to Twitch: {}", self.channel); // !REMOVE
-        Ok(())
+    pub async fn connect(&mut self) -> Result<(), TwitchError> {
+        let stream = open_transport(&self.endpoint).await?;
+        let (reader, writer) = tokio::io::split(stream);
+
+        self.reader = Some(BufReader::new(reader).lines());
+        self.writer = Some(writer);
+
+        self.send_raw_message(&format!("CAP REQ :{}\r\n", CAPABILITIES))
+            .await?;
+        // Anonymous logins skip PASS; Twitch accepts any justinfan nick.
+        if let Some(access_token) = self.identity.access_token() {
+            let pass = format!("PASS oauth:{}\r\n", access_token);
+            self.send_raw_message(&pass).await?;
+        }
+        let nick = format!("NICK {}\r\n", self.identity.login());
+        self.send_raw_message(&nick).await?;
+        for channel in &self.channels {
+            self.outbound
+                .push_join(channel, format!("JOIN #{}\r\n", channel));
+        }
+        self.flush_outbound().await
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
     }
 
-    pub fn read_message(&mut self) -> Result<Option<TwitchMessage>, TwitchError> {
-        if let Some(reader) = &mut self.reader {
-            let mut line = String::new();
-            if reader.read_line(&mut line)? > 0 {
-                if line.starts_with("PING") {
-                    self.send_raw_message("PONG :tmi.twitch.tv\r\n")?;
-                } else if line.contains("PRIVMSG") {
-                    let parts: Vec<&str> = line.split(' ').collect();
-                    if parts.len() >= 4 {
-                        let sender = parts[0][1..].split('!').next().unwrap_or("").to_string();
-                        let text = parts[3..].join(" ")[1..].trim().to_string();
-                        return Ok(Some(TwitchMessage { sender, text }));
-                    }
-                    return Err(TwitchError::MessageParseError);
-                }
+    /// Joins a channel, now if connected and on every reconnect after.
+    pub async fn join(&mut self, channel: &str) -> Result<(), TwitchError> {
+        let channel = normalize_channel(channel);
+        if self.channels.contains(&channel) {
+            return Ok(());
+        }
+        self.channels.push(channel.clone());
+        self.outbound
+            .push_join(&channel, format!("JOIN #{}\r\n", channel));
+        self.flush_outbound().await
+    }
+
+    /// Leaves a channel and stops rejoining it.
+    pub async fn part(&mut self, channel: &str) -> Result<(), TwitchError> {
+        let channel = normalize_channel(channel);
+        if !self.channels.contains(&channel) {
+            return Ok(());
+        }
+        self.channels.retain(|c| *c != channel);
+        self.outbound.remove_channel(&channel);
+        self.send_raw_message(&format!("PART #{}\r\n", channel))
+            .await
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// The number of lines still waiting to be written.
+    pub fn pending_outbound(&self) -> usize {
+        self.outbound.len()
+    }
+
+    /// How long until the next queued line may be written, `None` if
+    /// nothing is queued.
+    pub fn next_outbound_in(&mut self) -> Option<Duration> {
+        self.outbound.next_ready_in()
+    }
+
+    /// Waits for the next line from the server.
+    ///
+    /// Returns `TwitchError::ConnectionClosed` once the server hangs up.
+    /// Cancel-safe: a line that was only partly received is kept for the
+    /// next call.
+    pub async fn read_line(&mut self) -> Result<String, TwitchError> {
+        let reader = self.reader.as_mut().ok_or(TwitchError::ConnectionError)?;
+        match reader.next_line().await {
+            Ok(Some(line)) => Ok(line),
+            Ok(None) => {
+                self.drop_connection();
+                Err(TwitchError::ConnectionClosed)
+            }
+            Err(e) => {
+                self.drop_connection();
+                Err(e.into())
             }
         }
-        Ok(None)
     }
 
-    pub fn send_message(&mut self, message: &str) -> Result<(), TwitchError> {
-        self.send_raw_message(&format!("PRIVMSG #{} :{}\r\n", self.channel, message))
+    /// Turns a line from `read_line` into an event, answering `PING`s.
+    ///
+    /// Returns `Ok(None)` for lines that carry no event (numerics, `CAP`
+    /// replies, `PING`).
+    pub async fn handle_line(&mut self, line: &str) -> Result<Option<ChatEvent>, TwitchError> {
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let irc = IrcMessage::parse(line)?;
+        if irc.command == "PING" {
+            let token = irc.trailing().unwrap_or("tmi.twitch.tv");
+            self.send_raw_message(&format!("PONG :{}\r\n", token))
+                .await?;
+            return Ok(None);
+        }
+
+        let event = ChatEvent::from_irc(&irc)?;
+        if let Some(ChatEvent::UserState(state)) = &event {
+            self.outbound
+                .set_elevated(&state.channel, state.is_elevated());
+        }
+        Ok(event)
+    }
+
+    /// Queues a plain chat message and writes whatever the rate limits allow.
+    ///
+    /// A message identical to one sent to the same channel in the last 30
+    /// seconds is dropped. If the write fails the message stays queued for
+    /// the next connection.
+    pub async fn send_message(&mut self, channel: &str, message: &str) -> Result<(), TwitchError> {
+        self.deliver(channel, message, DeliveryMode::Say, None)
+            .await
+    }
+
+    /// Replies to the message with id `parent_id`.
+    pub async fn send_reply(
+        &mut self,
+        channel: &str,
+        parent_id: &str,
+        message: &str,
+    ) -> Result<(), TwitchError> {
+        self.deliver(channel, message, DeliveryMode::Reply, Some(parent_id))
+            .await
+    }
+
+    /// Sends a `/me` action.
+    pub async fn send_action(&mut self, channel: &str, message: &str) -> Result<(), TwitchError> {
+        self.deliver(channel, message, DeliveryMode::Action, None)
+            .await
+    }
+
+    /// Sends `message` the way `mode` asks, split into several messages on
+    /// word boundaries if it is longer than Twitch allows. Every part goes
+    /// through the rate limiter on its own.
+    ///
+    /// Fails with `TwitchError::ReadOnly` on an anonymous connection.
+    pub async fn deliver(
+        &mut self,
+        channel: &str,
+        message: &str,
+        mode: DeliveryMode,
+        reply_to: Option<&str>,
+    ) -> Result<(), TwitchError> {
+        if self.is_read_only() {
+            return Err(TwitchError::ReadOnly);
+        }
+        let channel = normalize_channel(channel);
+        for part in split_message(message, MAX_MESSAGE_CHARS) {
+            let line = format_privmsg(&channel, &part, mode, reply_to);
+            // Repeats of a recent message are dropped here, which is normal.
+            self.outbound.push_message(&channel, &part, line);
+        }
+        self.flush_outbound().await
+    }
+
+    /// Writes every queued line whose budget is available now.
+    pub async fn flush_outbound(&mut self) -> Result<(), TwitchError> {
+        if self.writer.is_none() {
+            return Ok(());
+        }
+        while let Some(queued) = self.outbound.pop_ready() {
+            if let Err(e) = self.send_raw_message(&queued.line).await {
+                self.outbound.push_front(queued);
+                return Err(e);
+            }
+        }
+        Ok(())
     }
 
-    fn send_raw_message(&mut self, message: &str) -> Result<(), TwitchError> {
-        println!("Sending message: {}", message); // !REMOVE
-        if let Some(stream) = &mut self.stream {
-            stream.write_all(message.as_bytes())?;
+    async fn send_raw_message(&mut self, message: &str) -> Result<(), TwitchError> {
+        if let Some(writer) = &mut self.writer {
+            let written = async {
+                writer.write_all(message.as_bytes()).await?;
+                writer.flush().await
+            }
+            .await;
+            if let Err(e) = written {
+                self.drop_connection();
+                return Err(e.into());
+            }
         }
         Ok(())
     }
 
-    pub fn disconnect(&mut self) -> Result<(), TwitchError> {
-        self.send_raw_message(&format!("PART #{}\r\n", self.channel))?;
-        if let Some(stream) = &mut self.stream {
-            stream.shutdown(std::net::Shutdown::Both)?;
+    pub async fn disconnect(&mut self) -> Result<(), TwitchError> {
+        for channel in self.channels.clone() {
+            self.send_raw_message(&format!("PART #{}\r\n", channel))
+                .await?;
+        }
+        if let Some(writer) = &mut self.writer {
+            writer.shutdown().await?;
         }
+        self.drop_connection();
         Ok(())
     }
+
+    fn drop_connection(&mut self) {
+        self.writer = None;
+        self.reader = None;
+    }
+}
+
+/// A channel name as it is stored and joined: lowercase, without the
+/// leading `#`.
+pub fn normalize_channel(channel: &str) -> String {
+    channel.trim_start_matches('#').to_lowercase()
+}
+
+async fn open_transport(endpoint: &ChatEndpoint) -> Result<Box<dyn ChatTransport>, TwitchError> {
+    let tcp = TcpStream::connect((endpoint.host.as_str(), endpoint.port))
+        .await
+        .map_err(|_| TwitchError::ConnectionError)?;
+
+    if !endpoint.tls {
+        return Ok(Box::new(tcp));
+    }
+
+    let connector = native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(endpoint.accept_invalid_certs)
+        .build()?;
+    let connector = tokio_native_tls::TlsConnector::from(connector);
+    let tls = connector.connect(&endpoint.host, tcp).await?;
+
+    Ok(Box::new(tls))
 }
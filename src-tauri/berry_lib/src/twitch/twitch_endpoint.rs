@@ -1,4 +1,3 @@
-use super::twitch_api::TwitchChatAPI;
 use colored::*;
 use reqwest;
 use serde::{Deserialize, Serialize};
@@ -14,12 +13,11 @@ pub struct TwitchUser {
     // other fields if needed
 }
 
-pub async fn get_user_twitch_id<'a>(
+pub async fn get_user_twitch_id(
     username: &str,
-    api: &'a TwitchChatAPI<'a>,
+    access_token: &str,
 ) -> Result<String, Box<dyn std::error::Error>> {
     println!("{}", "Getting User Twitch ID".bright_blue().bold().underline());
-    let access_token = api.get_access_token();
 
     let fmt_access_token = format!("Bearer {}", access_token);
 
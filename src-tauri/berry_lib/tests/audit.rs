@@ -0,0 +1,157 @@
+use berry_lib::moderation::audit::{
+    ActionResult, AuditEntry, AuditLog, AuditQuery, DecisionOrigin, ExportFormat,
+};
+use berry_lib::openai::moderation::{
+    FlaggedMessage, ModerationCategory, ModerationScores, PunishmentAction,
+};
+
+fn entry(username: &str, user_id: &str, category: &str, timestamp: i64) -> AuditEntry {
+    let category = ModerationCategory::from(category);
+    let mut scores = ModerationScores::default();
+    scores.set(&category, 0.9);
+    let mut entry = AuditEntry::new(
+        "#Bar",
+        DecisionOrigin::Automatic,
+        FlaggedMessage::new(username, user_id, "msg-1", "bad, \"words\"", category, 0.9),
+        scores,
+        Some(0.55),
+        PunishmentAction::Timeout(60),
+        ActionResult::Applied,
+    );
+    entry.timestamp = timestamp;
+    entry
+}
+
+fn filled_log() -> AuditLog {
+    let log = AuditLog::in_memory();
+    log.record(entry("troll", "1", "hate", 100)).unwrap();
+    log.record(entry("troll", "1", "violence", 200)).unwrap();
+    log.record(entry("other", "2", "hate", 300)).unwrap();
+    log
+}
+
+#[test]
+fn queries_by_user_category_and_time() {
+    let log = filled_log();
+    assert_eq!(log.len(), 3);
+    assert_eq!(log.query(&AuditQuery::default()).len(), 3);
+
+    let by_user = AuditQuery {
+        user: Some("@Troll".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(log.query(&by_user).len(), 2);
+
+    let by_id_and_category = AuditQuery {
+        user: Some("1".to_string()),
+        category: Some(ModerationCategory::Hate),
+        ..Default::default()
+    };
+    assert_eq!(log.query(&by_id_and_category)[0].timestamp, 100);
+
+    let by_time = AuditQuery {
+        since: Some(200),
+        until: Some(300),
+        ..Default::default()
+    };
+    let entries = log.query(&by_time);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message.category, ModerationCategory::Violence);
+
+    let other_channel = AuditQuery {
+        channel: Some("baz".to_string()),
+        ..Default::default()
+    };
+    assert!(log.query(&other_channel).is_empty());
+    assert_eq!(entries[0].channel, "bar");
+}
+
+#[test]
+fn log_survives_reload_and_skips_broken_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("logs").join("audit.jsonl");
+
+    let log = AuditLog::load_from(path.clone()).unwrap();
+    log.record(entry("troll", "1", "hate", 100)).unwrap();
+    log.record(entry("other", "2", "hate", 200)).unwrap();
+
+    // A line cut short by a crash.
+    let mut contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents.lines().count(), 2);
+    contents.push_str("{\"timestamp\":3");
+    std::fs::write(&path, contents).unwrap();
+
+    let reloaded = AuditLog::load_from(path).unwrap();
+    assert_eq!(
+        reloaded.query(&AuditQuery::default()),
+        log.query(&AuditQuery::default())
+    );
+}
+
+#[test]
+fn exports_jsonl() {
+    let log = filled_log();
+    let mut out = Vec::new();
+    log.export(&AuditQuery::default(), ExportFormat::Jsonl, &mut out)
+        .unwrap();
+
+    let text = String::from_utf8(out).unwrap();
+    let entries: Vec<AuditEntry> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(entries, log.query(&AuditQuery::default()));
+}
+
+#[test]
+fn exports_csv_with_every_score() {
+    let log = filled_log();
+    let query = AuditQuery {
+        user: Some("other".to_string()),
+        ..Default::default()
+    };
+    let mut out = Vec::new();
+    log.export(&query, ExportFormat::Csv, &mut out).unwrap();
+
+    let mut reader = csv::Reader::from_reader(out.as_slice());
+    let header = reader.headers().unwrap().clone();
+    assert!(header.iter().any(|column| column == "self-harm/intent"));
+    let column = |name: &str| header.iter().position(|c| c == name).unwrap();
+
+    let rows: Vec<csv::StringRecord> = reader.records().map(|row| row.unwrap()).collect();
+    assert_eq!(rows.len(), 1);
+    let row = &rows[0];
+    assert_eq!(&row[column("time")], "1970-01-01T00:05:00+00:00");
+    assert_eq!(&row[column("username")], "other");
+    assert_eq!(&row[column("text")], "bad, \"words\"");
+    assert_eq!(&row[column("threshold")], "0.55");
+    assert_eq!(&row[column("action")], "Timeout(60)");
+    assert_eq!(&row[column("result")], "Applied");
+    assert_eq!(&row[column("origin")], "Automatic");
+    assert_eq!(&row[column("hate")], "0.9");
+    assert_eq!(&row[column("violence")], "0.0");
+}
+
+#[test]
+fn csv_has_columns_for_new_categories() {
+    let log = AuditLog::in_memory();
+    let mut illicit = entry("troll", "1", "illicit", 100);
+    illicit
+        .scores
+        .set(&ModerationCategory::from("illicit"), 0.7);
+    log.record(illicit).unwrap();
+    log.record(entry("other", "2", "hate", 200)).unwrap();
+
+    let mut out = Vec::new();
+    log.export(&AuditQuery::default(), ExportFormat::Csv, &mut out)
+        .unwrap();
+    let mut reader = csv::Reader::from_reader(out.as_slice());
+    let header = reader.headers().unwrap().clone();
+    let column = |name: &str| header.iter().position(|c| c == name).unwrap();
+
+    let rows: Vec<csv::StringRecord> = reader.records().map(|row| row.unwrap()).collect();
+    assert_eq!(&rows[0][column("illicit")], "0.7");
+    assert_eq!(&rows[0][column("hate")], "0.0");
+    assert_eq!(&rows[1][column("illicit")], "");
+    assert_eq!(&rows[1][column("hate")], "0.9");
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use berry_lib::moderation::batcher::{BatchConfig, MetricsSnapshot, ModerationBatcher};
+use berry_lib::moderation::normalize::NormalizedText;
+use berry_lib::moderation::provider::ModerationProvider;
+use berry_lib::openai::moderation::{ModerationError, OpenAiConfig, OpenAiProvider};
+use common::batch_response;
+use common::mock_http::MockHttp;
+use std::time::Duration;
+
+fn batcher(server: &MockHttp, config: BatchConfig) -> ModerationBatcher {
+    let openai = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    ModerationBatcher::new(OpenAiProvider::new(openai), config)
+}
+
+fn inputs(server: &MockHttp) -> Vec<Vec<String>> {
+    server
+        .requests_to("/v1/moderations")
+        .iter()
+        .map(|request| serde_json::from_value(request.json()["input"].clone()).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn batches_messages_into_one_request() {
+    let server = MockHttp::start().await;
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &batch_response(&[0.1, 0.9, 0.3]),
+    );
+    let batcher = batcher(&server, BatchConfig::default());
+
+    let (first, second, third) = tokio::join!(
+        batcher.classify("hello"),
+        batcher.classify("hateful words"),
+        batcher.classify("something else"),
+    );
+
+    assert_eq!(
+        inputs(&server),
+        vec![vec!["hello", "hateful words", "something else"]]
+    );
+    assert_eq!(first.unwrap().scores.hate, 0.1);
+    let second = second.unwrap();
+    assert!(second.flagged);
+    assert_eq!(second.scores.hate, 0.9);
+    assert_eq!(third.unwrap().scores.hate, 0.3);
+}
+
+#[tokio::test]
+async fn sends_repeated_messages_once() {
+    let server = MockHttp::start().await;
+    server.respond("POST", "/v1/moderations", 200, &batch_response(&[0.9, 0.2]));
+    let batcher = batcher(&server, BatchConfig::default());
+
+    let (first, second, third) = tokio::join!(
+        batcher.classify("spam spam"),
+        batcher.classify("hi"),
+        batcher.classify("SPAM   spam"),
+    );
+
+    assert_eq!(inputs(&server), vec![vec!["spam spam", "hi"]]);
+    assert_eq!(first.unwrap(), third.unwrap());
+    assert_eq!(second.unwrap().scores.hate, 0.2);
+}
+
+#[tokio::test]
+async fn splits_batches_at_the_size_limit() {
+    let server = MockHttp::start().await;
+    server.respond("POST", "/v1/moderations", 200, &batch_response(&[0.1, 0.1]));
+    let config = BatchConfig {
+        max_batch: 2,
+        ..BatchConfig::default()
+    };
+    let batcher = batcher(&server, config);
+
+    let (a, b, c, d) = tokio::join!(
+        batcher.classify("a"),
+        batcher.classify("b"),
+        batcher.classify("c"),
+        batcher.classify("d"),
+    );
+    assert!(a.is_ok() && b.is_ok() && c.is_ok() && d.is_ok());
+
+    let mut sent = inputs(&server);
+    sent.sort();
+    assert_eq!(sent, vec![vec!["a", "b"], vec!["c", "d"]]);
+}
+
+#[tokio::test]
+async fn answers_repeats_from_the_cache() {
+    let server = MockHttp::start().await;
+    server.respond("POST", "/v1/moderations", 200, &batch_response(&[0.9]));
+    let batcher = batcher(&server, BatchConfig::default());
+
+    let first = batcher.classify("free m0ney").await.unwrap();
+    let again = batcher.classify("FREE MONEY").await.unwrap();
+    let obfuscated = batcher.classify("fr\u{0435}\u{0435} money").await.unwrap();
+
+    assert_eq!(first, again);
+    assert_eq!(first, obfuscated);
+    assert_eq!(inputs(&server), vec![vec!["free money"]]);
+}
+
+#[tokio::test]
+async fn messages_are_classified_and_cached_by_their_skeleton_as_given() {
+    let server = MockHttp::start().await;
+    server.respond("POST", "/v1/moderations", 200, &batch_response(&[0.9]));
+    let batcher = batcher(&server, BatchConfig::default());
+    let message = NormalizedText {
+        original: "FREE M0NEY".to_string(),
+        skeleton: "FREE MONEY".to_string(),
+    };
+
+    let first = batcher.classify_message(&message).await.unwrap();
+    let again = batcher.classify_message(&message).await.unwrap();
+
+    assert_eq!(first, again);
+    assert_eq!(inputs(&server), vec![vec!["FREE MONEY"]]);
+    assert_eq!(batcher.metrics().snapshot().cache_hits, 1);
+}
+
+#[tokio::test]
+async fn evicts_the_least_recently_used_result() {
+    let server = MockHttp::start().await;
+    server.respond("POST", "/v1/moderations", 200, &batch_response(&[0.1]));
+    let config = BatchConfig {
+        cache_capacity: 2,
+        ..BatchConfig::default()
+    };
+    let batcher = batcher(&server, config);
+
+    for text in ["one", "two", "one", "three", "one", "two"] {
+        batcher.classify(text).await.unwrap();
+    }
+    assert_eq!(
+        inputs(&server),
+        vec![vec!["one"], vec!["two"], vec!["three"], vec!["two"]]
+    );
+}
+
+#[tokio::test]
+async fn failures_reach_every_message_and_are_not_cached() {
+    let server = MockHttp::start().await;
+    // One result for two inputs cannot be mapped back.
+    server.respond("POST", "/v1/moderations", 200, &batch_response(&[0.1]));
+    let batcher = batcher(&server, BatchConfig::default());
+
+    let (first, second) = tokio::join!(batcher.classify("a"), batcher.classify("b"));
+    assert!(matches!(first, Err(ModerationError::ApiError)));
+    assert!(matches!(second, Err(ModerationError::ApiError)));
+
+    assert!(batcher.classify("a").await.is_ok());
+    assert_eq!(server.requests_to("/v1/moderations").len(), 2);
+}
+
+#[tokio::test]
+async fn counts_requests_saved_and_latency() {
+    let server = MockHttp::start().await;
+    server.respond("POST", "/v1/moderations", 200, &batch_response(&[0.1, 0.2]));
+    let config = BatchConfig {
+        window: Duration::from_millis(20),
+        ..BatchConfig::default()
+    };
+    let batcher = batcher(&server, config);
+
+    let _ = tokio::join!(
+        batcher.classify("a"),
+        batcher.classify("b"),
+        batcher.classify("a")
+    );
+    batcher.classify("b").await.unwrap();
+
+    let metrics = batcher.metrics().snapshot();
+    assert_eq!(
+        metrics,
+        MetricsSnapshot {
+            messages: 4,
+            cache_hits: 1,
+            requests: 1,
+            failed_requests: 0,
+            inputs_sent: 2,
+            ..metrics.clone()
+        }
+    );
+    assert_eq!(metrics.requests_saved(), 3);
+    assert_eq!(metrics.cache_hit_rate(), 0.25);
+    assert!(metrics.max_latency_ms as f64 >= metrics.average_latency_ms);
+
+    batcher.metrics().reset();
+    assert_eq!(batcher.metrics().snapshot(), MetricsSnapshot::default());
+}
+
+#[tokio::test]
+async fn messages_with_images_are_sent_on_their_own() {
+    let server = MockHttp::start().await;
+    server.respond("POST", "/v1/moderations", 200, &batch_response(&[0.1]));
+    let openai = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()))
+        .with_image_inputs(true);
+    let batcher = ModerationBatcher::new(OpenAiProvider::new(openai), BatchConfig::default());
+    let image = NormalizedText::new("https://i.imgur.com/cat.png");
+    let text = NormalizedText::new("hello");
+
+    let (first, second) = tokio::join!(
+        batcher.classify_message(&image),
+        batcher.classify_message(&text),
+    );
+    assert!(first.is_ok() && second.is_ok());
+    batcher.classify_message(&image).await.unwrap();
+
+    let inputs: Vec<_> = server
+        .requests_to("/v1/moderations")
+        .iter()
+        .map(|request| request.json()["input"].clone())
+        .collect();
+    assert_eq!(inputs.len(), 3);
+    assert!(inputs.contains(&serde_json::json!(["hello"])));
+    let images = inputs
+        .iter()
+        .filter(|input| input[1]["image_url"]["url"] == "https://i.imgur.com/cat.png")
+        .count();
+    assert_eq!(images, 2);
+    assert_eq!(batcher.metrics().snapshot().cache_hits, 0);
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use berry_lib::moderation::blocked_terms::{
+    BlockedTerm, BlockedTermError, BlockedTermStore, SyncReport, TermKind,
+};
+use berry_lib::moderation::strikes::LadderStep;
+use berry_lib::twitch::helix::{HelixClient, HelixError};
+use common::mock_http::MockHttp;
+
+fn store_with(terms: Vec<BlockedTerm>) -> BlockedTermStore {
+    let store = BlockedTermStore::in_memory();
+    for term in terms {
+        store.add("bar", term).unwrap();
+    }
+    store
+}
+
+fn blocked(store: &BlockedTermStore, text: &str) -> Option<String> {
+    store.find("bar", text).map(|term| term.pattern)
+}
+
+#[test]
+fn literal_terms_match_whole_words_in_any_case() {
+    let store = store_with(vec![BlockedTerm::literal("buy followers")]);
+    assert_eq!(
+        blocked(&store, "BUY FOLLOWERS today!"),
+        Some("buy followers".to_string())
+    );
+    assert_eq!(
+        blocked(&store, "(buy followers)"),
+        Some("buy followers".to_string())
+    );
+    assert_eq!(blocked(&store, "I won't buy followersx"), None);
+    assert_eq!(blocked(&store, "buy some followers"), None);
+}
+
+#[test]
+fn matching_sees_through_homoglyphs() {
+    let store = store_with(vec![BlockedTerm::literal("spam")]);
+    assert!(blocked(&store, "\u{0455}\u{0440}\u{0430}\u{043C} here").is_some());
+    assert!(blocked(&store, "\u{FF33}\u{FF30}\u{FF21}\u{FF2D}").is_some());
+    assert!(blocked(&store, "sp\u{200B}am").is_some());
+    assert!(blocked(&store, "SP4M!!").is_some());
+}
+
+#[test]
+fn terms_in_other_scripts_still_match() {
+    let store = store_with(vec![BlockedTerm::literal("пример")]);
+    assert!(blocked(&store, "это ПРИМЕР текста").is_some());
+    assert!(blocked(&store, "примеры").is_none());
+}
+
+#[test]
+fn wildcards_match_within_a_word() {
+    let store = store_with(vec![BlockedTerm::wildcard("free*coins")]);
+    assert!(blocked(&store, "get FREEBIECOINS now").is_some());
+    assert!(blocked(&store, "freecoins").is_some());
+    assert!(blocked(&store, "free coins").is_none());
+}
+
+#[test]
+fn regex_terms_match_the_original_and_the_skeleton() {
+    let store = store_with(vec![
+        BlockedTerm::regex(r"\b\d{3}[-. ]?\d{3}[-. ]?\d{4}\b"),
+        BlockedTerm::regex(r"rival-?stream\.(com|tv)"),
+    ]);
+    assert!(blocked(&store, "call me at 555-123-4567").is_some());
+    assert!(blocked(&store, "go to RIVALSTREAM.TV").is_some());
+    assert!(blocked(&store, "go to rivalѕtream.com").is_some());
+    assert!(blocked(&store, "my score is 5551234").is_none());
+}
+
+#[test]
+fn rejects_empty_and_invalid_terms() {
+    let store = BlockedTermStore::in_memory();
+    assert!(matches!(
+        store.add("bar", BlockedTerm::literal("  ")),
+        Err(BlockedTermError::EmptyTerm)
+    ));
+    assert!(matches!(
+        store.add("bar", BlockedTerm::regex("(unclosed")),
+        Err(BlockedTermError::InvalidPattern { .. })
+    ));
+    assert!(store.terms("bar").is_empty());
+}
+
+#[test]
+fn adding_a_term_again_replaces_its_punishment() {
+    let store = store_with(vec![BlockedTerm::literal("spam")]);
+    store
+        .add(
+            "#Bar",
+            BlockedTerm::literal("spam").with_punishment(LadderStep::timeout(600)),
+        )
+        .unwrap();
+
+    let terms = store.terms("bar");
+    assert_eq!(terms.len(), 1);
+    assert_eq!(terms[0].punishment, LadderStep::timeout(600));
+    assert!(store.find("baz", "spam").is_none());
+}
+
+#[test]
+fn terms_survive_a_reload() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blocked_terms.bin");
+
+    let store = BlockedTermStore::load_from(path.clone()).unwrap();
+    store.add("bar", BlockedTerm::wildcard("rival*")).unwrap();
+    store.add("bar", BlockedTerm::literal("spam")).unwrap();
+    store.remove("bar", "spam").unwrap();
+
+    let reloaded = BlockedTermStore::load_from(path).unwrap();
+    assert_eq!(reloaded.terms("bar"), vec![BlockedTerm::wildcard("rival*")]);
+    assert!(reloaded.find("bar", "watch rivalstream").is_some());
+}
+
+#[test]
+fn failed_save_leaves_terms_unchanged() {
+    let dir = tempfile::tempdir().unwrap();
+    let blocker = dir.path().join("blocker");
+    std::fs::write(&blocker, "not a directory").unwrap();
+
+    let store = BlockedTermStore::load_from(blocker.join("blocked_terms.bin")).unwrap();
+    assert!(matches!(
+        store.add("bar", BlockedTerm::literal("spam")),
+        Err(BlockedTermError::StorageError(_))
+    ));
+    assert!(store.find("bar", "spam").is_none());
+}
+
+#[tokio::test]
+async fn syncs_both_ways_with_twitch() {
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let store = store_with(vec![
+        BlockedTerm::literal("spam"),
+        BlockedTerm::regex(r"\d{3}-\d{4}"),
+        BlockedTerm {
+            twitch_id: Some("t3".to_string()),
+            ..BlockedTerm::literal("removed here")
+        },
+        BlockedTerm {
+            twitch_id: Some("t9".to_string()),
+            ..BlockedTerm::literal("removed on twitch")
+        },
+    ]);
+    store.remove("bar", "removed here").unwrap();
+
+    server.respond(
+        "GET",
+        "/moderation/blocked_terms",
+        200,
+        r#"{"data":[{"id":"t1","text":"rival*"},{"id":"t2","text":"other"}],"pagination":{}}"#,
+    );
+    server.respond(
+        "POST",
+        "/moderation/blocked_terms",
+        200,
+        r#"{"data":[{"id":"t4","text":"spam"}]}"#,
+    );
+    server.respond("DELETE", "/moderation/blocked_terms", 204, "");
+
+    let report = store.sync("bar", &helix, "1001").await.unwrap();
+    assert_eq!(
+        report,
+        SyncReport {
+            added_here: 2,
+            added_on_twitch: 1,
+            removed_here: 1,
+            removed_on_twitch: 1,
+        }
+    );
+
+    let requests = server.requests_to("/moderation/blocked_terms");
+    let delete = requests.iter().find(|r| r.method == "DELETE").unwrap();
+    assert!(delete.path.contains("broadcaster_id=1001"));
+    assert!(delete.path.contains("id=t3"));
+    let post = requests.iter().find(|r| r.method == "POST").unwrap();
+    assert_eq!(post.json()["text"], "spam");
+
+    let terms = store.terms("bar");
+    let summary: Vec<_> = terms
+        .iter()
+        .map(|term| (term.pattern.as_str(), term.kind, term.twitch_id.as_deref()))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("spam", TermKind::Literal, Some("t4")),
+            (r"\d{3}-\d{4}", TermKind::Regex, None),
+            ("rival*", TermKind::Wildcard, Some("t1")),
+            ("other", TermKind::Literal, Some("t2")),
+        ]
+    );
+
+    server.respond(
+        "GET",
+        "/moderation/blocked_terms",
+        200,
+        r#"{"data":[{"id":"t1","text":"rival*"},{"id":"t2","text":"other"},{"id":"t4","text":"spam"}]}"#,
+    );
+    let report = store.sync("bar", &helix, "1001").await.unwrap();
+    assert_eq!(report, SyncReport::default());
+    assert_eq!(store.terms("bar").len(), 4);
+}
+
+#[tokio::test]
+async fn sync_adopts_terms_already_on_twitch() {
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let store = store_with(vec![BlockedTerm::literal("Spam")]);
+
+    server.respond(
+        "GET",
+        "/moderation/blocked_terms",
+        200,
+        r#"{"data":[{"id":"t1","text":"spam"}]}"#,
+    );
+    let report = store.sync("bar", &helix, "1001").await.unwrap();
+
+    assert_eq!(report, SyncReport::default());
+    assert!(server
+        .requests()
+        .iter()
+        .all(|request| request.method == "GET"));
+    assert_eq!(store.terms("bar")[0].twitch_id.as_deref(), Some("t1"));
+}
+
+#[tokio::test]
+async fn sync_surfaces_helix_errors() {
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let store = store_with(vec![BlockedTerm::literal("spam")]);
+
+    server.respond(
+        "GET",
+        "/moderation/blocked_terms",
+        403,
+        r#"{"error":"Forbidden","status":403,"message":"Not a moderator"}"#,
+    );
+    assert!(matches!(
+        store.sync("bar", &helix, "1001").await,
+        Err(BlockedTermError::HelixError(HelixError::NotModerator))
+    ));
+    assert_eq!(store.terms("bar")[0].twitch_id, None);
+}
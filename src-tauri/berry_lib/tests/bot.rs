@@ -0,0 +1,1008 @@
+mod common;
+
+use async_trait::async_trait;
+use berry_lib::moderation::audit::{ActionResult, AuditLog, DecisionOrigin};
+use berry_lib::moderation::blocked_terms::{BlockedTerm, BlockedTermStore};
+use berry_lib::moderation::local::LocalClassifier;
+use berry_lib::moderation::policy::{CategoryRule, PolicyStore, RuleAction};
+use berry_lib::moderation::pool::PoolConfig;
+use berry_lib::moderation::provider::{FallbackProvider, ModerationProvider, ModerationResult};
+use berry_lib::moderation::review::ReviewQueue;
+use berry_lib::moderation::shadow::ShadowReport;
+use berry_lib::moderation::strikes::{LadderStep, StrikeLedger};
+use berry_lib::openai::moderation::{
+    ModerationCategory, ModerationError, OpenAiConfig, OpenAiProvider, PunishmentAction,
+};
+use berry_lib::twitch::bot::Bot;
+use berry_lib::twitch::channel::{ChannelSettings, PermissionLevel};
+use berry_lib::twitch::helix::HelixClient;
+use berry_lib::twitch::identity::{BotAccounts, ChatIdentity};
+use berry_lib::twitch::supervisor::{Backoff, ConnectionState};
+use common::fake_tmi::FakeTmi;
+use common::mock_http::MockHttp;
+use common::{eventually, flagged_response, moderation_response};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Flags every message for hate, after a delay.
+struct SlowProvider(Duration);
+
+#[async_trait]
+impl ModerationProvider for SlowProvider {
+    fn name(&self) -> &str {
+        "slow"
+    }
+
+    async fn classify(&self, _text: &str) -> Result<ModerationResult, ModerationError> {
+        tokio::time::sleep(self.0).await;
+        let mut result = ModerationResult {
+            flagged: true,
+            ..Default::default()
+        };
+        result.categories.hate = true;
+        result.scores.hate = 0.9;
+        Ok(result)
+    }
+}
+
+fn accounts() -> BotAccounts {
+    BotAccounts::new(ChatIdentity::authenticated("BerryBot", "99", "secret"))
+}
+
+fn bot(tmi: &FakeTmi, accounts: BotAccounts) -> Bot {
+    let mut bot = Bot::with_endpoint(accounts, &["bar"], tmi.endpoint())
+        .unwrap()
+        .with_backoff(Backoff::new(
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+        ));
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = false;
+    bot
+}
+
+fn spawn(mut bot: Bot) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let _ = bot.run().await;
+    })
+}
+
+async fn start(tmi: &mut FakeTmi, bot: Bot) -> JoinHandle<()> {
+    let handle = spawn(bot);
+    tmi.expect_line("JOIN #bar").await;
+    handle
+}
+
+#[tokio::test]
+async fn logs_in_as_validated_account_and_joins() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts());
+    let handle = start(&mut tmi, bot).await;
+
+    assert_eq!(
+        &tmi.received()[..4],
+        [
+            "CAP REQ :twitch.tv/tags twitch.tv/commands twitch.tv/membership",
+            "PASS oauth:secret",
+            "NICK berrybot",
+            "JOIN #bar",
+        ]
+    );
+    handle.abort();
+}
+
+#[tokio::test]
+async fn separate_bot_account_logs_into_chat() {
+    let mut tmi = FakeTmi::start().await;
+    let accounts = BotAccounts::new(ChatIdentity::authenticated("streamer", "1", "streamer"))
+        .with_bot(ChatIdentity::authenticated("helper", "2", "helper-token"));
+    let bot = bot(&tmi, accounts);
+    let handle = start(&mut tmi, bot).await;
+
+    let received = tmi.received();
+    assert!(received.contains(&"PASS oauth:helper-token".to_string()));
+    assert!(received.contains(&"NICK helper".to_string()));
+    handle.abort();
+}
+
+#[tokio::test]
+async fn answers_ping() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts());
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_ping("tmi.twitch.tv");
+    tmi.expect_line("PONG :tmi.twitch.tv").await;
+    handle.abort();
+}
+
+#[tokio::test]
+async fn replies_to_command() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts());
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_privmsg("bar", "viewer", "msg-1", "!hello");
+    tmi.expect_line("@reply-parent-msg-id=msg-1 PRIVMSG #bar :Hello from Rust! !hello")
+        .await;
+    handle.abort();
+}
+
+#[tokio::test]
+async fn ignores_plain_chat_and_user_notices() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts());
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_privmsg("bar", "viewer", "msg-1", "just chatting");
+    tmi.send(
+        "@badge-info=;badges=;display-name=Raider;login=raider;msg-id=raid;\
+         msg-param-displayName=Raider;msg-param-login=raider;msg-param-viewerCount=12;\
+         room-id=1;system-msg=12\\sraiders;tmi-sent-ts=1700000000000;user-id=5 \
+         :tmi.twitch.tv USERNOTICE #bar",
+    );
+    assert!(tmi.sync().await.is_empty());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn enforces_command_permissions() {
+    let mut tmi = FakeTmi::start().await;
+    let mut bot = bot(&tmi, accounts());
+    bot.channel_mut("bar")
+        .unwrap()
+        .settings
+        .command_permissions
+        .insert("hello".to_string(), PermissionLevel::Moderator);
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_privmsg("bar", "viewer", "msg-1", "!hello");
+    assert!(tmi.sync().await.is_empty());
+
+    tmi.send_privmsg_with_badges("bar", "helper", "msg-2", "moderator/1", "!ping");
+    tmi.expect_prefix("@reply-parent-msg-id=msg-2 PRIVMSG #bar")
+        .await;
+    handle.abort();
+}
+
+#[tokio::test]
+async fn joins_channels_at_runtime() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts());
+    let control = bot.handle();
+    let handle = start(&mut tmi, bot).await;
+
+    let settings = ChannelSettings {
+        moderation_enabled: false,
+        ..ChannelSettings::default()
+    };
+    control.join("Baz", settings);
+    tmi.expect_line("JOIN #baz").await;
+
+    tmi.send_privmsg("baz", "viewer", "msg-1", "!hello");
+    tmi.expect_prefix("@reply-parent-msg-id=msg-1 PRIVMSG #baz")
+        .await;
+
+    control.part("baz");
+    tmi.expect_line("PART #baz").await;
+    handle.abort();
+}
+
+#[tokio::test]
+async fn reconnects_when_asked() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts());
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_reconnect();
+    tmi.expect_line("PART #bar").await;
+    tmi.expect_line("NICK berrybot").await;
+    tmi.expect_line("JOIN #bar").await;
+    assert_eq!(tmi.connections(), 2);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn channel_changes_during_a_reconnect_do_not_interrupt_it() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts()).with_backoff(Backoff::new(
+        Duration::from_millis(200),
+        Duration::from_millis(200),
+    ));
+    let control = bot.handle();
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_reconnect();
+    tmi.expect_line("PART #bar").await;
+    control.join("baz", ChannelSettings::default());
+    control.part("baz");
+    control.join("qux", ChannelSettings::default());
+
+    tmi.expect_line("NICK berrybot").await;
+    tmi.expect_line("JOIN #bar").await;
+    tmi.expect_line("JOIN #qux").await;
+    tmi.sync().await;
+    assert_eq!(tmi.connections(), 2);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn reconnects_after_connection_drops() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts());
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.drop_client();
+    tmi.wait_for_connections(2).await;
+    tmi.expect_line("JOIN #bar").await;
+
+    tmi.send_privmsg("bar", "viewer", "msg-1", "!hello");
+    tmi.expect_prefix("@reply-parent-msg-id=msg-1 PRIVMSG #bar")
+        .await;
+    handle.abort();
+}
+
+#[tokio::test]
+async fn reports_connection_state_while_reconnecting() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, accounts()).with_backoff(Backoff::new(
+        Duration::from_millis(100),
+        Duration::from_millis(100),
+    ));
+    let mut state = bot.connection_state();
+    let handle = start(&mut tmi, bot).await;
+    state
+        .wait_for(|s| *s == ConnectionState::Connected)
+        .await
+        .unwrap();
+
+    tmi.drop_client();
+    let reconnecting = state
+        .wait_for(|s| matches!(s, ConnectionState::Reconnecting { .. }))
+        .await
+        .unwrap()
+        .clone();
+    assert!(matches!(
+        reconnecting,
+        ConnectionState::Reconnecting { attempt: 1, .. }
+    ));
+    state
+        .wait_for(|s| *s == ConnectionState::Connected)
+        .await
+        .unwrap();
+    assert_eq!(tmi.connections(), 2);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn anonymous_bot_only_reads() {
+    let mut tmi = FakeTmi::start().await;
+    let bot = bot(&tmi, BotAccounts::anonymous());
+    let handle = start(&mut tmi, bot).await;
+
+    let received = tmi.received();
+    assert!(!received.iter().any(|line| line.starts_with("PASS")));
+    assert!(received
+        .iter()
+        .any(|line| line.starts_with("NICK justinfan")));
+
+    tmi.send_privmsg("bar", "viewer", "msg-1", "!hello");
+    assert!(tmi.sync().await.is_empty());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn commands_do_not_wait_for_moderation() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let policies = PolicyStore::in_memory();
+    let mut policy = policies.get("bar");
+    policy.hate = CategoryRule::new(0.5, RuleAction::Delete);
+    policies.set("bar", policy).unwrap();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_provider(SlowProvider(Duration::from_millis(300)))
+        .with_helix(helix)
+        .with_policies(policies);
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond("DELETE", "/moderation/chat", 204, "");
+    tmi.send_privmsg("bar", "troll", "msg-1", "!hello");
+    tmi.expect_prefix("@reply-parent-msg-id=msg-1 PRIVMSG #bar")
+        .await;
+    assert!(server.requests_to("/moderation/chat").is_empty());
+
+    // The late decision still deletes the message it was made for.
+    eventually(|| server.requests_to("/moderation/chat").len() == 1).await;
+    assert!(server.requests_to("/moderation/chat")[0]
+        .path
+        .contains("message_id=msg-1"));
+    handle.abort();
+}
+
+#[tokio::test]
+async fn slow_decisions_time_out() {
+    let mut tmi = FakeTmi::start().await;
+    let audit = AuditLog::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_provider(SlowProvider(Duration::from_secs(30)))
+        .with_moderation_pool(PoolConfig {
+            timeout: Duration::from_millis(20),
+            ..PoolConfig::default()
+        })
+        .with_audit(audit.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_privmsg("bar", "troll", "msg-1", "hateful words");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tmi.send_privmsg("bar", "viewer", "msg-2", "!hello");
+    tmi.expect_prefix("@reply-parent-msg-id=msg-2 PRIVMSG #bar")
+        .await;
+    assert!(audit.is_empty());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn locally_caught_messages_do_not_run_commands() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let blocked_terms = BlockedTermStore::in_memory();
+    blocked_terms
+        .add("bar", BlockedTerm::literal("hello"))
+        .unwrap();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_provider(LocalClassifier::new())
+        .with_helix(helix)
+        .with_blocked_terms(blocked_terms);
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond("DELETE", "/moderation/chat", 204, "");
+    tmi.send_privmsg("bar", "troll", "msg-1", "!hello");
+    assert!(tmi.sync().await.is_empty());
+    assert_eq!(server.requests_to("/moderation/chat").len(), 1);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn flagged_messages_are_punished_through_helix() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+
+    let mut bot = bot(&tmi, accounts()).with_openai(config).with_helix(helix);
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(true, 0.9),
+    );
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    tmi.send_privmsg("bar", "troll", "msg-1", "hateful words");
+    eventually(|| server.requests_to("/moderation/bans").len() == 1).await;
+
+    let requests = server.requests_to("/v1/moderations");
+    assert_eq!(requests[0].header("authorization"), Some("Bearer test-key"));
+    assert_eq!(requests[0].json()["input"], "hateful words");
+
+    let bans = server.requests_to("/moderation/bans");
+    assert_eq!(
+        bans[0].path,
+        "/moderation/bans?broadcaster_id=1001&moderator_id=99"
+    );
+    assert_eq!(bans[0].json()["data"]["user_id"], "12345");
+    assert_eq!(bans[0].json()["data"]["duration"], 60);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn policy_changes_apply_to_running_bot() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let policies = PolicyStore::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_policies(policies.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(true, 0.9),
+    );
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    tmi.send_privmsg("bar", "troll", "msg-1", "hateful words");
+    eventually(|| server.requests_to("/moderation/bans").len() == 1).await;
+
+    let mut policy = policies.get("bar");
+    policy.hate = CategoryRule::new(0.5, RuleAction::Ban);
+    policies.set("bar", policy).unwrap();
+    tmi.send_privmsg("bar", "troll", "msg-2", "more hateful words");
+    eventually(|| server.requests_to("/moderation/bans").len() == 2).await;
+
+    let bans = server.requests_to("/moderation/bans");
+    assert_eq!(bans[0].json()["data"]["duration"], 60);
+    assert!(bans[1].json()["data"].get("duration").is_none());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn stricter_thresholds_act_on_unflagged_messages() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let policies = PolicyStore::in_memory();
+    let review = ReviewQueue::in_memory();
+
+    let mut policy = policies.get("bar");
+    policy.hate = CategoryRule::new(0.3, RuleAction::Ban);
+    policies.set("bar", policy).unwrap();
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_policies(policies)
+        .with_review_queue(review.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(false, 0.4),
+    );
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    tmi.send_privmsg("bar", "troll", "msg-1", "quietly hateful words");
+    eventually(|| server.requests_to("/moderation/bans").len() == 1).await;
+
+    let ban = &server.requests_to("/moderation/bans")[0];
+    assert!(ban.json()["data"].get("duration").is_none());
+    assert!(review.is_empty());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn keeps_moderating_offline_with_local_fallback() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let unreachable = OpenAiConfig::new("test-key", "http://127.0.0.1:1/v1/moderations");
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+
+    let mut bot = bot(&tmi, accounts())
+        .with_provider(FallbackProvider::new(
+            OpenAiProvider::new(unreachable),
+            LocalClassifier::new(),
+        ))
+        .with_helix(helix);
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    tmi.send_privmsg("bar", "troll", "msg-1", "I will kill you");
+    eventually(|| server.requests_to("/moderation/bans").len() == 1).await;
+
+    let bans = server.requests_to("/moderation/bans");
+    assert_eq!(bans[0].json()["data"]["duration"], 30);
+
+    tmi.send_privmsg("bar", "viewer", "msg-2", "!hello");
+    tmi.expect_prefix("@reply-parent-msg-id=msg-2 PRIVMSG #bar")
+        .await;
+    handle.abort();
+}
+
+#[tokio::test]
+async fn repeat_offenders_are_escalated_until_cleared() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let strikes = StrikeLedger::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_strikes(strikes.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    for (count, id) in ["msg-1", "msg-2", "msg-3", "msg-4"].iter().enumerate() {
+        server.respond(
+            "POST",
+            "/v1/moderations",
+            200,
+            &moderation_response(true, 0.9),
+        );
+        tmi.send_privmsg("bar", "troll", id, &format!("hateful words {}", id));
+        eventually(|| server.requests_to("/moderation/bans").len() == count + 1).await;
+    }
+
+    let durations: Vec<_> = server
+        .requests_to("/moderation/bans")
+        .iter()
+        .map(|request| request.json()["data"]["duration"].clone())
+        .collect();
+    assert_eq!(
+        durations,
+        vec![
+            serde_json::json!(60),
+            serde_json::json!(600),
+            serde_json::json!(3600),
+            serde_json::Value::Null
+        ]
+    );
+    assert_eq!(strikes.active("bar", "12345"), 4);
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(false, 0.01),
+    );
+    tmi.send_privmsg_with_badges(
+        "bar",
+        "helper",
+        "msg-5",
+        "moderator/1",
+        "!clearstrikes @troll",
+    );
+    let reply = tmi
+        .expect_prefix("@reply-parent-msg-id=msg-5 PRIVMSG #bar")
+        .await;
+    assert!(reply.ends_with(":Cleared 4 strikes for troll"));
+    assert_eq!(strikes.active("bar", "12345"), 0);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn strike_commands_are_for_moderators() {
+    let mut tmi = FakeTmi::start().await;
+    let strikes = StrikeLedger::in_memory();
+    strikes
+        .record("bar", "555", "troll", &ModerationCategory::Hate)
+        .unwrap();
+
+    let bot = bot(&tmi, accounts()).with_strikes(strikes.clone());
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_privmsg("bar", "viewer", "msg-1", "!clearstrikes troll");
+    assert!(tmi.sync().await.is_empty());
+    assert_eq!(strikes.active("bar", "555"), 1);
+
+    tmi.send_privmsg_with_badges("bar", "helper", "msg-2", "moderator/1", "!strikes troll");
+    let reply = tmi
+        .expect_prefix("@reply-parent-msg-id=msg-2 PRIVMSG #bar")
+        .await;
+    assert!(reply.ends_with(":troll has 1 active strikes"));
+    handle.abort();
+}
+
+#[tokio::test]
+async fn moderation_decisions_are_audited() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_audit(audit.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(true, 0.9),
+    );
+    server.respond(
+        "POST",
+        "/moderation/bans",
+        403,
+        r#"{"error":"Forbidden","status":403,"message":"Not a moderator"}"#,
+    );
+    tmi.send_privmsg("bar", "troll", "msg-1", "hateful words");
+    eventually(|| audit.len() == 1).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(true, 0.2),
+    );
+    tmi.send_privmsg("bar", "troll", "msg-2", "mildly hateful words");
+    eventually(|| audit.len() == 2).await;
+
+    let entries = audit.query(&Default::default());
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].channel, "bar");
+    assert_eq!(entries[0].origin, DecisionOrigin::Automatic);
+    assert_eq!(entries[0].message.message_id, "msg-1");
+    assert_eq!(entries[0].message.user_id, "12345");
+    assert_eq!(entries[0].scores.hate, 0.9);
+    assert_eq!(entries[0].threshold, Some(0.55));
+    assert_eq!(entries[0].action, PunishmentAction::Timeout(60));
+    assert_eq!(
+        entries[0].result,
+        ActionResult::Failed("Not a moderator in this channel".to_string())
+    );
+    assert_eq!(entries[1].action, PunishmentAction::None);
+    assert_eq!(entries[1].result, ActionResult::NoAction);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn shadow_mode_records_without_punishing() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+    let strikes = StrikeLedger::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_audit(audit.clone())
+        .with_strikes(strikes.clone());
+    let settings = &mut bot.channel_mut("bar").unwrap().settings;
+    settings.moderation_enabled = true;
+    settings.shadow_mode = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(true, 0.9),
+    );
+    tmi.send_privmsg("bar", "troll", "msg-1", "hateful words");
+    eventually(|| audit.len() == 1).await;
+
+    // A moderator times the user out and deletes another message.
+    tmi.send(
+        "@ban-duration=600;room-id=1001;target-user-id=12345;tmi-sent-ts=1700000000000 \
+         :tmi.twitch.tv CLEARCHAT #bar :troll",
+    );
+    tmi.send(
+        "@login=other;room-id=;target-msg-id=msg-9;tmi-sent-ts=1700000000000 \
+         :tmi.twitch.tv CLEARMSG #bar :spam spam",
+    );
+    tmi.sync().await;
+
+    assert!(server.requests_to("/moderation/bans").is_empty());
+    assert_eq!(strikes.active("bar", "12345"), 0);
+
+    let entries = audit.query(&Default::default());
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].action, PunishmentAction::Timeout(60));
+    assert_eq!(entries[0].result, ActionResult::Shadow);
+    assert_eq!(entries[1].origin, DecisionOrigin::Manual);
+    assert_eq!(entries[1].action, PunishmentAction::Timeout(600));
+    assert_eq!(entries[2].action, PunishmentAction::Delete);
+    assert_eq!(entries[2].message.message_id, "msg-9");
+
+    let report = ShadowReport::build(&entries, 300);
+    assert_eq!(report.matched.len(), 1);
+    assert!(report.matched[0].same_action);
+    assert_eq!(report.human_only[0].message.username, "other");
+    handle.abort();
+}
+
+#[tokio::test]
+async fn moderator_actions_outside_shadow_mode_are_not_audited() {
+    let mut tmi = FakeTmi::start().await;
+    let audit = AuditLog::in_memory();
+    let bot = bot(&tmi, accounts()).with_audit(audit.clone());
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send("@room-id=1001;target-user-id=12345 :tmi.twitch.tv CLEARCHAT #bar :troll");
+    tmi.sync().await;
+    assert!(audit.is_empty());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn exempt_chatters_skip_moderation() {
+    let mut tmi = FakeTmi::start().await;
+    let openai = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", openai.url()));
+
+    let mut bot = bot(&tmi, accounts()).with_openai(config);
+    let settings = &mut bot.channel_mut("bar").unwrap().settings;
+    settings.moderation_enabled = true;
+    settings.exemptions = settings.exemptions.clone().allow_user("12345");
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_privmsg("bar", "regular", "msg-1", "!hello");
+    tmi.expect_prefix("@reply-parent-msg-id=msg-1 PRIVMSG #bar")
+        .await;
+    tmi.send_privmsg_with_badges("bar", "helper", "msg-2", "moderator/1", "!ping");
+    tmi.expect_prefix("@reply-parent-msg-id=msg-2 PRIVMSG #bar")
+        .await;
+
+    assert!(openai.requests().is_empty());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn spam_is_caught_before_moderation() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_audit(audit.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond("DELETE", "/moderation/chat", 204, "");
+    tmi.send_privmsg(
+        "bar",
+        "troll",
+        "msg-1",
+        "buy followers at cheap-followers.com",
+    );
+    tmi.sync().await;
+
+    let deletes = server.requests_to("/moderation/chat");
+    assert_eq!(deletes.len(), 1);
+    assert!(deletes[0].path.contains("message_id=msg-1"));
+    assert!(server.requests_to("/v1/moderations").is_empty());
+
+    let entries = audit.query(&Default::default());
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message.category.as_str(), "spam/links");
+    assert_eq!(entries[0].action, PunishmentAction::Delete);
+    assert_eq!(entries[0].result, ActionResult::Applied);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn repeated_spam_does_not_earn_strikes() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let strikes = StrikeLedger::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_helix(helix)
+        .with_strikes(strikes.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond("DELETE", "/moderation/chat", 204, "");
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    let shouts = [
+        "WHY IS NOBODY LISTENING TO ME",
+        "I SAID STOP PLAYING LIKE THAT",
+        "THIS IS THE WORST STREAM EVER",
+        "EVERYONE IN CHAT IS WRONG TODAY",
+        "ONE MORE TIME FOR THE PEOPLE",
+    ];
+    for (i, text) in shouts.iter().enumerate() {
+        tmi.send_privmsg("bar", "shouter", &format!("msg-{}", i), text);
+    }
+    tmi.sync().await;
+
+    assert_eq!(server.requests_to("/moderation/chat").len(), shouts.len());
+    assert!(server.requests_to("/moderation/bans").is_empty());
+    assert_eq!(strikes.active("bar", "12345"), 0);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn permitted_chatters_may_post_a_link() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+
+    let mut bot = bot(&tmi, accounts()).with_openai(config).with_helix(helix);
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    tmi.send_privmsg_with_badges("bar", "helper", "msg-1", "moderator/1", "!permit @troll");
+    let reply = tmi
+        .expect_prefix("@reply-parent-msg-id=msg-1 PRIVMSG #bar")
+        .await;
+    assert!(reply.ends_with(":troll may post a link in the next 60 seconds"));
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(false, 0.01),
+    );
+    tmi.send_privmsg(
+        "bar",
+        "troll",
+        "msg-2",
+        "my art is at portfolio.example.com",
+    );
+    tmi.sync().await;
+    eventually(|| server.requests_to("/v1/moderations").len() == 1).await;
+
+    assert!(server.requests_to("/moderation/chat").is_empty());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn blocked_terms_are_punished_with_their_own_action() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+    let blocked_terms = BlockedTermStore::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_audit(audit.clone())
+        .with_blocked_terms(blocked_terms.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    // Terms added while the bot runs apply to the next message.
+    blocked_terms
+        .add(
+            "bar",
+            BlockedTerm::regex(r"\d{3}-\d{3}-\d{4}").with_punishment(LadderStep::timeout(600)),
+        )
+        .unwrap();
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    tmi.send_privmsg("bar", "troll", "msg-1", "her number is 555-123-4567");
+    tmi.sync().await;
+
+    let bans = server.requests_to("/moderation/bans");
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].json()["data"]["duration"], 600);
+    assert!(server.requests_to("/v1/moderations").is_empty());
+
+    let entries = audit.query(&Default::default());
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message.category.as_str(), "blocked_term");
+    assert_eq!(entries[0].threshold, None);
+    handle.abort();
+}
+
+#[tokio::test]
+async fn provider_sees_the_skeleton_but_logs_keep_the_original() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_audit(audit.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(true, 0.9),
+    );
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    tmi.send_privmsg("bar", "troll", "msg-1", "h\u{0430}\u{200B}teful w0rdsss");
+    eventually(|| audit.len() == 1).await;
+
+    let moderations = server.requests_to("/v1/moderations");
+    assert_eq!(moderations.len(), 1);
+    assert_eq!(moderations[0].json()["input"], "hateful words");
+
+    let entries = audit.query(&Default::default());
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message.text, "h\u{0430}\u{200B}teful w0rdsss");
+    handle.abort();
+}
+
+#[tokio::test]
+async fn borderline_messages_wait_for_review() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let review = ReviewQueue::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_review_queue(review.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(false, 0.01),
+    );
+    tmi.send_privmsg("bar", "viewer", "msg-1", "hello");
+    eventually(|| server.requests_to("/v1/moderations").len() == 1).await;
+
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &moderation_response(false, 0.5),
+    );
+    tmi.send_privmsg("bar", "troll", "msg-2", "borderline words");
+    eventually(|| review.len() == 1).await;
+
+    let item = &review.pending(Some("bar"))[0];
+    assert_eq!(item.room_id, "1001");
+    assert_eq!(item.message.message_id, "msg-2");
+    assert_eq!(item.message.category, ModerationCategory::Hate);
+    assert_eq!(item.threshold, Some(0.55));
+    assert_eq!(item.suggested, PunishmentAction::Timeout(60));
+    assert!(server.requests_to("/moderation/bans").is_empty());
+    handle.abort();
+}
+
+#[tokio::test]
+async fn acts_on_every_flagged_category() {
+    let mut tmi = FakeTmi::start().await;
+    let server = MockHttp::start().await;
+    let config = OpenAiConfig::new("test-key", &format!("{}/v1/moderations", server.url()));
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+
+    let mut bot = bot(&tmi, accounts())
+        .with_openai(config)
+        .with_helix(helix)
+        .with_audit(audit.clone());
+    bot.channel_mut("bar").unwrap().settings.moderation_enabled = true;
+    let handle = start(&mut tmi, bot).await;
+
+    // Violence alone earns 30 seconds, self-harm/intent earns 120.
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &flagged_response(&[("violence", 0.96), ("self-harm/intent", 0.96)]),
+    );
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    tmi.send_privmsg("bar", "troll", "msg-1", "dark words");
+    eventually(|| audit.len() == 1).await;
+
+    let bans = server.requests_to("/moderation/bans");
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].json()["data"]["duration"], 120);
+    let entries = audit.query(&Default::default());
+    assert_eq!(
+        entries[0].message.category,
+        ModerationCategory::SelfHarmIntent
+    );
+    assert_eq!(entries[0].threshold, Some(0.95));
+    assert_eq!(entries[0].action, PunishmentAction::Timeout(120));
+    handle.abort();
+}
@@ -0,0 +1,94 @@
+use berry_lib::openai::moderation::{ModerationCategories, ModerationCategory, ModerationScores};
+use serde_json::{json, Map, Value};
+
+/// Categories and scores as OpenAI sends them, with only `flagged` set.
+fn openai_json(flagged: &ModerationCategory) -> (Value, Value) {
+    let mut flags = Map::new();
+    let mut scores = Map::new();
+    for category in ModerationCategory::ALL {
+        flags.insert(category.as_str().to_string(), json!(category == *flagged));
+        let score = if category == *flagged { 0.9 } else { 0.01 };
+        scores.insert(category.as_str().to_string(), json!(score));
+    }
+    (Value::Object(flags), Value::Object(scores))
+}
+
+#[test]
+fn names_match_openai() {
+    for category in ModerationCategory::ALL {
+        let name = category.as_str();
+        assert_eq!(serde_json::to_value(&category).unwrap(), name);
+        assert_eq!(
+            serde_json::from_value::<ModerationCategory>(json!(name)).unwrap(),
+            category
+        );
+        assert_eq!(ModerationCategory::from_name(name), Some(category.clone()));
+        assert_eq!(category.to_string(), name);
+    }
+    assert_eq!(ModerationCategory::from_name("self_harm"), None);
+    assert_eq!(ModerationCategory::from_name("spam/links"), None);
+}
+
+#[test]
+fn every_category_is_read_from_responses() {
+    for category in ModerationCategory::ALL {
+        let (flags, scores) = openai_json(&category);
+        let flags: ModerationCategories = serde_json::from_value(flags).unwrap();
+        let scores: ModerationScores = serde_json::from_value(scores).unwrap();
+
+        assert_eq!(flags.flagged(), vec![category.clone()], "{}", category);
+        assert!(flags.get(&category));
+        assert_eq!(scores.get(&category), 0.9, "{}", category);
+    }
+}
+
+#[test]
+fn every_category_can_be_set() {
+    for category in ModerationCategory::ALL {
+        let mut flags = ModerationCategories::default();
+        flags.set(&category, true);
+        assert_eq!(flags.flagged(), vec![category.clone()]);
+        flags.set(&category, false);
+        assert!(flags.flagged().is_empty());
+
+        let mut scores = ModerationScores::default();
+        scores.set(&category, 0.5);
+        assert_eq!(scores.get(&category), 0.5);
+        scores.set(&category, 0.7);
+        assert_eq!(scores.get(&category), 0.7);
+        let json = serde_json::to_value(&scores).unwrap();
+        assert_eq!(json[category.as_str()], 0.7);
+    }
+}
+
+#[test]
+fn unknown_categories_are_kept_by_name() {
+    let illicit = ModerationCategory::from("illicit");
+    assert_eq!(illicit, ModerationCategory::Other("illicit".to_string()));
+    assert_eq!(ModerationCategory::from("hate"), ModerationCategory::Hate);
+    assert_eq!(serde_json::to_value(&illicit).unwrap(), "illicit");
+    assert_eq!(
+        serde_json::from_value::<ModerationCategory>(json!("illicit")).unwrap(),
+        illicit
+    );
+
+    let flags = ModerationCategories::from_names([
+        ("illicit".to_string(), true),
+        ("hate".to_string(), true),
+        ("illicit/violent".to_string(), false),
+    ]);
+    assert_eq!(
+        flags.flagged(),
+        vec![ModerationCategory::Hate, illicit.clone()]
+    );
+    assert!(!flags.get(&ModerationCategory::from("illicit/violent")));
+
+    let scores = ModerationScores::from_names([("illicit".to_string(), 0.8)]);
+    assert_eq!(scores.get(&illicit), 0.8);
+    assert_eq!(
+        scores.get(&ModerationCategory::from("illicit/violent")),
+        0.0
+    );
+    assert_eq!(scores.all().len(), 12);
+    assert_eq!(scores.all().last(), Some(&(illicit, 0.8)));
+}
@@ -0,0 +1,75 @@
+use berry_lib::twitch::channel::{ChannelSettings, PermissionLevel};
+use berry_lib::twitch::chat_event::ChatEvent;
+use berry_lib::twitch::irc_message::IrcMessage;
+use berry_lib::twitch::tags::Badges;
+
+fn event(line: &str) -> ChatEvent {
+    ChatEvent::from_irc(&IrcMessage::parse(line).unwrap())
+        .unwrap()
+        .unwrap()
+}
+
+#[test]
+fn events_carry_their_source_channel() {
+    assert_eq!(
+        event(":foo!foo@foo.tmi.twitch.tv PRIVMSG #partner_one :hi").channel(),
+        Some("partner_one")
+    );
+    assert_eq!(
+        event("@login=foo;target-msg-id=1 :tmi.twitch.tv CLEARMSG #partner_two :x").channel(),
+        Some("partner_two")
+    );
+    assert_eq!(
+        event(":tmi.twitch.tv NOTICE * :Login authentication failed").channel(),
+        None
+    );
+    assert_eq!(event(":tmi.twitch.tv RECONNECT").channel(), None);
+}
+
+#[test]
+fn permission_level_follows_highest_badge() {
+    assert_eq!(
+        PermissionLevel::of(&Badges::parse("broadcaster/1,subscriber/0", "")),
+        PermissionLevel::Broadcaster
+    );
+    assert_eq!(
+        PermissionLevel::of(&Badges::parse("moderator/1,vip/1", "")),
+        PermissionLevel::Moderator
+    );
+    assert_eq!(
+        PermissionLevel::of(&Badges::parse("vip/1", "")),
+        PermissionLevel::Vip
+    );
+    assert_eq!(
+        PermissionLevel::of(&Badges::parse("subscriber/6", "subscriber/7")),
+        PermissionLevel::Subscriber
+    );
+    assert_eq!(PermissionLevel::of(&Badges::default()), PermissionLevel::Everyone);
+}
+
+#[test]
+fn command_permissions_are_per_setting() {
+    let mut strict = ChannelSettings::default();
+    strict
+        .command_permissions
+        .insert("test".into(), PermissionLevel::Moderator);
+    let relaxed = ChannelSettings::default();
+
+    let viewer = Badges::default();
+    let moderator = Badges::parse("moderator/1", "");
+
+    assert!(!strict.can_use("test", &viewer));
+    assert!(strict.can_use("test", &moderator));
+    assert!(strict.can_use("ping", &viewer));
+    assert!(relaxed.can_use("test", &viewer));
+}
+
+#[test]
+fn default_permission_applies_to_unlisted_commands() {
+    let settings = ChannelSettings {
+        default_permission: PermissionLevel::Subscriber,
+        ..ChannelSettings::default()
+    };
+    assert!(!settings.can_use("ping", &Badges::default()));
+    assert!(settings.can_use("ping", &Badges::parse("subscriber/1", "")));
+}
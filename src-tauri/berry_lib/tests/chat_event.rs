@@ -0,0 +1,325 @@
+use berry_lib::twitch::chat_event::{
+    ChatEvent, ClearChatAction, GiftBombEvent, RaidEvent, SubEvent, SubTier, UserNoticeKind,
+};
+use berry_lib::twitch::irc_message::IrcMessage;
+use std::time::Duration;
+
+fn event(line: &str) -> ChatEvent {
+    let irc = IrcMessage::parse(line).unwrap();
+    ChatEvent::from_irc(&irc)
+        .unwrap()
+        .unwrap_or_else(|| panic!("no event for {line:?}"))
+}
+
+fn notice_kind(line: &str) -> UserNoticeKind {
+    match event(line) {
+        ChatEvent::UserNotice(notice) => notice.kind,
+        other => panic!("expected USERNOTICE, got {other:?}"),
+    }
+}
+
+#[test]
+fn privmsg_becomes_message() {
+    match event("@id=abc;user-id=1 :foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :hello") {
+        ChatEvent::Message(message) => {
+            assert_eq!(message.sender, "foo");
+            assert_eq!(message.id, "abc");
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn clearchat_distinguishes_clear_ban_and_timeout() {
+    match event("@room-id=1;tmi-sent-ts=1642715695392 :tmi.twitch.tv CLEARCHAT #dallas") {
+        ChatEvent::ClearChat(clear) => {
+            assert_eq!(clear.channel, "dallas");
+            assert_eq!(clear.action, ClearChatAction::ClearAll);
+            assert!(clear.sent_at.is_some());
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+
+    match event("@room-id=1;target-user-id=87654321 :tmi.twitch.tv CLEARCHAT #dallas :ronni") {
+        ChatEvent::ClearChat(clear) => assert_eq!(
+            clear.action,
+            ClearChatAction::Ban {
+                login: "ronni".into(),
+                user_id: "87654321".into(),
+            }
+        ),
+        other => panic!("unexpected {other:?}"),
+    }
+
+    match event("@ban-duration=350;room-id=1;target-user-id=87654321 :tmi.twitch.tv CLEARCHAT #dallas :ronni") {
+        ChatEvent::ClearChat(clear) => assert_eq!(
+            clear.action,
+            ClearChatAction::Timeout {
+                login: "ronni".into(),
+                user_id: "87654321".into(),
+                duration: Duration::from_secs(350),
+            }
+        ),
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn clearmsg_carries_target_message() {
+    match event("@login=foo;room-id=;target-msg-id=94e6c7ff;tmi-sent-ts=1642720582342 :tmi.twitch.tv CLEARMSG #bar :what a great day") {
+        ChatEvent::ClearMsg(clear) => {
+            assert_eq!(clear.channel, "bar");
+            assert_eq!(clear.login, "foo");
+            assert_eq!(clear.target_msg_id, "94e6c7ff");
+            assert_eq!(clear.text, "what a great day");
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn usernotice_sub() {
+    let kind = notice_kind("@login=ronni;msg-id=sub;msg-param-cumulative-months=1;msg-param-sub-plan=1000;user-id=1 :tmi.twitch.tv USERNOTICE #dallas");
+    assert_eq!(
+        kind,
+        UserNoticeKind::Sub(SubEvent {
+            tier: SubTier::Tier1,
+            months: 1,
+            streak_months: None,
+            subscriber: "ronni".into(),
+            gifted_by: None,
+        })
+    );
+}
+
+#[test]
+fn usernotice_resub_with_message() {
+    let line = "@display-name=ronni;login=ronni;msg-id=resub;msg-param-cumulative-months=6;msg-param-streak-months=2;msg-param-sub-plan=Prime;system-msg=ronni\\shas\\ssubscribed\\sfor\\s6\\smonths!;user-id=87654321 :tmi.twitch.tv USERNOTICE #dallas :Great stream -- keep it up!";
+    match event(line) {
+        ChatEvent::UserNotice(notice) => {
+            assert_eq!(notice.channel, "dallas");
+            assert_eq!(notice.user_id, "87654321");
+            assert_eq!(notice.system_msg, "ronni has subscribed for 6 months!");
+            assert_eq!(notice.text.as_deref(), Some("Great stream -- keep it up!"));
+            assert_eq!(
+                notice.kind,
+                UserNoticeKind::Resub(SubEvent {
+                    tier: SubTier::Prime,
+                    months: 6,
+                    streak_months: Some(2),
+                    subscriber: "ronni".into(),
+                    gifted_by: None,
+                })
+            );
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn usernotice_gift_sub_names_gifter_and_recipient() {
+    let kind = notice_kind("@login=tww2;msg-id=subgift;msg-param-months=1;msg-param-recipient-display-name=Mr_Woodchuck;msg-param-recipient-id=55554444;msg-param-recipient-user-name=mr_woodchuck;msg-param-sub-plan=2000 :tmi.twitch.tv USERNOTICE #forstycup");
+    assert_eq!(
+        kind,
+        UserNoticeKind::SubGift(SubEvent {
+            tier: SubTier::Tier2,
+            months: 1,
+            streak_months: None,
+            subscriber: "mr_woodchuck".into(),
+            gifted_by: Some("tww2".into()),
+        })
+    );
+
+    let kind = notice_kind("@login=ananonymousgifter;msg-id=anonsubgift;msg-param-recipient-user-name=someone;msg-param-sub-plan=3000 :tmi.twitch.tv USERNOTICE #forstycup");
+    match kind {
+        UserNoticeKind::SubGift(sub) => {
+            assert_eq!(sub.tier, SubTier::Tier3);
+            assert_eq!(sub.gifted_by, None);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn usernotice_gift_bomb() {
+    let kind = notice_kind("@login=generous;msg-id=submysterygift;msg-param-mass-gift-count=50;msg-param-sub-plan=1000 :tmi.twitch.tv USERNOTICE #forstycup");
+    assert_eq!(
+        kind,
+        UserNoticeKind::GiftBomb(GiftBombEvent {
+            tier: SubTier::Tier1,
+            count: 50,
+            gifted_by: "generous".into(),
+        })
+    );
+}
+
+#[test]
+fn usernotice_raid() {
+    let kind = notice_kind("@login=testchannel;msg-id=raid;msg-param-displayName=TestChannel;msg-param-login=testchannel;msg-param-viewerCount=15 :tmi.twitch.tv USERNOTICE #othertestchannel");
+    assert_eq!(
+        kind,
+        UserNoticeKind::Raid(RaidEvent {
+            from_login: "testchannel".into(),
+            from_display_name: "TestChannel".into(),
+            viewers: 15,
+        })
+    );
+}
+
+#[test]
+fn usernotice_announcement_and_unknown() {
+    let kind = notice_kind("@login=mod;msg-id=announcement;msg-param-color=PRIMARY :tmi.twitch.tv USERNOTICE #bar :Heads up!");
+    assert_eq!(
+        kind,
+        UserNoticeKind::Announcement {
+            color: "PRIMARY".into()
+        }
+    );
+
+    let kind = notice_kind("@login=x;msg-id=bitsbadgetier :tmi.twitch.tv USERNOTICE #bar");
+    assert_eq!(kind, UserNoticeKind::Other("bitsbadgetier".into()));
+}
+
+#[test]
+fn roomstate_keeps_partial_updates_partial() {
+    match event("@emote-only=0;followers-only=-1;r9k=0;room-id=12345678;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #bar") {
+        ChatEvent::RoomState(state) => {
+            assert_eq!(state.room_id, "12345678");
+            assert_eq!(state.emote_only, Some(false));
+            assert_eq!(state.followers_only, Some(-1));
+            assert_eq!(state.slow, Some(0));
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+
+    match event("@room-id=12345678;slow=10 :tmi.twitch.tv ROOMSTATE #bar") {
+        ChatEvent::RoomState(state) => {
+            assert_eq!(state.slow, Some(10));
+            assert_eq!(state.emote_only, None);
+            assert_eq!(state.subs_only, None);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn userstate_reports_moderator_status() {
+    match event("@badge-info=;badges=moderator/1;color=;display-name=BerryBot;emote-sets=0,33;mod=1;subscriber=0;user-type=mod :tmi.twitch.tv USERSTATE #bar") {
+        ChatEvent::UserState(state) => {
+            assert_eq!(state.channel, "bar");
+            assert!(state.moderator);
+            assert!(state.is_elevated());
+            assert_eq!(state.color, None);
+            assert_eq!(state.emote_sets, vec!["0", "33"]);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+
+    match event("@badges=broadcaster/1;mod=0 :tmi.twitch.tv USERSTATE #berrybot") {
+        ChatEvent::UserState(state) => {
+            assert!(!state.moderator);
+            assert!(state.is_elevated());
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn globaluserstate() {
+    match event("@badge-info=;badges=;color=#0D4200;display-name=BerryBot;emote-sets=0;turbo=0;user-id=12345678;user-type= :tmi.twitch.tv GLOBALUSERSTATE") {
+        ChatEvent::GlobalUserState(state) => {
+            assert_eq!(state.user_id, "12345678");
+            assert_eq!(state.display_name, "BerryBot");
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn notice_with_and_without_channel() {
+    match event("@msg-id=delete_message_success :tmi.twitch.tv NOTICE #bar :The message from foo is now deleted.") {
+        ChatEvent::Notice(notice) => {
+            assert_eq!(notice.channel.as_deref(), Some("bar"));
+            assert_eq!(notice.msg_id.as_deref(), Some("delete_message_success"));
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+
+    match event(":tmi.twitch.tv NOTICE * :Login authentication failed") {
+        ChatEvent::Notice(notice) => {
+            assert_eq!(notice.channel, None);
+            assert_eq!(notice.msg_id, None);
+            assert_eq!(notice.text, "Login authentication failed");
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn whisper() {
+    match event("@badges=;color=#8A2BE2;display-name=PetsgomOO;emotes=;message-id=306;thread-id=12345678_87654321;turbo=0;user-id=87654321;user-type= :petsgomoo!petsgomoo@petsgomoo.tmi.twitch.tv WHISPER berrybot :hello") {
+        ChatEvent::Whisper(whisper) => {
+            assert_eq!(whisper.from_login, "petsgomoo");
+            assert_eq!(whisper.from_user_id, "87654321");
+            assert_eq!(whisper.to_login, "berrybot");
+            assert_eq!(whisper.thread_id, "12345678_87654321");
+            assert_eq!(whisper.text, "hello");
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn join_and_part() {
+    match event(":ronni!ronni@ronni.tmi.twitch.tv JOIN #dallas") {
+        ChatEvent::Join(member) => {
+            assert_eq!(member.channel, "dallas");
+            assert_eq!(member.login, "ronni");
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+    assert!(matches!(
+        event(":ronni!ronni@ronni.tmi.twitch.tv PART #dallas"),
+        ChatEvent::Part(_)
+    ));
+}
+
+#[test]
+fn hosttarget_start_and_stop() {
+    match event(":tmi.twitch.tv HOSTTARGET #abc :xyz 10") {
+        ChatEvent::HostTarget(host) => {
+            assert_eq!(host.channel, "abc");
+            assert_eq!(host.target.as_deref(), Some("xyz"));
+            assert_eq!(host.viewers, Some(10));
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+    match event(":tmi.twitch.tv HOSTTARGET #abc :- 0") {
+        ChatEvent::HostTarget(host) => assert_eq!(host.target, None),
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn reconnect() {
+    assert!(matches!(event(":tmi.twitch.tv RECONNECT"), ChatEvent::Reconnect));
+}
+
+#[test]
+fn lines_without_events_are_skipped() {
+    for line in [
+        ":tmi.twitch.tv 001 berrybot :Welcome, GLHF!",
+        ":tmi.twitch.tv CAP * ACK :twitch.tv/tags",
+        "PING :tmi.twitch.tv",
+        ":berrybot.tmi.twitch.tv 353 berrybot = #bar :berrybot",
+    ] {
+        let irc = IrcMessage::parse(line).unwrap();
+        assert!(ChatEvent::from_irc(&irc).unwrap().is_none(), "{line}");
+    }
+}
+
+#[test]
+fn channel_commands_without_a_channel_are_errors() {
+    let irc = IrcMessage::parse(":tmi.twitch.tv CLEARCHAT").unwrap();
+    assert!(ChatEvent::from_irc(&irc).is_err());
+}
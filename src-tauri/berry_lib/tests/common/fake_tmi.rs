@@ -0,0 +1,226 @@
+//! An in-process stand-in for Twitch's chat server.
+//!
+//! It answers CAP, NICK, JOIN and PART the way TMI does, records every line
+//! the bot sends and lets a test inject PRIVMSG, USERNOTICE, PING or
+//! RECONNECT lines into the current connection.
+
+use berry_lib::twitch::twitch_api::ChatEndpoint;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const POLL: Duration = Duration::from_millis(5);
+
+enum Outgoing {
+    Line(String),
+    Close,
+}
+
+#[derive(Default)]
+struct State {
+    received: Vec<String>,
+    connections: usize,
+    client: Option<mpsc::UnboundedSender<Outgoing>>,
+}
+
+pub struct FakeTmi {
+    port: u16,
+    state: Arc<Mutex<State>>,
+    /// Index into `received` of the first line `expect` has not matched yet.
+    cursor: usize,
+    syncs: usize,
+}
+
+impl FakeTmi {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let accept_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, accept_state.clone()));
+            }
+        });
+
+        FakeTmi {
+            port,
+            state,
+            cursor: 0,
+            syncs: 0,
+        }
+    }
+
+    pub fn endpoint(&self) -> ChatEndpoint {
+        ChatEndpoint::plaintext("127.0.0.1", self.port)
+    }
+
+    /// Every line received so far, across all connections.
+    pub fn received(&self) -> Vec<String> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+
+    /// Writes a raw line to the connected bot.
+    pub fn send(&self, line: &str) {
+        let state = self.state.lock().unwrap();
+        let client = state.client.as_ref().expect("no client connected");
+        let _ = client.send(Outgoing::Line(line.to_string()));
+    }
+
+    pub fn send_privmsg(&self, channel: &str, sender: &str, id: &str, text: &str) {
+        self.send_privmsg_with_badges(channel, sender, id, "", text);
+    }
+
+    pub fn send_privmsg_with_badges(
+        &self,
+        channel: &str,
+        sender: &str,
+        id: &str,
+        badges: &str,
+        text: &str,
+    ) {
+        self.send(&format!(
+            "@badge-info=;badges={badges};color=#1E90FF;display-name={sender};emotes=;\
+             first-msg=0;id={id};mod=0;room-id=1001;tmi-sent-ts=1700000000000;user-id=12345;\
+             user-type= :{sender}!{sender}@{sender}.tmi.twitch.tv PRIVMSG #{channel} :{text}"
+        ));
+    }
+
+    pub fn send_ping(&self, token: &str) {
+        self.send(&format!("PING :{}", token));
+    }
+
+    pub fn send_reconnect(&self) {
+        self.send(":tmi.twitch.tv RECONNECT");
+    }
+
+    /// Hangs up on the connected bot.
+    pub fn drop_client(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(client) = state.client.take() {
+            let _ = client.send(Outgoing::Close);
+        }
+    }
+
+    /// Waits for a line matching `matches` after the last matched one and
+    /// returns it. Panics after five seconds.
+    pub async fn expect<F>(&mut self, matches: F) -> String
+    where
+        F: Fn(&str) -> bool,
+    {
+        let deadline = tokio::time::Instant::now() + TIMEOUT;
+        loop {
+            {
+                let state = self.state.lock().unwrap();
+                let found = state.received[self.cursor..]
+                    .iter()
+                    .position(|line| matches(line));
+                if let Some(offset) = found {
+                    self.cursor += offset + 1;
+                    return state.received[self.cursor - 1].clone();
+                }
+            }
+            if tokio::time::Instant::now() >= deadline {
+                panic!(
+                    "expected line not received, got: {:#?}",
+                    &self.received()[self.cursor..]
+                );
+            }
+            tokio::time::sleep(POLL).await;
+        }
+    }
+
+    pub async fn expect_line(&mut self, expected: &str) -> String {
+        self.expect(|line| line == expected).await
+    }
+
+    pub async fn expect_prefix(&mut self, prefix: &str) -> String {
+        self.expect(|line| line.starts_with(prefix)).await
+    }
+
+    /// Round-trips a PING so everything the bot sent before it has arrived,
+    /// and returns those lines.
+    pub async fn sync(&mut self) -> Vec<String> {
+        self.syncs += 1;
+        let token = format!("sync-{}", self.syncs);
+        let start = self.cursor;
+        self.send_ping(&token);
+        self.expect_line(&format!("PONG :{}", token)).await;
+        let received = self.received();
+        received[start..self.cursor - 1].to_vec()
+    }
+
+    pub async fn wait_for_connections(&self, count: usize) {
+        let deadline = tokio::time::Instant::now() + TIMEOUT;
+        while self.connections() < count || self.state.lock().unwrap().client.is_none() {
+            if tokio::time::Instant::now() >= deadline {
+                panic!("expected {} connections, got {}", count, self.connections());
+            }
+            tokio::time::sleep(POLL).await;
+        }
+    }
+}
+
+async fn serve(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    {
+        let mut state = state.lock().unwrap();
+        state.connections += 1;
+        state.client = Some(tx);
+    }
+
+    let mut nick = String::new();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = match line {
+                    Ok(Some(line)) => line,
+                    _ => break,
+                };
+                state.lock().unwrap().received.push(line.clone());
+                for reply in replies_to(&line, &mut nick) {
+                    if writer.write_all(format!("{}\r\n", reply).as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            outgoing = rx.recv() => match outgoing {
+                Some(Outgoing::Line(line)) => {
+                    if writer.write_all(format!("{}\r\n", line).as_bytes()).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Outgoing::Close) | None => break,
+            },
+        }
+    }
+}
+
+/// What TMI answers to a line from the client.
+fn replies_to(line: &str, nick: &mut String) -> Vec<String> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    match command {
+        "CAP" => match rest.strip_prefix("REQ :") {
+            Some(caps) => vec![format!(":tmi.twitch.tv CAP * ACK :{}", caps)],
+            None => vec![],
+        },
+        "NICK" => {
+            *nick = rest.to_string();
+            vec![format!(":tmi.twitch.tv 001 {} :Welcome, GLHF!", nick)]
+        }
+        "JOIN" | "PART" => vec![format!(
+            ":{nick}!{nick}@{nick}.tmi.twitch.tv {command} {rest}",
+        )],
+        _ => vec![],
+    }
+}
@@ -0,0 +1,82 @@
+// Each test binary uses only part of the support code.
+#![allow(dead_code)]
+
+pub mod fake_tmi;
+pub mod mock_http;
+
+/// An OpenAI moderation response where only `hate` can be flagged.
+pub fn moderation_response(flagged: bool, hate: f64) -> String {
+    moderation_results(&[moderation_result(flagged, hate)])
+}
+
+/// An OpenAI moderation response flagging every listed category with its
+/// score, e.g. `[("self-harm", 0.99)]`.
+pub fn flagged_response(scores: &[(&str, f64)]) -> String {
+    moderation_results(&[moderation_result_for(true, scores)])
+}
+
+/// A response to a batch, flagging `hate` for scores above 0.5.
+pub fn batch_response(hate: &[f64]) -> String {
+    let results: Vec<String> = hate
+        .iter()
+        .map(|score| moderation_result(*score > 0.5, *score))
+        .collect();
+    moderation_results(&results)
+}
+
+fn moderation_results(results: &[String]) -> String {
+    format!(
+        r#"{{"id": "modr-1", "model": "text-moderation-007", "results": [{}]}}"#,
+        results.join(", ")
+    )
+}
+
+fn moderation_result(flagged: bool, hate: f64) -> String {
+    moderation_result_for(flagged, &[("hate", hate)])
+}
+
+fn moderation_result_for(flagged: bool, listed: &[(&str, f64)]) -> String {
+    let categories = [
+        "harassment",
+        "harassment/threatening",
+        "hate",
+        "hate/threatening",
+        "self-harm",
+        "self-harm/instructions",
+        "self-harm/intent",
+        "sexual",
+        "sexual/minors",
+        "violence",
+        "violence/graphic",
+    ];
+    let score = |category: &str| listed.iter().find(|(c, _)| *c == category).map(|(_, s)| *s);
+    let flags: Vec<String> = categories
+        .iter()
+        .map(|c| format!(r#""{}": {}"#, c, flagged && score(c).is_some()))
+        .collect();
+    let scores: Vec<String> = categories
+        .iter()
+        .map(|c| format!(r#""{}": {}"#, c, score(c).unwrap_or(0.0001)))
+        .collect();
+    format!(
+        r#"{{"flagged": {}, "categories": {{{}}}, "category_scores": {{{}}}}}"#,
+        flagged,
+        flags.join(", "),
+        scores.join(", ")
+    )
+}
+
+/// Waits until `check` holds, e.g. for a moderation decision made off the
+/// chat loop. Panics after five seconds.
+pub async fn eventually<F>(check: F)
+where
+    F: Fn() -> bool,
+{
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+    while !check() {
+        if tokio::time::Instant::now() >= deadline {
+            panic!("condition not met in time");
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+}
@@ -0,0 +1,77 @@
+use berry_lib::moderation::exemptions::ExemptionRules;
+use berry_lib::twitch::chat_event::SubTier;
+use berry_lib::twitch::tags::Badges;
+
+#[test]
+fn exempts_broadcaster_moderators_and_vips_by_default() {
+    let rules = ExemptionRules::default();
+    assert!(rules.is_exempt("1", "streamer", &Badges::parse("broadcaster/1", "")));
+    assert!(rules.is_exempt("2", "helper", &Badges::parse("moderator/1", "")));
+    assert!(rules.is_exempt("3", "friend", &Badges::parse("vip/1", "")));
+    assert!(!rules.is_exempt("4", "viewer", &Badges::default()));
+    assert!(!rules.is_exempt(
+        "5",
+        "sub",
+        &Badges::parse("subscriber/3012", "subscriber/14")
+    ));
+}
+
+#[test]
+fn badge_exemptions_can_be_turned_off() {
+    let rules = ExemptionRules {
+        moderators: false,
+        ..Default::default()
+    };
+    assert!(!rules.is_exempt("2", "helper", &Badges::parse("moderator/1", "")));
+    assert!(!ExemptionRules::none().is_exempt(
+        "1",
+        "nightbot",
+        &Badges::parse("broadcaster/1", "")
+    ));
+}
+
+#[test]
+fn exempts_subscribers_by_tier_and_months() {
+    let tier2 = ExemptionRules {
+        subscriber_tier: Some(SubTier::Tier2),
+        ..Default::default()
+    };
+    assert!(tier2.is_exempt("1", "a", &Badges::parse("subscriber/2006", "subscriber/6")));
+    assert!(tier2.is_exempt("1", "a", &Badges::parse("subscriber/3000", "subscriber/1")));
+    assert!(!tier2.is_exempt("1", "a", &Badges::parse("subscriber/12", "subscriber/12")));
+
+    let loyal_tier2 = ExemptionRules {
+        subscriber_tier: Some(SubTier::Tier2),
+        subscriber_months: Some(12),
+        ..Default::default()
+    };
+    assert!(!loyal_tier2.is_exempt("1", "a", &Badges::parse("subscriber/2006", "subscriber/6")));
+    assert!(loyal_tier2.is_exempt("1", "a", &Badges::parse("subscriber/2012", "subscriber/13")));
+    assert!(!loyal_tier2.is_exempt("1", "a", &Badges::default()));
+}
+
+#[test]
+fn exempts_allowlisted_users_and_known_bots() {
+    let rules = ExemptionRules::none().allow_user("42").with_bot("MyBot");
+    assert!(rules.is_exempt("42", "anyone", &Badges::default()));
+    assert!(rules.is_exempt("7", "mybot", &Badges::default()));
+    assert!(!rules.is_exempt("7", "nightbot", &Badges::default()));
+    assert!(ExemptionRules::default().is_exempt("7", "Nightbot", &Badges::default()));
+}
+
+#[test]
+fn subscriber_tier_comes_from_badge_version() {
+    assert_eq!(
+        Badges::parse("subscriber/3024", "subscriber/24").subscriber_tier(),
+        Some(SubTier::Tier3)
+    );
+    assert_eq!(
+        Badges::parse("subscriber/2000", "").subscriber_tier(),
+        Some(SubTier::Tier2)
+    );
+    assert_eq!(
+        Badges::parse("founder/0", "founder/3").subscriber_tier(),
+        Some(SubTier::Tier1)
+    );
+    assert_eq!(Badges::default().subscriber_tier(), None);
+}
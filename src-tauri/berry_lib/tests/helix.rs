@@ -0,0 +1,245 @@
+mod common;
+
+use berry_lib::openai::moderation::{
+    execute_punishment, FlaggedMessage, ModerationCategory, PunishmentAction,
+};
+use berry_lib::twitch::helix::{HelixClient, HelixError};
+use common::mock_http::MockHttp;
+use std::time::Duration;
+
+async fn helix() -> (MockHttp, HelixClient) {
+    let server = MockHttp::start().await;
+    let client = HelixClient::new("client-id", "mod-token", "77").with_base_url(&server.url());
+    (server, client)
+}
+
+fn flagged(category: &str, score: f64) -> FlaggedMessage {
+    FlaggedMessage::new(
+        "troll",
+        "555",
+        "msg-1",
+        "bad words",
+        ModerationCategory::from(category),
+        score,
+    )
+}
+
+#[tokio::test]
+async fn bans_with_reason() {
+    let (server, helix) = helix().await;
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+
+    helix.ban_user("1001", "555", "spam").await.unwrap();
+
+    let request = &server.requests_to("/moderation/bans")[0];
+    assert_eq!(
+        request.path,
+        "/moderation/bans?broadcaster_id=1001&moderator_id=77"
+    );
+    assert_eq!(request.header("authorization"), Some("Bearer mod-token"));
+    assert_eq!(request.header("client-id"), Some("client-id"));
+    assert_eq!(request.json()["data"]["user_id"], "555");
+    assert_eq!(request.json()["data"]["reason"], "spam");
+    assert!(request.json()["data"].get("duration").is_none());
+}
+
+#[tokio::test]
+async fn times_out_for_duration() {
+    let (server, helix) = helix().await;
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+
+    helix
+        .timeout_user("1001", "555", Duration::from_secs(600), "caps")
+        .await
+        .unwrap();
+
+    let request = &server.requests_to("/moderation/bans")[0];
+    assert_eq!(request.json()["data"]["duration"], 600);
+    assert_eq!(request.json()["data"]["reason"], "caps");
+}
+
+#[tokio::test]
+async fn deletes_message_by_id() {
+    let (server, helix) = helix().await;
+    server.respond("DELETE", "/moderation/chat", 204, "");
+
+    helix.delete_message("1001", "msg-1").await.unwrap();
+
+    let request = &server.requests_to("/moderation/chat")[0];
+    assert_eq!(request.method, "DELETE");
+    assert_eq!(
+        request.path,
+        "/moderation/chat?broadcaster_id=1001&moderator_id=77&message_id=msg-1"
+    );
+}
+
+#[tokio::test]
+async fn sends_warning() {
+    let (server, helix) = helix().await;
+    server.respond("POST", "/moderation/warnings", 200, r#"{"data":[]}"#);
+
+    helix.warn_user("1001", "555", "be nice").await.unwrap();
+
+    let request = &server.requests_to("/moderation/warnings")[0];
+    assert_eq!(request.json()["data"]["user_id"], "555");
+    assert_eq!(request.json()["data"]["reason"], "be nice");
+}
+
+async fn error_for(status: u16, message: &str) -> HelixError {
+    let (server, helix) = helix().await;
+    let body = format!(
+        r#"{{"error":"Error","status":{},"message":"{}"}}"#,
+        status, message
+    );
+    server.respond("POST", "/moderation/bans", status, &body);
+    helix.ban_user("1001", "555", "spam").await.unwrap_err()
+}
+
+#[tokio::test]
+async fn reports_missing_scope() {
+    match error_for(401, "Missing scope: moderator:manage:banned_users").await {
+        HelixError::MissingScope(message) => assert!(message.contains("banned_users")),
+        e => panic!("unexpected error: {e}"),
+    }
+}
+
+#[tokio::test]
+async fn reports_invalid_token() {
+    assert!(matches!(
+        error_for(401, "Invalid OAuth token").await,
+        HelixError::InvalidToken
+    ));
+}
+
+#[tokio::test]
+async fn reports_missing_moderator_role() {
+    assert!(matches!(
+        error_for(
+            403,
+            "The user in moderator_id is not one of the broadcaster's moderators"
+        )
+        .await,
+        HelixError::NotModerator
+    ));
+}
+
+#[tokio::test]
+async fn reports_protected_target() {
+    assert!(matches!(
+        error_for(
+            400,
+            "The user specified in the user_id field may not be banned."
+        )
+        .await,
+        HelixError::TargetProtected(_)
+    ));
+}
+
+#[tokio::test]
+async fn reports_rate_limit_and_other_errors() {
+    assert!(matches!(
+        error_for(429, "Too Many Requests").await,
+        HelixError::RateLimited
+    ));
+    match error_for(500, "Internal Server Error").await {
+        HelixError::Api { status, message } => {
+            assert_eq!(status, 500);
+            assert_eq!(message, "Internal Server Error");
+        }
+        e => panic!("unexpected error: {e}"),
+    }
+}
+
+#[tokio::test]
+async fn punishment_times_out_with_category_reason() {
+    let (server, helix) = helix().await;
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+
+    execute_punishment(
+        &helix,
+        "1001",
+        &flagged("hate", 0.9),
+        PunishmentAction::Timeout(60),
+    )
+    .await
+    .unwrap();
+
+    let request = &server.requests_to("/moderation/bans")[0];
+    assert_eq!(request.json()["data"]["user_id"], "555");
+    assert_eq!(request.json()["data"]["duration"], 60);
+    assert_eq!(
+        request.json()["data"]["reason"],
+        "Automated moderation: hate"
+    );
+}
+
+#[tokio::test]
+async fn punishment_deletes_the_message() {
+    let (server, helix) = helix().await;
+    server.respond("DELETE", "/moderation/chat", 204, "");
+
+    execute_punishment(
+        &helix,
+        "1001",
+        &flagged("sexual", 0.95),
+        PunishmentAction::Delete,
+    )
+    .await
+    .unwrap();
+
+    let deletes = server.requests_to("/moderation/chat");
+    assert_eq!(deletes.len(), 1);
+    assert!(deletes[0].path.ends_with("message_id=msg-1"));
+}
+
+#[tokio::test]
+async fn punishment_warns_the_user() {
+    let (server, helix) = helix().await;
+    server.respond("POST", "/moderation/warnings", 200, r#"{"data":[]}"#);
+
+    execute_punishment(
+        &helix,
+        "1001",
+        &flagged("harassment", 0.99),
+        PunishmentAction::Warn,
+    )
+    .await
+    .unwrap();
+
+    let request = &server.requests_to("/moderation/warnings")[0];
+    assert_eq!(
+        request.json()["data"]["reason"],
+        "Automated moderation: harassment"
+    );
+}
+
+#[tokio::test]
+async fn no_punishment_never_calls_twitch() {
+    let (server, helix) = helix().await;
+
+    execute_punishment(
+        &helix,
+        "1001",
+        &flagged("hate", 0.2),
+        PunishmentAction::None,
+    )
+    .await
+    .unwrap();
+
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn punishment_surfaces_helix_errors() {
+    let (server, helix) = helix().await;
+    server.respond(
+        "POST",
+        "/moderation/bans",
+        401,
+        r#"{"error":"Unauthorized","status":401,"message":"Invalid OAuth token"}"#,
+    );
+
+    let result =
+        execute_punishment(&helix, "1001", &flagged("hate", 0.9), PunishmentAction::Ban).await;
+    assert!(matches!(result, Err(HelixError::InvalidToken)));
+}
@@ -0,0 +1,66 @@
+use berry_lib::twitch::identity::{BotAccounts, ChatIdentity, TokenInfo};
+use berry_lib::twitch::twitch_api::{TwitchChatAPI, TwitchError};
+
+#[test]
+fn anonymous_login_is_justinfan() {
+    let identity = ChatIdentity::anonymous();
+    let number = identity.login().strip_prefix("justinfan").unwrap();
+    assert!(number.parse::<u32>().is_ok());
+    assert!(identity.is_read_only());
+    assert_eq!(identity.access_token(), None);
+    assert_eq!(identity.user_id(), None);
+}
+
+#[test]
+fn authenticated_login_is_lowercased() {
+    let identity = ChatIdentity::authenticated("BerryBot", "123", "token");
+    assert_eq!(identity.login(), "berrybot");
+    assert_eq!(identity.user_id(), Some("123"));
+    assert_eq!(identity.access_token(), Some("token"));
+    assert!(!identity.is_read_only());
+}
+
+#[test]
+fn bot_account_takes_over_chat() {
+    let broadcaster = ChatIdentity::authenticated("streamer", "1", "streamer-token");
+    let accounts = BotAccounts::new(broadcaster.clone());
+    assert_eq!(accounts.chat(), &broadcaster);
+
+    let accounts = accounts.with_bot(ChatIdentity::authenticated("berrybot", "2", "bot-token"));
+    assert_eq!(accounts.chat().login(), "berrybot");
+    assert_eq!(accounts.api_token(), Some("streamer-token"));
+}
+
+#[test]
+fn anonymous_accounts_have_no_api_token() {
+    let accounts = BotAccounts::anonymous();
+    assert!(accounts.chat().is_read_only());
+    assert_eq!(accounts.api_token(), None);
+}
+
+#[test]
+fn parses_validation_response() {
+    let body = r#"{
+        "client_id": "wbmytr93xzw8zbg0p1izqyzzc5mbiz",
+        "login": "twitchdev",
+        "scopes": ["channel:read:subscriptions"],
+        "user_id": "141981764",
+        "expires_in": 5520838
+    }"#;
+    let info: TokenInfo = serde_json::from_str(body).unwrap();
+    assert_eq!(info.login, "twitchdev");
+    assert_eq!(info.user_id, "141981764");
+    assert_eq!(info.scopes, vec!["channel:read:subscriptions"]);
+    assert_eq!(info.expires_in, 5520838);
+}
+
+#[tokio::test]
+async fn read_only_connection_refuses_to_send() {
+    let mut api = TwitchChatAPI::new(ChatIdentity::anonymous(), &["bar"]).unwrap();
+    assert!(api.is_read_only());
+    assert!(matches!(
+        api.send_message("bar", "hello").await,
+        Err(TwitchError::ReadOnly)
+    ));
+    assert_eq!(api.pending_outbound(), 0);
+}
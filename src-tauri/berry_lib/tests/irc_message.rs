@@ -0,0 +1,343 @@
+use berry_lib::twitch::irc_message::{unescape_tag_value, IrcMessage, IrcPrefix};
+use berry_lib::twitch::twitch_api::TwitchMessage;
+
+fn parse(line: &str) -> IrcMessage {
+    IrcMessage::parse(line).unwrap_or_else(|e| panic!("failed to parse {line:?}: {e:?}"))
+}
+
+#[test]
+fn parses_ping() {
+    let msg = parse("PING :tmi.twitch.tv\r\n");
+    assert!(msg.tags.is_empty());
+    assert_eq!(msg.prefix, None);
+    assert_eq!(msg.command, "PING");
+    assert!(msg.params.is_empty());
+    assert_eq!(msg.trailing(), Some("tmi.twitch.tv"));
+}
+
+#[test]
+fn parses_server_numeric_welcome() {
+    let msg = parse(":tmi.twitch.tv 001 berrybot :Welcome, GLHF!");
+    assert_eq!(
+        msg.prefix,
+        Some(IrcPrefix {
+            nick: None,
+            user: None,
+            host: Some("tmi.twitch.tv".into()),
+        })
+    );
+    assert_eq!(msg.command, "001");
+    assert_eq!(msg.params, vec!["berrybot"]);
+    assert_eq!(msg.trailing(), Some("Welcome, GLHF!"));
+}
+
+#[test]
+fn parses_motd_numeric_with_trailing_gt() {
+    let msg = parse(":tmi.twitch.tv 372 berrybot :You are in a maze of twisty passages, all alike.");
+    assert_eq!(msg.command, "372");
+    assert_eq!(msg.param(0), Some("berrybot"));
+    assert_eq!(msg.trailing(), Some("You are in a maze of twisty passages, all alike."));
+
+    let msg = parse(":tmi.twitch.tv 376 berrybot :>");
+    assert_eq!(msg.trailing(), Some(">"));
+}
+
+#[test]
+fn parses_cap_ack() {
+    let msg = parse(":tmi.twitch.tv CAP * ACK :twitch.tv/tags twitch.tv/commands twitch.tv/membership");
+    assert_eq!(msg.command, "CAP");
+    assert_eq!(msg.params, vec!["*", "ACK"]);
+    assert_eq!(
+        msg.trailing(),
+        Some("twitch.tv/tags twitch.tv/commands twitch.tv/membership")
+    );
+}
+
+#[test]
+fn parses_join_without_trailing() {
+    let msg = parse(":ronni!ronni@ronni.tmi.twitch.tv JOIN #dallas");
+    assert_eq!(
+        msg.prefix,
+        Some(IrcPrefix {
+            nick: Some("ronni".into()),
+            user: Some("ronni".into()),
+            host: Some("ronni.tmi.twitch.tv".into()),
+        })
+    );
+    assert_eq!(msg.nick(), Some("ronni"));
+    assert_eq!(msg.command, "JOIN");
+    assert_eq!(msg.params, vec!["#dallas"]);
+    assert_eq!(msg.trailing, None);
+}
+
+#[test]
+fn parses_names_list() {
+    let msg = parse(":ronni.tmi.twitch.tv 353 ronni = #dallas :ronni fred wilma");
+    assert_eq!(msg.command, "353");
+    assert_eq!(msg.params, vec!["ronni", "=", "#dallas"]);
+    assert_eq!(msg.trailing(), Some("ronni fred wilma"));
+}
+
+#[test]
+fn parses_untagged_privmsg() {
+    let msg = parse(":foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :bleedPurple");
+    assert_eq!(msg.nick(), Some("foo"));
+    assert_eq!(msg.command, "PRIVMSG");
+    assert_eq!(msg.params, vec!["#bar"]);
+    assert_eq!(msg.trailing(), Some("bleedPurple"));
+}
+
+#[test]
+fn keeps_colons_and_spaces_inside_trailing() {
+    let msg = parse(":foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :time is 12:30 :) see https://example.com:8080/x");
+    assert_eq!(
+        msg.trailing(),
+        Some("time is 12:30 :) see https://example.com:8080/x")
+    );
+}
+
+#[test]
+fn keeps_empty_trailing() {
+    let msg = parse(":foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :");
+    assert_eq!(msg.trailing(), Some(""));
+}
+
+#[test]
+fn parses_tagged_privmsg() {
+    let msg = parse(
+        "@badge-info=;badges=turbo/1;color=#0D4200;display-name=ronni;emotes=25:0-4,12-16/1902:6-10;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;mod=0;room-id=1337;subscriber=0;tmi-sent-ts=1507246572675;turbo=1;user-id=1337;user-type=global_mod :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #ronni :Kappa Keepo Kappa\r\n",
+    );
+    assert_eq!(msg.tag("badge-info"), Some(""));
+    assert_eq!(msg.tag("badges"), Some("turbo/1"));
+    assert_eq!(msg.tag("color"), Some("#0D4200"));
+    assert_eq!(msg.tag("display-name"), Some("ronni"));
+    assert_eq!(msg.tag("emotes"), Some("25:0-4,12-16/1902:6-10"));
+    assert_eq!(msg.tag("id"), Some("b34ccfc7-4977-403a-8a94-33c6bac34fb8"));
+    assert_eq!(msg.tag("tmi-sent-ts"), Some("1507246572675"));
+    assert_eq!(msg.tag("user-id"), Some("1337"));
+    assert_eq!(msg.tag("user-type"), Some("global_mod"));
+    assert_eq!(msg.tags.len(), 13);
+    assert_eq!(msg.nick(), Some("ronni"));
+    assert_eq!(msg.command, "PRIVMSG");
+    assert_eq!(msg.params, vec!["#ronni"]);
+    assert_eq!(msg.trailing(), Some("Kappa Keepo Kappa"));
+}
+
+#[test]
+fn parses_bits_privmsg() {
+    let msg = parse(
+        "@badge-info=;badges=staff/1,bits/1000;bits=100;color=;display-name=ronni;emotes=;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;mod=0;room-id=12345678;subscriber=0;tmi-sent-ts=1507246572675;turbo=1;user-id=12345678;user-type=staff :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #ronni :cheer100",
+    );
+    assert_eq!(msg.tag("bits"), Some("100"));
+    assert_eq!(msg.tag("color"), Some(""));
+    assert_eq!(msg.tag("emotes"), Some(""));
+    assert_eq!(msg.trailing(), Some("cheer100"));
+}
+
+#[test]
+fn parses_reply_privmsg_with_escaped_parent_body() {
+    let msg = parse(
+        "@badge-info=;badges=;client-nonce=abc;color=#1E90FF;display-name=Viewer;emotes=;first-msg=0;flags=;id=7a1b;mod=0;reply-parent-display-name=Streamer;reply-parent-msg-body=hello\\sthere\\:\\sfriend;reply-parent-msg-id=3c2d;reply-parent-user-id=42;reply-parent-user-login=streamer;returning-chatter=0;room-id=42;subscriber=0;tmi-sent-ts=1700000000000;turbo=0;user-id=77;user-type= :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #streamer :@Streamer hi back",
+    );
+    assert_eq!(msg.tag("reply-parent-msg-body"), Some("hello there; friend"));
+    assert_eq!(msg.tag("reply-parent-msg-id"), Some("3c2d"));
+    assert_eq!(msg.tag("user-type"), Some(""));
+    assert_eq!(msg.trailing(), Some("@Streamer hi back"));
+}
+
+#[test]
+fn parses_action_privmsg() {
+    let msg = parse(":foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :\u{1}ACTION waves\u{1}");
+    assert_eq!(msg.trailing(), Some("\u{1}ACTION waves\u{1}"));
+}
+
+#[test]
+fn parses_clearchat_ban_and_clear() {
+    let msg = parse("@room-id=12345678;target-user-id=87654321;tmi-sent-ts=1642715756806 :tmi.twitch.tv CLEARCHAT #dallas :ronni");
+    assert_eq!(msg.command, "CLEARCHAT");
+    assert_eq!(msg.params, vec!["#dallas"]);
+    assert_eq!(msg.trailing(), Some("ronni"));
+    assert_eq!(msg.tag("target-user-id"), Some("87654321"));
+
+    let msg = parse("@room-id=12345678;tmi-sent-ts=1642715695392 :tmi.twitch.tv CLEARCHAT #dallas");
+    assert_eq!(msg.params, vec!["#dallas"]);
+    assert_eq!(msg.trailing, None);
+}
+
+#[test]
+fn parses_clearchat_timeout() {
+    let msg = parse("@ban-duration=350;room-id=12345678;target-user-id=87654321;tmi-sent-ts=1642719320727 :tmi.twitch.tv CLEARCHAT #dallas :ronni");
+    assert_eq!(msg.tag("ban-duration"), Some("350"));
+}
+
+#[test]
+fn parses_clearmsg() {
+    let msg = parse("@login=foo;room-id=;target-msg-id=94e6c7ff-bf98-4faa-af5d-7ad633a158a9;tmi-sent-ts=1642720582342 :tmi.twitch.tv CLEARMSG #bar :what a great day");
+    assert_eq!(msg.command, "CLEARMSG");
+    assert_eq!(msg.tag("login"), Some("foo"));
+    assert_eq!(msg.tag("room-id"), Some(""));
+    assert_eq!(
+        msg.tag("target-msg-id"),
+        Some("94e6c7ff-bf98-4faa-af5d-7ad633a158a9")
+    );
+    assert_eq!(msg.trailing(), Some("what a great day"));
+}
+
+#[test]
+fn parses_resub_usernotice_with_escaped_system_msg() {
+    let msg = parse(
+        "@badge-info=;badges=staff/1,broadcaster/1,turbo/1;color=#008000;display-name=ronni;emotes=;id=db25007f-7a18-43eb-9379-80131e44d633;login=ronni;mod=0;msg-id=resub;msg-param-cumulative-months=6;msg-param-streak-months=2;msg-param-should-share-streak=1;msg-param-sub-plan=Prime;msg-param-sub-plan-name=Prime;room-id=12345678;subscriber=1;system-msg=ronni\\shas\\ssubscribed\\sfor\\s6\\smonths!;tmi-sent-ts=1507246572675;turbo=1;user-id=87654321;user-type=staff :tmi.twitch.tv USERNOTICE #dallas :Great stream -- keep it up!",
+    );
+    assert_eq!(msg.command, "USERNOTICE");
+    assert_eq!(msg.tag("msg-id"), Some("resub"));
+    assert_eq!(msg.tag("msg-param-sub-plan"), Some("Prime"));
+    assert_eq!(msg.tag("system-msg"), Some("ronni has subscribed for 6 months!"));
+    assert_eq!(msg.trailing(), Some("Great stream -- keep it up!"));
+}
+
+#[test]
+fn parses_raid_usernotice_without_trailing() {
+    let msg = parse(
+        "@badge-info=;badges=turbo/1;color=#9ACD32;display-name=TestChannel;emotes=;id=3d830f12-795c-447d-af3c-ea05e40fbddb;login=testchannel;mod=0;msg-id=raid;msg-param-displayName=TestChannel;msg-param-login=testchannel;msg-param-viewerCount=15;room-id=33332222;subscriber=0;system-msg=15\\sraiders\\sfrom\\sTestChannel\\shave\\sjoined\\n!;tmi-sent-ts=1507246572675;turbo=1;user-id=123456;user-type= :tmi.twitch.tv USERNOTICE #othertestchannel",
+    );
+    assert_eq!(msg.tag("msg-param-viewerCount"), Some("15"));
+    assert_eq!(
+        msg.tag("system-msg"),
+        Some("15 raiders from TestChannel have joined\n!")
+    );
+    assert_eq!(msg.trailing, None);
+}
+
+#[test]
+fn parses_roomstate() {
+    let msg = parse("@emote-only=0;followers-only=-1;r9k=0;room-id=12345678;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #bar");
+    assert_eq!(msg.command, "ROOMSTATE");
+    assert_eq!(msg.tag("followers-only"), Some("-1"));
+    assert_eq!(msg.params, vec!["#bar"]);
+}
+
+#[test]
+fn parses_userstate_and_globaluserstate() {
+    let msg = parse("@badge-info=;badges=staff/1;color=#0D4200;display-name=ronni;emote-sets=0,33,50,237,793,2126,3517,4578,5569,9400,10337,12239;mod=1;subscriber=1;turbo=1;user-type=staff :tmi.twitch.tv USERSTATE #dallas");
+    assert_eq!(msg.command, "USERSTATE");
+    assert_eq!(msg.tag("mod"), Some("1"));
+
+    let msg = parse("@badge-info=subscriber/8;badges=subscriber/6;color=#0D4200;display-name=dallas;emote-sets=0,33,50,237,793,2126,3517,4578,5569,9400,10337,12239;turbo=0;user-id=12345678;user-type=admin :tmi.twitch.tv GLOBALUSERSTATE");
+    assert_eq!(msg.command, "GLOBALUSERSTATE");
+    assert!(msg.params.is_empty());
+    assert_eq!(msg.tag("badge-info"), Some("subscriber/8"));
+}
+
+#[test]
+fn parses_notice() {
+    let msg = parse("@msg-id=delete_message_success :tmi.twitch.tv NOTICE #bar :The message from foo is now deleted.");
+    assert_eq!(msg.command, "NOTICE");
+    assert_eq!(msg.tag("msg-id"), Some("delete_message_success"));
+    assert_eq!(msg.trailing(), Some("The message from foo is now deleted."));
+
+    let msg = parse(":tmi.twitch.tv NOTICE * :Login authentication failed");
+    assert_eq!(msg.params, vec!["*"]);
+    assert_eq!(msg.trailing(), Some("Login authentication failed"));
+}
+
+#[test]
+fn parses_whisper() {
+    let msg = parse("@badges=staff/1,bits-charity/1;color=#8A2BE2;display-name=PetsgomOO;emotes=;message-id=306;thread-id=12345678_87654321;turbo=0;user-id=87654321;user-type=staff :petsgomoo!petsgomoo@petsgomoo.tmi.twitch.tv WHISPER foo :hello");
+    assert_eq!(msg.command, "WHISPER");
+    assert_eq!(msg.nick(), Some("petsgomoo"));
+    assert_eq!(msg.params, vec!["foo"]);
+    assert_eq!(msg.tag("thread-id"), Some("12345678_87654321"));
+    assert_eq!(msg.trailing(), Some("hello"));
+}
+
+#[test]
+fn parses_hosttarget_and_reconnect() {
+    let msg = parse(":tmi.twitch.tv HOSTTARGET #abc :xyz 10");
+    assert_eq!(msg.command, "HOSTTARGET");
+    assert_eq!(msg.params, vec!["#abc"]);
+    assert_eq!(msg.trailing(), Some("xyz 10"));
+
+    let msg = parse(":tmi.twitch.tv RECONNECT");
+    assert_eq!(msg.command, "RECONNECT");
+    assert!(msg.params.is_empty());
+    assert_eq!(msg.trailing, None);
+}
+
+#[test]
+fn parses_part() {
+    let msg = parse(":ronni!ronni@ronni.tmi.twitch.tv PART #dallas");
+    assert_eq!(msg.command, "PART");
+    assert_eq!(msg.nick(), Some("ronni"));
+}
+
+#[test]
+fn parses_valueless_and_client_tags() {
+    let msg = parse("@emote-only;+client-nonce=abc;vendor.example/key=v :tmi.twitch.tv ROOMSTATE #bar");
+    assert_eq!(msg.tag("emote-only"), Some(""));
+    assert_eq!(msg.tag("+client-nonce"), Some("abc"));
+    assert_eq!(msg.tag("vendor.example/key"), Some("v"));
+}
+
+#[test]
+fn tolerates_repeated_spaces() {
+    let msg = parse(":foo!foo@foo.tmi.twitch.tv  PRIVMSG   #bar   :hi  there");
+    assert_eq!(msg.command, "PRIVMSG");
+    assert_eq!(msg.params, vec!["#bar"]);
+    assert_eq!(msg.trailing(), Some("hi  there"));
+}
+
+#[test]
+fn parses_bare_nick_prefix() {
+    let msg = parse(":justinfan123 JOIN #bar");
+    assert_eq!(msg.nick(), Some("justinfan123"));
+    assert_eq!(msg.prefix.unwrap().host, None);
+}
+
+#[test]
+fn parses_nick_at_host_prefix() {
+    let msg = parse(":foo@foo.tmi.twitch.tv PRIVMSG #bar :hi");
+    let prefix = msg.prefix.unwrap();
+    assert_eq!(prefix.nick.as_deref(), Some("foo"));
+    assert_eq!(prefix.user, None);
+    assert_eq!(prefix.host.as_deref(), Some("foo.tmi.twitch.tv"));
+}
+
+#[test]
+fn rejects_malformed_lines() {
+    assert!(IrcMessage::parse("").is_err());
+    assert!(IrcMessage::parse("\r\n").is_err());
+    assert!(IrcMessage::parse("@tags-only").is_err());
+    assert!(IrcMessage::parse(":prefix-only").is_err());
+    assert!(IrcMessage::parse("@a=b :tmi.twitch.tv").is_err());
+}
+
+#[test]
+fn unescapes_every_sequence() {
+    assert_eq!(unescape_tag_value("a\\:b"), "a;b");
+    assert_eq!(unescape_tag_value("a\\sb"), "a b");
+    assert_eq!(unescape_tag_value("a\\\\b"), "a\\b");
+    assert_eq!(unescape_tag_value("a\\rb"), "a\rb");
+    assert_eq!(unescape_tag_value("a\\nb"), "a\nb");
+    assert_eq!(unescape_tag_value("a\\xb"), "axb");
+    assert_eq!(unescape_tag_value("trailing\\"), "trailing");
+    assert_eq!(unescape_tag_value("\\\\s"), "\\s");
+    assert_eq!(unescape_tag_value(""), "");
+    assert_eq!(unescape_tag_value("ünï\\scødé"), "ünï cødé");
+}
+
+#[test]
+fn builds_twitch_message_from_privmsg() {
+    let irc = parse("@id=1;user-id=2 :foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :!ping now: please ");
+    let message = TwitchMessage::try_from(&irc).unwrap();
+    assert_eq!(message.sender, "foo");
+    assert_eq!(message.text, "!ping now: please");
+}
+
+#[test]
+fn refuses_twitch_message_from_other_commands() {
+    let irc = parse(":tmi.twitch.tv NOTICE * :Login authentication failed");
+    assert!(TwitchMessage::try_from(&irc).is_err());
+
+    let irc = parse(":tmi.twitch.tv PRIVMSG #bar :no nick");
+    assert!(TwitchMessage::try_from(&irc).is_err());
+}
@@ -0,0 +1,117 @@
+use berry_lib::moderation::normalize::{
+    collapse_repeats, decode_leet, map_confusables, skeleton, strip_invisible, strip_marks,
+    NormalizedText,
+};
+
+#[test]
+fn plain_text_is_only_lowercased() {
+    assert_eq!(skeleton("Hello chat, good game!"), "hello chat, good game!");
+    assert_eq!(skeleton("  lots   of\tspace \n"), "lots of space");
+    assert_eq!(skeleton(""), "");
+}
+
+#[test]
+fn strips_zero_width_and_invisible_characters() {
+    assert_eq!(strip_invisible("k\u{200B}i\u{200C}l\u{200D}l"), "kill");
+    assert_eq!(strip_invisible("\u{FEFF}spam\u{2060}"), "spam");
+    assert_eq!(strip_invisible("s\u{00AD}p\u{034F}a\u{E0041}m"), "spam");
+    assert_eq!(strip_invisible("right\u{202E}to left"), "rightto left");
+}
+
+#[test]
+fn nfkc_folds_fullwidth_and_stylised_letters() {
+    assert_eq!(skeleton("\u{FF33}\u{FF30}\u{FF21}\u{FF2D}"), "spam");
+    assert_eq!(skeleton("\u{1D41F}\u{1D42B}\u{1D41E}\u{1D41E}"), "free");
+    assert_eq!(skeleton("\u{1D53C}\u{1D552}\u{1D565}"), "eat");
+    assert_eq!(skeleton("\u{24E2}\u{24DF}\u{24D0}\u{24DC}"), "spam");
+    assert_eq!(skeleton("\u{FB01}ne"), "fine");
+}
+
+#[test]
+fn strips_accents_and_zalgo_but_not_other_scripts() {
+    assert_eq!(strip_marks("caf\u{00E9} d\u{00E9}j\u{00E0}"), "cafe deja");
+    let zalgo = "h\u{0301}\u{0302}\u{0303}e\u{0305}\u{0306}l\u{0309}\u{030A}lo";
+    assert_eq!(skeleton(zalgo), "hello");
+    assert_eq!(
+        strip_marks("\u{0928}\u{092E}\u{0938}\u{094D}\u{0924}\u{0947}"),
+        "\u{0928}\u{092E}\u{0938}\u{094D}\u{0924}\u{0947}"
+    );
+    assert_eq!(skeleton("\u{00DF}"), "\u{00DF}");
+}
+
+#[test]
+fn maps_lookalikes_in_mixed_and_fully_confusable_words() {
+    assert_eq!(map_confusables("\u{0440}\u{0430}ypal"), "paypal");
+    assert_eq!(map_confusables("\u{0455}\u{0440}\u{0430}\u{043C}"), "spam");
+    assert_eq!(map_confusables("\u{03BF}\u{03BA}"), "ok");
+    assert_eq!(map_confusables("\u{1D00}\u{0299}\u{1D04}"), "abc");
+    assert_eq!(skeleton("\u{0405}\u{0420}\u{0410}\u{041C}"), "spam");
+}
+
+#[test]
+fn leaves_ordinary_text_in_other_scripts_alone() {
+    assert_eq!(
+        skeleton("\u{043F}\u{0440}\u{0438}\u{0432}\u{0435}\u{0442} \u{043C}\u{0438}\u{0440}"),
+        "\u{043F}\u{0440}\u{0438}\u{0432}\u{0435}\u{0442} \u{043C}\u{0438}\u{0440}"
+    );
+    assert_eq!(
+        skeleton("\u{039A}\u{03B1}\u{03BB}\u{03B7}\u{03BC}\u{03AD}\u{03C1}\u{03B1}"),
+        "\u{03BA}\u{03B1}\u{03BB}\u{03B7}\u{03BC}\u{03AD}\u{03C1}\u{03B1}"
+    );
+    assert_eq!(
+        skeleton("\u{3053}\u{3093}\u{306B}\u{3061}\u{306F}"),
+        "\u{3053}\u{3093}\u{306B}\u{3061}\u{306F}"
+    );
+}
+
+#[test]
+fn decodes_leetspeak_in_words() {
+    assert_eq!(decode_leet("h4ck3r"), "hacker");
+    assert_eq!(decode_leet("k1ll y0u"), "kill you");
+    assert_eq!(decode_leet("5p@m"), "spam");
+    assert_eq!(decode_leet("b!tch"), "bitch");
+    assert_eq!(decode_leet("7+his"), "tthis");
+}
+
+#[test]
+fn leaves_numbers_and_trailing_punctuation_alone() {
+    assert_eq!(decode_leet("gg 2024, 3 - 1"), "gg 2024, 3 - 1");
+    assert_eq!(decode_leet("1337 500"), "1337 500");
+    assert_eq!(decode_leet("hello! wow!!"), "hello! wow!!");
+    assert_eq!(decode_leet("!hello @user"), "!hello @user");
+    assert_eq!(decode_leet("$5 @ 10:30"), "$5 @ 10:30");
+    assert_eq!(decode_leet("a|b"), "aib");
+}
+
+#[test]
+fn collapses_stretched_letters() {
+    assert_eq!(collapse_repeats("heeeeeey"), "hey");
+    assert_eq!(collapse_repeats("good  book"), "good book");
+    assert_eq!(collapse_repeats("noooo!!!!!"), "no!");
+    assert_eq!(collapse_repeats("\u{0430}\u{0430}\u{0430}"), "\u{0430}");
+}
+
+#[test]
+fn combines_every_stage() {
+    assert_eq!(
+        skeleton("\u{FF46}\u{1D42B}\u{0435}\u{0435} m0ney"),
+        "free money"
+    );
+    assert_eq!(
+        skeleton("I W\u{200B}ILL K1LL Y\u{03BF}U"),
+        "i will kill you"
+    );
+    assert_eq!(skeleton("5\u{200D}P\u{0410}\u{0430}\u{0430}M"), "spam");
+}
+
+#[test]
+fn keeps_the_original_alongside_the_skeleton() {
+    let text = NormalizedText::new("Fr\u{0435}\u{0435} M0NEY");
+    assert_eq!(text.original, "Fr\u{0435}\u{0435} M0NEY");
+    assert_eq!(text.skeleton, "free money");
+    assert!(text.was_obfuscated());
+
+    let plain = NormalizedText::new("Free   Money");
+    assert_eq!(plain.skeleton, "free money");
+    assert!(!plain.was_obfuscated());
+}
@@ -0,0 +1,81 @@
+use berry_lib::twitch::outgoing::{format_privmsg, split_message, DeliveryMode, MAX_MESSAGE_CHARS};
+
+#[test]
+fn short_message_is_one_part() {
+    assert_eq!(split_message("hello there", MAX_MESSAGE_CHARS), vec!["hello there"]);
+}
+
+#[test]
+fn splits_on_word_boundaries() {
+    let parts = split_message("one two three four", 9);
+    assert_eq!(parts, vec!["one two", "three", "four"]);
+}
+
+#[test]
+fn part_can_fill_the_limit_exactly() {
+    let parts = split_message("abcd efgh ij", 9);
+    assert_eq!(parts, vec!["abcd efgh", "ij"]);
+}
+
+#[test]
+fn long_word_is_cut() {
+    let parts = split_message("hi abcdefghijkl yo", 5);
+    assert_eq!(parts, vec!["hi", "abcde", "fghij", "kl yo"]);
+}
+
+#[test]
+fn counts_characters_not_bytes() {
+    let parts = split_message("ééééé ü", 5);
+    assert_eq!(parts, vec!["ééééé", "ü"]);
+}
+
+#[test]
+fn newlines_become_spaces() {
+    assert_eq!(split_message("a\nb\r\n c", 500), vec!["a b c"]);
+}
+
+#[test]
+fn every_part_of_a_long_response_fits() {
+    let text = "word ".repeat(300);
+    let parts = split_message(&text, MAX_MESSAGE_CHARS);
+    assert_eq!(parts.len(), 3);
+    assert!(parts.iter().all(|p| p.chars().count() <= MAX_MESSAGE_CHARS));
+    assert_eq!(parts.join(" "), text.trim_end());
+}
+
+#[test]
+fn empty_text_has_no_parts() {
+    assert!(split_message("   ", MAX_MESSAGE_CHARS).is_empty());
+}
+
+#[test]
+fn formats_reply_with_parent_tag() {
+    assert_eq!(
+        format_privmsg("bar", "pong", DeliveryMode::Reply, Some("abc-123")),
+        "@reply-parent-msg-id=abc-123 PRIVMSG #bar :pong\r\n"
+    );
+}
+
+#[test]
+fn reply_without_parent_is_plain() {
+    assert_eq!(
+        format_privmsg("bar", "pong", DeliveryMode::Reply, None),
+        "PRIVMSG #bar :pong\r\n"
+    );
+}
+
+#[test]
+fn formats_action() {
+    assert_eq!(
+        format_privmsg("bar", "waves", DeliveryMode::Action, Some("abc")),
+        "PRIVMSG #bar :\u{1}ACTION waves\u{1}\r\n"
+    );
+}
+
+#[test]
+fn say_ignores_parent() {
+    assert_eq!(
+        format_privmsg("bar", "hi", DeliveryMode::Say, Some("abc")),
+        "PRIVMSG #bar :hi\r\n"
+    );
+}
@@ -0,0 +1,345 @@
+use berry_lib::moderation::policy::{
+    CategoryRule, ModerationPolicy, PolicyError, PolicyStore, RuleAction, SeverityOrder,
+    MAX_TIMEOUT_SECS,
+};
+use berry_lib::openai::moderation::{ModerationCategory, ModerationScores, PunishmentAction};
+
+#[test]
+fn default_policy_matches_previous_table() {
+    let policy = ModerationPolicy::default();
+    let hate = policy.rule(&ModerationCategory::Hate);
+    assert_eq!(hate.threshold, 0.55);
+    assert_eq!(hate.punishment(), PunishmentAction::Timeout(60));
+    assert_eq!(
+        policy.rule(&ModerationCategory::Sexual).punishment(),
+        PunishmentAction::Delete
+    );
+    assert_eq!(
+        policy.rule(&ModerationCategory::SexualMinors).punishment(),
+        PunishmentAction::Ban
+    );
+    assert_eq!(
+        policy.rule(&ModerationCategory::Harassment).punishment(),
+        PunishmentAction::Warn
+    );
+    assert!(ModerationCategory::from_name("spam").is_none());
+    assert!(policy.validate().is_ok());
+}
+
+#[test]
+fn serializes_with_openai_category_names() {
+    let json = serde_json::to_value(ModerationPolicy::default()).unwrap();
+    assert_eq!(json["hate/threatening"]["action"], "Ban");
+    assert_eq!(json["self-harm/intent"]["durationSecs"], 120);
+}
+
+#[test]
+fn rejects_threshold_out_of_range() {
+    let mut policy = ModerationPolicy::default();
+    policy.rule_mut(&ModerationCategory::Violence).threshold = 1.5;
+    match policy.validate() {
+        Err(PolicyError::InvalidThreshold { category, .. }) => assert_eq!(category, "violence"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    policy.rule_mut(&ModerationCategory::Violence).threshold = f64::NAN;
+    assert!(policy.validate().is_err());
+}
+
+#[test]
+fn rejects_timeout_without_valid_duration() {
+    let mut policy = ModerationPolicy {
+        hate: CategoryRule::timeout(0.5, 0),
+        ..Default::default()
+    };
+    assert!(matches!(
+        policy.validate(),
+        Err(PolicyError::InvalidDuration { .. })
+    ));
+
+    policy.hate = CategoryRule::timeout(0.5, MAX_TIMEOUT_SECS + 1);
+    assert!(policy.validate().is_err());
+
+    // Durations are ignored for other actions.
+    policy.hate = CategoryRule::new(0.5, RuleAction::Ban);
+    assert!(policy.validate().is_ok());
+}
+
+#[test]
+fn channels_without_policy_get_default() {
+    let store = PolicyStore::in_memory();
+    assert_eq!(store.get("bar"), ModerationPolicy::default());
+    assert!(store.channels().is_empty());
+}
+
+#[test]
+fn policies_are_per_channel_and_shared_between_clones() {
+    let store = PolicyStore::in_memory();
+    let shared = store.clone();
+
+    let strict = ModerationPolicy {
+        hate: CategoryRule::new(0.3, RuleAction::Ban),
+        ..Default::default()
+    };
+    store.set("#Bar", strict.clone()).unwrap();
+
+    assert_eq!(shared.get("bar"), strict);
+    assert_eq!(shared.get("baz"), ModerationPolicy::default());
+    assert_eq!(shared.channels(), vec!["bar"]);
+
+    shared.reset("bar").unwrap();
+    assert_eq!(store.get("bar"), ModerationPolicy::default());
+}
+
+#[test]
+fn invalid_policy_is_not_stored() {
+    let store = PolicyStore::in_memory();
+    let mut policy = ModerationPolicy::default();
+    policy.sexual.threshold = -0.1;
+
+    assert!(store.set("bar", policy).is_err());
+    assert_eq!(store.get("bar"), ModerationPolicy::default());
+}
+
+#[test]
+fn policies_survive_reload() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config").join("policies.json");
+
+    let store = PolicyStore::load_from(path.clone()).unwrap();
+    let policy = ModerationPolicy {
+        violence: CategoryRule::timeout(0.7, 600),
+        ..Default::default()
+    };
+    store.set("bar", policy.clone()).unwrap();
+
+    let reloaded = PolicyStore::load_from(path).unwrap();
+    assert_eq!(reloaded.get("bar"), policy);
+}
+
+#[test]
+fn finds_scores_just_below_a_threshold() {
+    let policy = ModerationPolicy::default();
+    let scores = |hate: f64, sexual: f64| ModerationScores {
+        hate,
+        sexual,
+        ..Default::default()
+    };
+
+    let (category, rule) = policy.borderline(&scores(0.5, 0.0), 0.1).unwrap();
+    assert_eq!(category, ModerationCategory::Hate);
+    assert_eq!(rule.punishment(), PunishmentAction::Timeout(60));
+
+    // The category closest to its threshold wins.
+    let (category, _) = policy.borderline(&scores(0.5, 0.86), 0.1).unwrap();
+    assert_eq!(category, ModerationCategory::Sexual);
+
+    assert!(policy.borderline(&scores(0.4, 0.0), 0.1).is_none());
+    assert!(policy.borderline(&scores(0.5, 0.0), 0.0).is_none());
+    // Nothing to review when a category is punished anyway.
+    assert!(policy.borderline(&scores(0.6, 0.86), 0.1).is_none());
+}
+
+#[test]
+fn categories_without_an_action_are_never_borderline() {
+    let policy = ModerationPolicy {
+        hate: CategoryRule::new(0.55, RuleAction::None),
+        ..ModerationPolicy::default()
+    };
+    let scores = ModerationScores {
+        hate: 0.5,
+        ..Default::default()
+    };
+    assert!(policy.borderline(&scores, 0.1).is_none());
+}
+
+fn scores(listed: &[(ModerationCategory, f64)]) -> ModerationScores {
+    let mut scores = ModerationScores::default();
+    for (category, score) in listed {
+        scores.set(category, *score);
+    }
+    scores
+}
+
+#[test]
+fn every_category_can_be_the_offence() {
+    let policy = ModerationPolicy::default();
+    let order = SeverityOrder::default();
+    for category in ModerationCategory::ALL {
+        let offence = policy
+            .decide(
+                std::slice::from_ref(&category),
+                &scores(&[(category.clone(), 1.0)]),
+                &order,
+            )
+            .unwrap();
+        assert_eq!(offence.category, category);
+        assert_eq!(offence.categories, vec![category.clone()]);
+        assert_eq!(offence.score, 1.0);
+        assert_eq!(offence.threshold, policy.rule(&category).threshold);
+        assert_eq!(offence.punishment, policy.rule(&category).punishment());
+    }
+}
+
+#[test]
+fn harshest_punishment_of_all_flagged_categories_wins() {
+    use ModerationCategory::*;
+    let policy = ModerationPolicy::default();
+    let flagged = [Violence, Hate, HarassmentThreatening];
+    let scored = scores(&[(Violence, 0.96), (Hate, 0.6), (HarassmentThreatening, 0.98)]);
+
+    let offence = policy
+        .decide(&flagged, &scored, &SeverityOrder::default())
+        .unwrap();
+    assert_eq!(offence.category, HarassmentThreatening);
+    assert_eq!(offence.punishment, PunishmentAction::Ban);
+    assert_eq!(
+        offence.categories,
+        vec![Hate, HarassmentThreatening, Violence]
+    );
+
+    // A category below its threshold earns nothing, however severe.
+    let offence = policy
+        .decide(
+            &[SexualMinors, Violence],
+            &scores(&[(SexualMinors, 0.2), (Violence, 0.96)]),
+            &SeverityOrder::default(),
+        )
+        .unwrap();
+    assert_eq!(offence.category, Violence);
+    assert_eq!(offence.punishment, PunishmentAction::Timeout(30));
+}
+
+#[test]
+fn severity_order_breaks_ties() {
+    use ModerationCategory::*;
+    let policy = ModerationPolicy::default();
+    let flagged = [Sexual, SelfHarm];
+    let scored = scores(&[(Sexual, 0.9), (SelfHarm, 0.99)]);
+
+    let offence = policy
+        .decide(&flagged, &scored, &SeverityOrder::default())
+        .unwrap();
+    assert_eq!(offence.category, SelfHarm);
+    assert_eq!(offence.punishment, PunishmentAction::Delete);
+
+    let sexual_first = SeverityOrder::new(vec![Sexual]);
+    assert_eq!(sexual_first.rank(&Sexual), 0);
+    assert_eq!(sexual_first.rank(&Harassment), 1);
+    let offence = policy.decide(&flagged, &scored, &sexual_first).unwrap();
+    assert_eq!(offence.category, Sexual);
+    assert_eq!(offence.score, 0.9);
+}
+
+#[test]
+fn flagged_below_every_threshold_is_not_punished() {
+    use ModerationCategory::*;
+    let policy = ModerationPolicy::default();
+    let offence = policy
+        .decide(
+            &[Harassment, Hate],
+            &scores(&[(Harassment, 0.3), (Hate, 0.2)]),
+            &SeverityOrder::default(),
+        )
+        .unwrap();
+    assert_eq!(offence.category, Hate);
+    assert_eq!(offence.punishment, PunishmentAction::None);
+
+    assert!(policy
+        .decide(&[], &ModerationScores::default(), &SeverityOrder::default())
+        .is_none());
+}
+
+#[test]
+fn reached_ignores_the_provider_flag() {
+    use ModerationCategory::*;
+    let mut policy = ModerationPolicy::default();
+    let scored = scores(&[(Hate, 0.4), (Violence, 0.96), (Sexual, 0.5)]);
+    assert_eq!(policy.reached(&scored), vec![Violence]);
+
+    policy.hate.threshold = 0.3;
+    assert_eq!(policy.reached(&scored), vec![Hate, Violence]);
+    assert!(policy.reached(&ModerationScores::default()).is_empty());
+}
+
+#[test]
+fn rules_can_be_set_for_new_categories() {
+    let illicit = ModerationCategory::from("illicit");
+    let mut policy = ModerationPolicy::default();
+    assert_eq!(policy.rule(&illicit).punishment(), PunishmentAction::None);
+    assert_eq!(policy.punishment_for(&illicit, 1.0), PunishmentAction::None);
+
+    *policy.rule_mut(&illicit) = CategoryRule::new(0.5, RuleAction::Ban);
+    let flagged = [ModerationCategory::Hate, illicit.clone()];
+    let scored = scores(&[(illicit.clone(), 0.8), (ModerationCategory::Hate, 0.6)]);
+    let offence = policy
+        .decide(&flagged, &scored, &SeverityOrder::default())
+        .unwrap();
+    assert_eq!(offence.category, illicit);
+    assert_eq!(offence.punishment, PunishmentAction::Ban);
+    assert_eq!(offence.categories, flagged.to_vec());
+
+    let dir = tempfile::tempdir().unwrap();
+    let store = PolicyStore::load_from(dir.path().join("policies.json")).unwrap();
+    store.set("bar", policy.clone()).unwrap();
+    let reloaded = PolicyStore::load_from(dir.path().join("policies.json")).unwrap();
+    assert_eq!(reloaded.get("bar").rule(&illicit).threshold, 0.5);
+
+    policy.rule_mut(&illicit).threshold = 2.0;
+    match policy.validate() {
+        Err(PolicyError::InvalidThreshold { category, .. }) => assert_eq!(category, "illicit"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn saved_policies_without_new_fields_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("policies.json");
+    let mut policy = serde_json::to_value(ModerationPolicy::default()).unwrap();
+    policy.as_object_mut().unwrap().remove("other");
+    let saved = serde_json::json!({"version": 1, "policies": {"bar": policy}});
+    std::fs::write(&path, saved.to_string()).unwrap();
+
+    let store = PolicyStore::load_from(path).unwrap();
+    assert_eq!(store.get("bar"), ModerationPolicy::default());
+}
+
+#[test]
+fn policies_from_a_newer_version_are_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("policies.json");
+    std::fs::write(&path, r#"{"version": 99, "policies": {}}"#).unwrap();
+    assert!(matches!(
+        PolicyStore::load_from(path),
+        Err(PolicyError::StorageError(_))
+    ));
+}
+
+#[test]
+fn failed_save_leaves_policy_unchanged() {
+    let dir = tempfile::tempdir().unwrap();
+    let blocker = dir.path().join("blocker");
+    std::fs::write(&blocker, b"").unwrap();
+    // The parent is a file, so every save fails.
+    let store = PolicyStore::load_from(blocker.join("policies.json")).unwrap();
+
+    let mut policy = ModerationPolicy::default();
+    policy.hate.threshold = 0.1;
+    assert!(matches!(
+        store.set("bar", policy),
+        Err(PolicyError::StorageError(_))
+    ));
+    assert_eq!(store.get("bar"), ModerationPolicy::default());
+}
+
+#[test]
+fn corrupt_file_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("policies.json");
+    std::fs::write(&path, b"xx").unwrap();
+    assert!(matches!(
+        PolicyStore::load_from(path),
+        Err(PolicyError::StorageError(_))
+    ));
+}
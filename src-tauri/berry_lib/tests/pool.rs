@@ -0,0 +1,168 @@
+use async_trait::async_trait;
+use berry_lib::moderation::local::LocalClassifier;
+use berry_lib::moderation::normalize::NormalizedText;
+use berry_lib::moderation::pool::{ModerationOutcome, ModerationPool, PoolConfig};
+use berry_lib::moderation::provider::{FallbackProvider, ModerationProvider, ModerationResult};
+use berry_lib::openai::moderation::ModerationError;
+use berry_lib::twitch::twitch_api::TwitchMessage;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Waits before answering and remembers how many calls ran at once.
+#[derive(Default)]
+struct Counting {
+    delay: Duration,
+    running: AtomicUsize,
+    most_running: AtomicUsize,
+}
+
+#[async_trait]
+impl ModerationProvider for Counting {
+    fn name(&self) -> &str {
+        "counting"
+    }
+
+    async fn classify(&self, text: &str) -> Result<ModerationResult, ModerationError> {
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.most_running.fetch_max(running, Ordering::SeqCst);
+        tokio::time::sleep(self.delay).await;
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        Ok(ModerationResult {
+            flagged: text.contains("bad"),
+            ..Default::default()
+        })
+    }
+}
+
+/// Never answers, like a request to a stalled endpoint.
+struct Stalled;
+
+#[async_trait]
+impl ModerationProvider for Stalled {
+    fn name(&self) -> &str {
+        "stalled"
+    }
+
+    async fn classify(&self, _text: &str) -> Result<ModerationResult, ModerationError> {
+        std::future::pending().await
+    }
+}
+
+fn counting(delay_ms: u64) -> Arc<Counting> {
+    Arc::new(Counting {
+        delay: Duration::from_millis(delay_ms),
+        ..Default::default()
+    })
+}
+
+fn pool(
+    provider: Arc<Counting>,
+    config: PoolConfig,
+) -> (ModerationPool, mpsc::UnboundedReceiver<ModerationOutcome>) {
+    let (outcomes, received) = mpsc::unbounded_channel();
+    (ModerationPool::spawn(provider, config, outcomes), received)
+}
+
+fn message(id: &str, text: &str) -> (TwitchMessage, NormalizedText) {
+    let message = TwitchMessage {
+        id: id.to_string(),
+        channel: "bar".to_string(),
+        text: text.to_string(),
+        ..Default::default()
+    };
+    (message, NormalizedText::new(text))
+}
+
+async fn submit(pool: &ModerationPool, id: &str, text: &str) -> bool {
+    let (message, normalized) = message(id, text);
+    pool.submit(message, normalized).await
+}
+
+#[tokio::test]
+async fn outcomes_carry_their_message() {
+    let (pool, mut outcomes) = pool(counting(0), PoolConfig::default());
+    assert!(submit(&pool, "msg-1", "a b\u{0430}d word").await);
+
+    let outcome = outcomes.recv().await.unwrap();
+    assert_eq!(outcome.message.id, "msg-1");
+    assert_eq!(outcome.message.text, "a b\u{0430}d word");
+    assert_eq!(outcome.normalized.skeleton, "a bad word");
+    assert!(outcome.result.unwrap().flagged);
+}
+
+#[tokio::test]
+async fn runs_at_most_the_configured_workers_at_once() {
+    let provider = counting(30);
+    let config = PoolConfig {
+        workers: 3,
+        ..PoolConfig::default()
+    };
+    let (pool, mut outcomes) = pool(provider.clone(), config);
+
+    for i in 0..10 {
+        assert!(submit(&pool, &format!("msg-{}", i), "hello").await);
+    }
+    let mut ids = Vec::new();
+    for _ in 0..10 {
+        ids.push(outcomes.recv().await.unwrap().message.id);
+    }
+
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 10);
+    assert_eq!(provider.most_running.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn slow_answers_time_out() {
+    let config = PoolConfig {
+        timeout: Duration::from_millis(20),
+        ..PoolConfig::default()
+    };
+    let (pool, mut outcomes) = pool(counting(5_000), config);
+    assert!(submit(&pool, "msg-1", "hello").await);
+
+    let outcome = outcomes.recv().await.unwrap();
+    assert_eq!(outcome.message.id, "msg-1");
+    assert!(matches!(outcome.result, Err(ModerationError::TimedOut)));
+    assert!(outcome.elapsed < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn stalled_primary_falls_back_before_the_pool_times_out() {
+    let provider = FallbackProvider::new(Stalled, LocalClassifier::new())
+        .with_timeout(Duration::from_millis(50));
+    let config = PoolConfig {
+        timeout: Duration::from_secs(1),
+        ..PoolConfig::default()
+    };
+    let (outcomes, mut received) = mpsc::unbounded_channel();
+    let pool = ModerationPool::spawn(Arc::new(provider), config, outcomes);
+    assert!(submit(&pool, "msg-1", "i want to die").await);
+
+    let outcome = received.recv().await.unwrap();
+    assert!(outcome.result.unwrap().categories.self_harm_intent);
+    assert!(outcome.elapsed < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn submitting_waits_while_the_queue_is_full() {
+    let config = PoolConfig {
+        workers: 1,
+        queue_capacity: 1,
+        timeout: Duration::from_secs(5),
+    };
+    let (pool, mut outcomes) = pool(counting(200), config);
+
+    // One message with the worker, one waiting for it and one queued.
+    for id in ["msg-1", "msg-2", "msg-3"] {
+        assert!(submit(&pool, id, "hello").await);
+    }
+    let blocked = tokio::time::timeout(Duration::from_millis(50), submit(&pool, "msg-4", "hello"));
+    assert!(blocked.await.is_err());
+
+    assert_eq!(outcomes.recv().await.unwrap().message.id, "msg-1");
+    assert!(submit(&pool, "msg-5", "hello").await);
+}
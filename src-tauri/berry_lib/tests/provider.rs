@@ -0,0 +1,254 @@
+mod common;
+
+use async_trait::async_trait;
+use berry_lib::moderation::local::LocalClassifier;
+use berry_lib::moderation::normalize::NormalizedText;
+use berry_lib::moderation::provider::{FallbackProvider, ModerationProvider, ModerationResult};
+use berry_lib::openai::moderation::{
+    find_image_urls, ModerationCategory, ModerationError, OpenAiConfig, OpenAiProvider,
+};
+use common::mock_http::MockHttp;
+use serde_json::json;
+use std::time::Duration;
+
+struct Failing;
+
+#[async_trait]
+impl ModerationProvider for Failing {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    async fn classify(&self, _text: &str) -> Result<ModerationResult, ModerationError> {
+        Err(ModerationError::ConnectionError)
+    }
+}
+
+#[tokio::test]
+async fn local_classifier_flags_threats() {
+    let result = LocalClassifier::new()
+        .classify("I will KILL you!!")
+        .await
+        .unwrap();
+    assert!(result.flagged);
+    assert!(result.categories.violence);
+    assert_eq!(result.scores.violence, 0.96);
+    assert_eq!(
+        result.categories.flagged(),
+        vec![ModerationCategory::Violence]
+    );
+}
+
+#[tokio::test]
+async fn local_classifier_passes_clean_text() {
+    let result = LocalClassifier::new()
+        .classify("what a great play, gg everyone")
+        .await
+        .unwrap();
+    assert_eq!(result, ModerationResult::default());
+}
+
+#[test]
+fn local_classifier_matches_whole_words_only() {
+    let classifier = LocalClassifier::new();
+    assert!(
+        !classifier
+            .classify_text("what a skill yourself has")
+            .flagged
+    );
+    assert!(!classifier.classify_text("kysnake").flagged);
+    assert!(classifier.classify_text("just kys").flagged);
+    assert!(classifier.classify_text("I'm going to kill you").flagged);
+}
+
+#[test]
+fn local_classifier_keeps_highest_score_per_category() {
+    let classifier = LocalClassifier::empty()
+        .with_term(ModerationCategory::Hate, "Foo Bar", 0.7)
+        .with_term(ModerationCategory::Hate, "baz", 0.9)
+        .with_term(ModerationCategory::Sexual, "qux", 0.2);
+
+    let result = classifier.classify_text("foo bar and baz, qux");
+    assert_eq!(result.scores.hate, 0.9);
+    assert!(result.categories.hate);
+    // Scores under the flag score count but do not flag.
+    assert_eq!(result.scores.sexual, 0.2);
+    assert!(!result.categories.sexual);
+    assert!(LocalClassifier::empty().classify_text("baz").scores.hate == 0.0);
+}
+
+#[tokio::test]
+async fn fallback_is_used_when_primary_fails() {
+    let provider = FallbackProvider::new(Failing, LocalClassifier::new());
+    let result = provider.classify("i want to die").await.unwrap();
+    assert!(result.categories.self_harm_intent);
+}
+
+#[tokio::test]
+async fn fallback_is_skipped_when_primary_succeeds() {
+    let provider = FallbackProvider::new(LocalClassifier::empty(), Failing);
+    assert!(!provider.classify("kys").await.unwrap().flagged);
+
+    let provider = FallbackProvider::new(Failing, Failing);
+    assert!(matches!(
+        provider.classify("kys").await,
+        Err(ModerationError::ConnectionError)
+    ));
+}
+
+#[tokio::test]
+async fn openai_provider_reads_first_result() {
+    let server = MockHttp::start().await;
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &common::moderation_response(true, 0.9),
+    );
+    let provider = OpenAiProvider::new(OpenAiConfig::new(
+        "key",
+        &format!("{}/v1/moderations", server.url()),
+    ));
+
+    let result = provider.classify("hateful words").await.unwrap();
+    assert!(result.flagged);
+    assert!(result.categories.hate);
+    assert_eq!(result.scores.hate, 0.9);
+}
+
+#[tokio::test]
+async fn openai_provider_reports_unreachable_endpoint() {
+    let provider = OpenAiProvider::new(OpenAiConfig::new("key", "http://127.0.0.1:1/v1"));
+    assert!(matches!(
+        provider.classify("hello").await,
+        Err(ModerationError::ConnectionError)
+    ));
+}
+
+#[tokio::test]
+async fn openai_provider_gives_up_on_a_stalled_endpoint() {
+    // Accepts connections but never answers.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/v1/moderations", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut open = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            open.push(stream);
+        }
+    });
+    let provider = OpenAiProvider::new(
+        OpenAiConfig::new("key", &url).with_timeout(Duration::from_millis(100)),
+    );
+
+    let result = tokio::time::timeout(Duration::from_secs(5), provider.classify("hello"))
+        .await
+        .expect("request was not timed out");
+    assert!(matches!(result, Err(ModerationError::ConnectionError)));
+}
+
+/// An omni model response with categories this version has no field for
+/// and without most of the legacy ones.
+const OMNI_RESPONSE: &str = r#"{
+    "id": "modr-2",
+    "model": "omni-moderation-latest",
+    "results": [{
+        "flagged": true,
+        "categories": {"hate": true, "illicit": true, "illicit/violent": false},
+        "category_scores": {"hate": 0.7, "illicit": 0.8, "illicit/violent": 0.01},
+        "category_applied_input_types": {
+            "hate": ["text"],
+            "illicit": ["text"],
+            "illicit/violent": ["text", "image"]
+        }
+    }]
+}"#;
+
+fn openai_config(server: &MockHttp) -> OpenAiConfig {
+    OpenAiConfig::new("key", &format!("{}/v1/moderations", server.url()))
+}
+
+#[tokio::test]
+async fn openai_provider_sends_the_chosen_model() {
+    let server = MockHttp::start().await;
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &common::moderation_response(false, 0.1),
+    );
+
+    OpenAiProvider::new(openai_config(&server))
+        .classify("hello")
+        .await
+        .unwrap();
+    let config = openai_config(&server).with_model("omni-moderation-latest");
+    OpenAiProvider::new(config).classify("hello").await.unwrap();
+
+    let requests = server.requests_to("/v1/moderations");
+    assert!(requests[0].json().get("model").is_none());
+    assert_eq!(requests[1].json()["model"], "omni-moderation-latest");
+    assert_eq!(requests[1].json()["input"], "hello");
+}
+
+#[tokio::test]
+async fn openai_provider_keeps_categories_it_does_not_know() {
+    let server = MockHttp::start().await;
+    server.respond("POST", "/v1/moderations", 200, OMNI_RESPONSE);
+    let provider = OpenAiProvider::new(openai_config(&server));
+
+    let result = provider.classify("illicit words").await.unwrap();
+    assert!(result.flagged);
+    assert_eq!(
+        result.categories.flagged(),
+        vec![
+            ModerationCategory::Hate,
+            ModerationCategory::from("illicit")
+        ]
+    );
+    assert_eq!(result.scores.hate, 0.7);
+    assert_eq!(result.scores.violence, 0.0);
+    assert_eq!(result.scores.get(&ModerationCategory::from("illicit")), 0.8);
+    assert_eq!(result.scores.other["illicit/violent"], 0.01);
+}
+
+#[tokio::test]
+async fn openai_provider_sends_linked_images_when_enabled() {
+    let server = MockHttp::start().await;
+    server.respond(
+        "POST",
+        "/v1/moderations",
+        200,
+        &common::moderation_response(false, 0.1),
+    );
+    let message = NormalizedText::new("look https://i.imgur.com/Cat.PNG");
+
+    OpenAiProvider::new(openai_config(&server))
+        .classify_message(&message)
+        .await
+        .unwrap();
+    let config = openai_config(&server).with_image_inputs(true);
+    OpenAiProvider::new(config)
+        .classify_message(&message)
+        .await
+        .unwrap();
+
+    let requests = server.requests_to("/v1/moderations");
+    assert_eq!(requests[0].json()["input"], message.skeleton.as_str());
+    assert_eq!(
+        requests[1].json()["input"],
+        json!([
+            {"type": "text", "text": message.skeleton},
+            {"type": "image_url", "image_url": {"url": "https://i.imgur.com/Cat.PNG"}}
+        ])
+    );
+}
+
+#[test]
+fn finds_links_to_images() {
+    let text = "a https://x.com/a.png?size=2 http://y.tv/b.GIF https://z.com/page \
+                example.com/c.jpg https://d.jpg";
+    assert_eq!(
+        find_image_urls(text),
+        vec!["https://x.com/a.png?size=2", "http://y.tv/b.GIF"]
+    );
+}
@@ -0,0 +1,184 @@
+use berry_lib::twitch::rate_limit::{LineKind, MockClock, OutboundQueue, RateLimits};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn queue(clock: &MockClock) -> OutboundQueue {
+    OutboundQueue::new(RateLimits::default(), Arc::new(clock.clone()))
+}
+
+fn push(queue: &mut OutboundQueue, channel: &str, text: &str) -> bool {
+    queue.push_message(channel, text, format!("PRIVMSG #{channel} :{text}\r\n"))
+}
+
+fn drain(queue: &mut OutboundQueue) -> Vec<String> {
+    std::iter::from_fn(|| queue.pop_ready().map(|q| q.line)).collect()
+}
+
+#[test]
+fn regular_account_sends_twenty_per_thirty_seconds() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+    for i in 0..25 {
+        assert!(push(&mut queue, "bar", &format!("message {i}")));
+    }
+
+    assert_eq!(drain(&mut queue).len(), 20);
+    assert_eq!(queue.len(), 5);
+    assert_eq!(queue.next_ready_in(), Some(Duration::from_secs(30)));
+
+    clock.advance(Duration::from_secs(29));
+    assert!(queue.pop_ready().is_none());
+    assert_eq!(queue.next_ready_in(), Some(Duration::from_secs(1)));
+
+    clock.advance(Duration::from_secs(1));
+    let sent = drain(&mut queue);
+    assert_eq!(sent.len(), 5);
+    assert_eq!(sent[0], "PRIVMSG #bar :message 20\r\n");
+    assert_eq!(queue.next_ready_in(), None);
+}
+
+#[test]
+fn window_slides_instead_of_resetting() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+
+    for i in 0..10 {
+        push(&mut queue, "bar", &format!("early {i}"));
+    }
+    assert_eq!(drain(&mut queue).len(), 10);
+
+    clock.advance(Duration::from_secs(15));
+    for i in 0..15 {
+        push(&mut queue, "bar", &format!("late {i}"));
+    }
+    assert_eq!(drain(&mut queue).len(), 10);
+
+    // The first ten expire 30 s after they were sent, not 30 s after the last.
+    clock.advance(Duration::from_secs(15));
+    assert_eq!(drain(&mut queue).len(), 5);
+}
+
+#[test]
+fn moderator_channels_get_the_higher_limit() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+    queue.set_elevated("modded", true);
+    assert!(queue.is_elevated("modded"));
+
+    for i in 0..120 {
+        push(&mut queue, "modded", &format!("message {i}"));
+    }
+    assert_eq!(drain(&mut queue).len(), 100);
+
+    queue.set_elevated("modded", false);
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(drain(&mut queue).len(), 20);
+}
+
+#[test]
+fn elevated_channel_is_not_blocked_behind_regular_one() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+    queue.set_elevated("modded", true);
+
+    for i in 0..21 {
+        push(&mut queue, "regular", &format!("r{i}"));
+    }
+    push(&mut queue, "modded", "m0");
+
+    let sent = drain(&mut queue);
+    assert_eq!(sent.len(), 21);
+    assert_eq!(sent.last().unwrap(), "PRIVMSG #modded :m0\r\n");
+    assert_eq!(queue.len(), 1);
+}
+
+#[test]
+fn joins_have_their_own_budget() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+    for i in 0..25 {
+        queue.push_join(&format!("c{i}"), format!("JOIN #c{i}\r\n"));
+    }
+    for i in 0..5 {
+        push(&mut queue, "c0", &format!("hi {i}"));
+    }
+
+    let sent: Vec<_> = std::iter::from_fn(|| queue.pop_ready()).collect();
+    assert_eq!(sent.iter().filter(|q| q.kind == LineKind::Join).count(), 20);
+    assert_eq!(sent.iter().filter(|q| q.kind == LineKind::Message).count(), 5);
+    assert_eq!(queue.next_ready_in(), Some(Duration::from_secs(10)));
+
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(drain(&mut queue).len(), 5);
+}
+
+#[test]
+fn duplicate_joins_collapse() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+    queue.push_join("bar", "JOIN #bar\r\n".into());
+    queue.push_join("bar", "JOIN #bar\r\n".into());
+    assert_eq!(queue.len(), 1);
+}
+
+#[test]
+fn duplicate_messages_within_thirty_seconds_are_dropped() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+
+    assert!(push(&mut queue, "bar", "Pong!"));
+    assert!(!push(&mut queue, "bar", "Pong!"));
+    assert!(push(&mut queue, "other", "Pong!"));
+
+    clock.advance(Duration::from_secs(29));
+    assert!(!push(&mut queue, "bar", "Pong!"));
+
+    clock.advance(Duration::from_secs(1));
+    assert!(push(&mut queue, "bar", "Pong!"));
+
+    assert_eq!(drain(&mut queue).len(), 3);
+}
+
+#[test]
+fn parting_removes_queued_lines() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+    push(&mut queue, "bar", "a");
+    queue.push_join("bar", "JOIN #bar\r\n".into());
+    push(&mut queue, "keep", "b");
+
+    queue.remove_channel("bar");
+    assert_eq!(drain(&mut queue), vec!["PRIVMSG #keep :b\r\n"]);
+}
+
+#[test]
+fn failed_writes_go_back_to_the_front() {
+    let clock = MockClock::default();
+    let mut queue = queue(&clock);
+    push(&mut queue, "bar", "first");
+    push(&mut queue, "bar", "second");
+
+    let first = queue.pop_ready().unwrap();
+    queue.push_front(first);
+    assert_eq!(
+        drain(&mut queue),
+        vec!["PRIVMSG #bar :first\r\n", "PRIVMSG #bar :second\r\n"]
+    );
+}
+
+#[test]
+fn custom_limits_are_respected() {
+    let clock = MockClock::default();
+    let limits = RateLimits {
+        user_messages: 2,
+        message_window: Duration::from_secs(5),
+        ..RateLimits::default()
+    };
+    let mut queue = OutboundQueue::new(limits, Arc::new(clock.clone()));
+    for i in 0..3 {
+        push(&mut queue, "bar", &i.to_string());
+    }
+    assert_eq!(drain(&mut queue).len(), 2);
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(drain(&mut queue).len(), 1);
+}
@@ -0,0 +1,229 @@
+mod common;
+
+use berry_lib::moderation::audit::{ActionResult, AuditLog, DecisionOrigin};
+use berry_lib::moderation::policy::MAX_TIMEOUT_SECS;
+use berry_lib::moderation::review::{ReviewDecision, ReviewError, ReviewQueue};
+use berry_lib::openai::moderation::{
+    FlaggedMessage, ModerationCategory, ModerationScores, PunishmentAction,
+};
+use berry_lib::twitch::helix::HelixClient;
+use common::mock_http::MockHttp;
+
+fn flagged(message_id: &str) -> FlaggedMessage {
+    FlaggedMessage::new(
+        "troll",
+        "12345",
+        message_id,
+        "borderline words",
+        ModerationCategory::Hate,
+        0.5,
+    )
+}
+
+fn push(queue: &ReviewQueue, channel: &str, message_id: &str) -> u64 {
+    let scores = ModerationScores {
+        hate: 0.5,
+        ..Default::default()
+    };
+    queue
+        .push(
+            channel,
+            "1001",
+            flagged(message_id),
+            scores,
+            Some(0.55),
+            PunishmentAction::Timeout(60),
+        )
+        .unwrap()
+}
+
+#[test]
+fn lists_pending_messages_per_channel() {
+    let queue = ReviewQueue::in_memory();
+    let first = push(&queue, "#Bar", "msg-1");
+    let second = push(&queue, "baz", "msg-2");
+    let third = push(&queue, "bar", "msg-3");
+    assert_eq!((first, second, third), (0, 1, 2));
+
+    let bar: Vec<_> = queue
+        .pending(Some("bar"))
+        .iter()
+        .map(|item| item.message.message_id.clone())
+        .collect();
+    assert_eq!(bar, vec!["msg-1", "msg-3"]);
+    assert_eq!(queue.pending(None).len(), 3);
+    assert_eq!(queue.get(second).unwrap().channel, "baz");
+}
+
+#[test]
+fn queue_survives_a_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("review_queue.bin");
+
+    let queue = ReviewQueue::load_from(path.clone()).unwrap();
+    push(&queue, "bar", "msg-1");
+    push(&queue, "bar", "msg-2");
+
+    let reloaded = ReviewQueue::load_from(path).unwrap();
+    assert_eq!(reloaded.pending(None), queue.pending(None));
+    assert_eq!(
+        reloaded.pending(None)[0].suggested,
+        PunishmentAction::Timeout(60)
+    );
+    assert_eq!(push(&reloaded, "bar", "msg-3"), 2);
+}
+
+#[tokio::test]
+async fn approving_carries_out_the_suggested_action() {
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+    let queue = ReviewQueue::in_memory();
+    let id = push(&queue, "bar", "msg-1");
+
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    let entry = queue
+        .resolve(id, ReviewDecision::Approve, Some(&helix), &audit)
+        .await
+        .unwrap();
+
+    let bans = server.requests_to("/moderation/bans");
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].json()["data"]["duration"], 60);
+    assert_eq!(entry.origin, DecisionOrigin::Manual);
+    assert_eq!(entry.action, PunishmentAction::Timeout(60));
+    assert_eq!(entry.result, ActionResult::Applied);
+    assert_eq!(entry.threshold, Some(0.55));
+    assert_eq!(audit.query(&Default::default()), vec![entry]);
+    assert!(queue.is_empty());
+}
+
+#[tokio::test]
+async fn moderators_may_pick_another_action() {
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+    let queue = ReviewQueue::in_memory();
+    let id = push(&queue, "bar", "msg-1");
+
+    server.respond("DELETE", "/moderation/chat", 204, "");
+    let entry = queue
+        .resolve(
+            id,
+            ReviewDecision::Override(PunishmentAction::Delete),
+            Some(&helix),
+            &audit,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(entry.action, PunishmentAction::Delete);
+    let deletes = server.requests_to("/moderation/chat");
+    assert_eq!(deletes.len(), 1);
+    assert!(deletes[0].path.contains("message_id=msg-1"));
+    assert!(server.requests_to("/moderation/bans").is_empty());
+}
+
+#[tokio::test]
+async fn invalid_override_timeouts_are_refused() {
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+    let queue = ReviewQueue::in_memory();
+    let id = push(&queue, "bar", "msg-1");
+
+    for duration_secs in [0, MAX_TIMEOUT_SECS + 1] {
+        let decision = ReviewDecision::Override(PunishmentAction::Timeout(duration_secs));
+        match queue.resolve(id, decision, Some(&helix), &audit).await {
+            Err(ReviewError::InvalidDuration(secs)) => assert_eq!(secs, duration_secs),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    assert!(server.requests().is_empty());
+    assert!(audit.is_empty());
+    assert_eq!(queue.len(), 1);
+}
+
+#[tokio::test]
+async fn dismissing_is_recorded_without_acting() {
+    let audit = AuditLog::in_memory();
+    let queue = ReviewQueue::in_memory();
+    let id = push(&queue, "bar", "msg-1");
+
+    let entry = queue
+        .resolve(id, ReviewDecision::Dismiss, None, &audit)
+        .await
+        .unwrap();
+    assert_eq!(entry.action, PunishmentAction::None);
+    assert_eq!(entry.result, ActionResult::NoAction);
+    assert_eq!(audit.len(), 1);
+    assert!(queue.is_empty());
+
+    assert!(matches!(
+        queue
+            .resolve(id, ReviewDecision::Dismiss, None, &audit)
+            .await,
+        Err(ReviewError::NotFound(0))
+    ));
+}
+
+#[tokio::test]
+async fn refused_actions_are_recorded_as_failed() {
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+    let queue = ReviewQueue::in_memory();
+    let first = push(&queue, "bar", "msg-1");
+    let second = push(&queue, "bar", "msg-2");
+
+    server.respond(
+        "POST",
+        "/moderation/bans",
+        403,
+        r#"{"error":"Forbidden","status":403,"message":"Not a moderator"}"#,
+    );
+    let entry = queue
+        .resolve(first, ReviewDecision::Approve, Some(&helix), &audit)
+        .await
+        .unwrap();
+    assert_eq!(
+        entry.result,
+        ActionResult::Failed("Not a moderator in this channel".to_string())
+    );
+
+    let entry = queue
+        .resolve(second, ReviewDecision::Approve, None, &audit)
+        .await
+        .unwrap();
+    assert_eq!(
+        entry.result,
+        ActionResult::Failed("No moderator credentials".to_string())
+    );
+
+    // Both stay queued, in order, for another try.
+    let pending: Vec<_> = queue.pending(None).iter().map(|item| item.id).collect();
+    assert_eq!(pending, vec![first, second]);
+    assert_eq!(audit.len(), 2);
+}
+
+#[tokio::test]
+async fn only_one_moderator_decides_on_a_message() {
+    let server = MockHttp::start().await;
+    let helix = HelixClient::new("client-id", "secret", "99").with_base_url(&server.url());
+    let audit = AuditLog::in_memory();
+    let queue = ReviewQueue::in_memory();
+    let id = push(&queue, "bar", "msg-1");
+
+    server.respond("POST", "/moderation/bans", 200, r#"{"data":[]}"#);
+    let (first, second) = tokio::join!(
+        queue.resolve(id, ReviewDecision::Approve, Some(&helix), &audit),
+        queue.resolve(id, ReviewDecision::Approve, Some(&helix), &audit),
+    );
+
+    assert!(first.is_ok());
+    assert!(matches!(second, Err(ReviewError::NotFound(_))));
+    assert_eq!(server.requests_to("/moderation/bans").len(), 1);
+    assert_eq!(audit.len(), 1);
+    assert!(queue.is_empty());
+}
@@ -0,0 +1,95 @@
+use berry_lib::moderation::audit::{ActionResult, AuditEntry, DecisionOrigin};
+use berry_lib::moderation::shadow::ShadowReport;
+use berry_lib::openai::moderation::{FlaggedMessage, ModerationCategory, PunishmentAction};
+
+fn shadow(username: &str, user_id: &str, action: PunishmentAction, at: i64) -> AuditEntry {
+    let mut entry = AuditEntry::new(
+        "bar",
+        DecisionOrigin::Automatic,
+        FlaggedMessage::new(
+            username,
+            user_id,
+            "msg-1",
+            "bad words",
+            ModerationCategory::Hate,
+            0.9,
+        ),
+        Default::default(),
+        Some(0.5),
+        action,
+        ActionResult::Shadow,
+    );
+    entry.timestamp = at;
+    entry
+}
+
+fn human(username: &str, user_id: &str, action: PunishmentAction, at: i64) -> AuditEntry {
+    let mut entry = AuditEntry::new(
+        "bar",
+        DecisionOrigin::Manual,
+        FlaggedMessage::new(username, user_id, "", "", ModerationCategory::from(""), 0.0),
+        Default::default(),
+        None,
+        action,
+        ActionResult::Applied,
+    );
+    entry.timestamp = at;
+    entry
+}
+
+#[test]
+fn pairs_shadow_decisions_with_moderator_actions() {
+    let entries = vec![
+        shadow("troll", "1", PunishmentAction::Timeout(60), 100),
+        human("troll", "1", PunishmentAction::Timeout(600), 130),
+        shadow("spammer", "2", PunishmentAction::Delete, 200),
+        // Deletions only carry the login.
+        human("Spammer", "", PunishmentAction::Ban, 210),
+        shadow("innocent", "3", PunishmentAction::Warn, 300),
+        human("missed", "4", PunishmentAction::Ban, 400),
+    ];
+
+    let report = ShadowReport::build(&entries, 60);
+    assert_eq!(report.matched.len(), 2);
+    assert!(report.matched[0].same_action);
+    assert!(!report.matched[1].same_action);
+    assert_eq!(report.shadow_only[0].message.username, "innocent");
+    assert_eq!(report.human_only[0].message.username, "missed");
+    assert_eq!(report.precision(), Some(2.0 / 3.0));
+    assert_eq!(report.recall(), Some(2.0 / 3.0));
+}
+
+#[test]
+fn actions_outside_the_window_do_not_match() {
+    let entries = vec![
+        shadow("troll", "1", PunishmentAction::Ban, 100),
+        human("troll", "1", PunishmentAction::Ban, 1000),
+    ];
+    let report = ShadowReport::build(&entries, 60);
+    assert!(report.matched.is_empty());
+    assert_eq!(report.shadow_only.len(), 1);
+    assert_eq!(report.human_only.len(), 1);
+}
+
+#[test]
+fn each_moderator_action_matches_once() {
+    let entries = vec![
+        shadow("troll", "1", PunishmentAction::Warn, 100),
+        shadow("troll", "1", PunishmentAction::Timeout(600), 110),
+        human("troll", "1", PunishmentAction::Timeout(600), 120),
+    ];
+    let report = ShadowReport::build(&entries, 60);
+    assert_eq!(report.matched.len(), 1);
+    assert_eq!(report.shadow_only.len(), 1);
+    assert!(report.human_only.is_empty());
+}
+
+#[test]
+fn live_decisions_are_ignored() {
+    let mut live = shadow("troll", "1", PunishmentAction::Ban, 100);
+    live.result = ActionResult::Applied;
+    let report = ShadowReport::build(&[live], 60);
+    assert_eq!(report, ShadowReport::default());
+    assert_eq!(report.precision(), None);
+    assert_eq!(report.recall(), None);
+}
@@ -0,0 +1,250 @@
+use berry_lib::moderation::policy::RuleAction;
+use berry_lib::moderation::spam::{find_links, SpamFilters, SpamKind, SpamTracker};
+use berry_lib::moderation::strikes::LadderStep;
+use berry_lib::openai::moderation::PunishmentAction;
+use berry_lib::twitch::tags::Emote;
+use berry_lib::twitch::twitch_api::TwitchMessage;
+use chrono::{DateTime, TimeDelta, Utc};
+
+fn message(user_id: &str, text: &str) -> TwitchMessage {
+    TwitchMessage {
+        channel: "bar".to_string(),
+        sender: format!("user{}", user_id),
+        user_id: user_id.to_string(),
+        text: text.to_string(),
+        ..Default::default()
+    }
+}
+
+fn at(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+}
+
+fn kind(filters: &SpamFilters, message: &TwitchMessage) -> Option<SpamKind> {
+    filters
+        .check_at(message, &mut SpamTracker::new(), at(0))
+        .map(|verdict| verdict.kind)
+}
+
+#[test]
+fn ordinary_chat_passes() {
+    let filters = SpamFilters::default();
+    for text in [
+        "hello chat, how is everyone doing today?",
+        "LOL",
+        "that was a great play :)",
+        "check out twitch.tv/bar",
+        "e.g. this is fine",
+    ] {
+        assert_eq!(kind(&filters, &message("1", text)), None, "{}", text);
+    }
+}
+
+#[test]
+fn catches_shouting_but_not_emotes() {
+    let filters = SpamFilters::default();
+    let shouting = message("1", "WHY IS NOBODY LISTENING TO ME");
+    let verdict = filters
+        .check_at(&shouting, &mut SpamTracker::new(), at(0))
+        .unwrap();
+    assert_eq!(verdict.kind, SpamKind::Caps);
+    assert_eq!(verdict.score, 1.0);
+    assert_eq!(verdict.threshold, 0.7);
+    assert_eq!(verdict.punishment, PunishmentAction::Delete);
+
+    let emotes = TwitchMessage {
+        emotes: vec![Emote {
+            id: "1".to_string(),
+            ranges: vec![(0, 2), (4, 6), (8, 10), (12, 14), (16, 18), (20, 22)],
+        }],
+        ..message("1", "LUL LUL LUL LUL LUL LUL ok")
+    };
+    assert_eq!(kind(&filters, &emotes), None);
+}
+
+#[test]
+fn catches_symbol_walls_and_zalgo() {
+    let filters = SpamFilters::default();
+    assert_eq!(
+        kind(&filters, &message("1", "!!!!!! $$$$$$ ###### @@@@@@")),
+        Some(SpamKind::Symbols)
+    );
+    let zalgo = "h\u{0301}\u{0302}\u{0303}\u{0304}e\u{0305}\u{0306}\u{0307}\u{0308}l\u{0309}\u{030A}\u{030B}\u{030C}lo";
+    assert_eq!(
+        kind(&filters, &message("1", zalgo)),
+        Some(SpamKind::Symbols)
+    );
+    assert_eq!(kind(&filters, &message("1", "café déjà vu")), None);
+}
+
+#[test]
+fn catches_emote_floods() {
+    let filters = SpamFilters::default();
+    let ranges: Vec<(usize, usize)> = (0..16).map(|i| (i * 5, i * 5 + 3)).collect();
+    let flood = TwitchMessage {
+        emotes: vec![Emote {
+            id: "25".to_string(),
+            ranges,
+        }],
+        ..message("1", &"Kapp ".repeat(16))
+    };
+    let verdict = filters
+        .check_at(&flood, &mut SpamTracker::new(), at(0))
+        .unwrap();
+    assert_eq!(verdict.kind, SpamKind::Emotes);
+    assert_eq!(verdict.score, 16.0);
+}
+
+#[test]
+fn catches_walls_of_text() {
+    let filters = SpamFilters::default();
+    assert_eq!(
+        kind(&filters, &message("1", &"word ".repeat(80))),
+        Some(SpamKind::Length)
+    );
+}
+
+#[test]
+fn catches_one_user_repeating_themselves() {
+    let filters = SpamFilters::default();
+    let mut tracker = SpamTracker::new();
+    let spam = message("1", "buy followers");
+    assert!(filters.check_at(&spam, &mut tracker, at(0)).is_none());
+    assert!(filters
+        .check_at(&message("1", "Buy   followers"), &mut tracker, at(1))
+        .is_none());
+    let verdict = filters.check_at(&spam, &mut tracker, at(2)).unwrap();
+    assert_eq!(verdict.kind, SpamKind::Repetition);
+    assert_eq!(verdict.punishment, PunishmentAction::Timeout(60));
+
+    // Once the window has passed the count starts over.
+    let mut tracker = SpamTracker::new();
+    assert!(filters.check_at(&spam, &mut tracker, at(0)).is_none());
+    assert!(filters.check_at(&spam, &mut tracker, at(1)).is_none());
+    assert!(filters.check_at(&spam, &mut tracker, at(40)).is_none());
+}
+
+#[test]
+fn catches_copypasta_across_users_but_not_short_messages() {
+    let filters = SpamFilters::default();
+    let mut tracker = SpamTracker::new();
+    let pasta = "this is a very long copypasta that everyone spams";
+    for user in 1..=4 {
+        assert!(filters
+            .check_at(&message(&user.to_string(), pasta), &mut tracker, at(user))
+            .is_none());
+    }
+    let verdict = filters
+        .check_at(&message("5", pasta), &mut tracker, at(5))
+        .unwrap();
+    assert_eq!(verdict.kind, SpamKind::Repetition);
+    assert_eq!(verdict.score, 5.0);
+
+    let mut tracker = SpamTracker::new();
+    for user in 1..=10 {
+        assert!(filters
+            .check_at(&message(&user.to_string(), "gg"), &mut tracker, at(user))
+            .is_none());
+    }
+}
+
+#[test]
+fn finds_link_domains() {
+    assert_eq!(
+        find_links("go to https://Example.com/path?x=1 or www.spam.xyz."),
+        vec!["example.com", "www.spam.xyz"]
+    );
+    assert_eq!(find_links("http://localhost:8080/"), Vec::<String>::new());
+    assert_eq!(
+        find_links("http://my-site.internal"),
+        vec!["my-site.internal"]
+    );
+    assert!(find_links("i.e. nothing here... ok").is_empty());
+}
+
+#[test]
+fn blocks_links_outside_the_allowlist() {
+    let mut filters = SpamFilters::default();
+    assert_eq!(
+        kind(&filters, &message("1", "free stuff at cheap-followers.com")),
+        Some(SpamKind::Links)
+    );
+    assert_eq!(
+        kind(&filters, &message("1", "clip: https://clips.twitch.tv/abc")),
+        None
+    );
+
+    filters
+        .links
+        .allowed_domains
+        .push("youtube.com".to_string());
+    assert_eq!(
+        kind(&filters, &message("1", "https://www.youtube.com/watch?v=1")),
+        None
+    );
+    assert!(!filters.links.is_allowed("notyoutube.com"));
+}
+
+#[test]
+fn permits_one_link_before_they_run_out() {
+    let filters = SpamFilters::default();
+    let link = message("1", "my art: https://portfolio.example.com");
+    let mut tracker = SpamTracker::new();
+
+    tracker.permit("@User1", at(60));
+    assert!(filters.check_at(&link, &mut tracker, at(10)).is_none());
+    assert!(filters
+        .check_at(
+            &message("1", "another one example.com"),
+            &mut tracker,
+            at(20)
+        )
+        .is_some());
+
+    tracker.permit("user1", at(60));
+    assert!(filters
+        .check_at(
+            &link,
+            &mut tracker,
+            at(60) + TimeDelta::try_seconds(1).unwrap()
+        )
+        .is_some());
+}
+
+#[test]
+fn filters_use_their_own_thresholds_and_punishments() {
+    let mut filters = SpamFilters::default();
+    filters.caps.max_ratio = 0.9;
+    filters.caps.punishment = LadderStep::timeout(300);
+    let mostly_caps = message("1", "MOSTLY CAPITALS HERE but not all");
+    assert_eq!(kind(&filters, &mostly_caps), None);
+
+    filters.caps.max_ratio = 0.5;
+    let verdict = filters
+        .check_at(&mostly_caps, &mut SpamTracker::new(), at(0))
+        .unwrap();
+    assert_eq!(verdict.punishment, PunishmentAction::Timeout(300));
+
+    filters.length.max_chars = 10;
+    filters.length.punishment = LadderStep::new(RuleAction::Warn);
+    let verdict = filters
+        .check_at(&mostly_caps, &mut SpamTracker::new(), at(0))
+        .unwrap();
+    assert_eq!(verdict.kind, SpamKind::Length);
+    assert_eq!(verdict.punishment, PunishmentAction::Warn);
+}
+
+#[test]
+fn disabled_filters_let_everything_through() {
+    let filters = SpamFilters::disabled();
+    let mut tracker = SpamTracker::new();
+    for _ in 0..5 {
+        assert!(filters
+            .check_at(
+                &message("1", "VISIT SPAM.COM !!!!!!!!!!!!"),
+                &mut tracker,
+                at(0)
+            )
+            .is_none());
+    }
+}
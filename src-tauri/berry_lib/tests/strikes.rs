@@ -0,0 +1,173 @@
+use berry_lib::moderation::policy::RuleAction;
+use berry_lib::moderation::strikes::{LadderStep, StrikeConfig, StrikeError, StrikeLedger};
+use berry_lib::openai::moderation::{ModerationCategory, PunishmentAction};
+use chrono::{TimeDelta, TimeZone, Utc};
+
+#[test]
+fn default_ladder_escalates_to_ban() {
+    let config = StrikeConfig::default();
+    assert_eq!(config.step(0), PunishmentAction::None);
+    assert_eq!(config.step(1), PunishmentAction::Warn);
+    assert_eq!(config.step(2), PunishmentAction::Timeout(600));
+    assert_eq!(config.step(3), PunishmentAction::Timeout(3600));
+    assert_eq!(config.step(4), PunishmentAction::Ban);
+    assert_eq!(config.step(10), PunishmentAction::Ban);
+}
+
+#[test]
+fn escalation_keeps_harsher_policy_punishment() {
+    let ledger = StrikeLedger::in_memory();
+    assert_eq!(
+        ledger.escalate(PunishmentAction::Timeout(60), 1),
+        PunishmentAction::Timeout(60)
+    );
+    assert_eq!(
+        ledger.escalate(PunishmentAction::Timeout(60), 2),
+        PunishmentAction::Timeout(600)
+    );
+    assert_eq!(
+        ledger.escalate(PunishmentAction::Warn, 3),
+        PunishmentAction::Timeout(3600)
+    );
+    assert_eq!(
+        ledger.escalate(PunishmentAction::Ban, 1),
+        PunishmentAction::Ban
+    );
+}
+
+#[test]
+fn strikes_count_per_user_and_channel() {
+    use ModerationCategory::*;
+    let ledger = StrikeLedger::in_memory();
+    assert_eq!(ledger.record("bar", "1", "Troll", &Hate).unwrap(), 1);
+    assert_eq!(ledger.record("#Bar", "1", "troll", &Violence).unwrap(), 2);
+    assert_eq!(ledger.record("bar", "2", "other", &Hate).unwrap(), 1);
+    assert_eq!(ledger.record("baz", "1", "troll", &Hate).unwrap(), 1);
+
+    assert_eq!(ledger.active("bar", "1"), 2);
+    assert_eq!(ledger.find_user("bar", "@TROLL"), Some("1".to_string()));
+    assert_eq!(ledger.find_user("bar", "nobody"), None);
+
+    let users = ledger.users("bar");
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[1].username, "troll");
+    assert_eq!(users[1].strikes[1].category, Violence);
+}
+
+#[test]
+fn strikes_decay() {
+    let ledger = StrikeLedger::in_memory();
+    ledger
+        .set_config(StrikeConfig {
+            decay_secs: Some(3600),
+            ..Default::default()
+        })
+        .unwrap();
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+
+    ledger
+        .record_at("bar", "1", "troll", &ModerationCategory::Hate, start)
+        .unwrap();
+    let later = start + TimeDelta::try_minutes(30).unwrap();
+    assert_eq!(
+        ledger
+            .record_at("bar", "1", "troll", &ModerationCategory::Hate, later)
+            .unwrap(),
+        2
+    );
+
+    assert_eq!(
+        ledger.active_at("bar", "1", start + TimeDelta::try_minutes(61).unwrap()),
+        1
+    );
+    assert_eq!(
+        ledger.active_at("bar", "1", start + TimeDelta::try_minutes(91).unwrap()),
+        0
+    );
+    assert!(ledger
+        .users_at("bar", start + TimeDelta::try_minutes(91).unwrap())
+        .is_empty());
+}
+
+#[test]
+fn strikes_without_decay_last_forever() {
+    let ledger = StrikeLedger::in_memory();
+    ledger
+        .set_config(StrikeConfig {
+            decay_secs: None,
+            ..Default::default()
+        })
+        .unwrap();
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+    ledger
+        .record_at("bar", "1", "troll", &ModerationCategory::Hate, start)
+        .unwrap();
+    assert_eq!(
+        ledger.active_at("bar", "1", start + TimeDelta::try_days(365).unwrap()),
+        1
+    );
+}
+
+#[test]
+fn clearing_forgets_strikes() {
+    let ledger = StrikeLedger::in_memory();
+    ledger
+        .record("bar", "1", "troll", &ModerationCategory::Hate)
+        .unwrap();
+    ledger
+        .record("bar", "1", "troll", &ModerationCategory::Hate)
+        .unwrap();
+
+    assert_eq!(ledger.clear("bar", "1").unwrap(), 2);
+    assert_eq!(ledger.active("bar", "1"), 0);
+    assert_eq!(ledger.clear("bar", "1").unwrap(), 0);
+    assert_eq!(
+        ledger
+            .record("bar", "1", "troll", &ModerationCategory::Hate)
+            .unwrap(),
+        1
+    );
+}
+
+#[test]
+fn rejects_invalid_ladders() {
+    let ledger = StrikeLedger::in_memory();
+    let empty = StrikeConfig {
+        ladder: vec![],
+        decay_secs: None,
+    };
+    assert!(matches!(
+        ledger.set_config(empty),
+        Err(StrikeError::EmptyLadder)
+    ));
+
+    let zero_timeout = StrikeConfig {
+        ladder: vec![LadderStep::timeout(0), LadderStep::new(RuleAction::Ban)],
+        decay_secs: None,
+    };
+    assert!(matches!(
+        ledger.set_config(zero_timeout),
+        Err(StrikeError::InvalidDuration(0))
+    ));
+    assert_eq!(ledger.config(), StrikeConfig::default());
+}
+
+#[test]
+fn strikes_survive_reload() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("strikes.bin");
+
+    let ledger = StrikeLedger::load_from(path.clone()).unwrap();
+    let config = StrikeConfig {
+        ladder: vec![LadderStep::timeout(30)],
+        decay_secs: Some(60),
+    };
+    ledger.set_config(config.clone()).unwrap();
+    ledger
+        .record("bar", "1", "troll", &ModerationCategory::Hate)
+        .unwrap();
+
+    let reloaded = StrikeLedger::load_from(path).unwrap();
+    assert_eq!(reloaded.config(), config);
+    assert_eq!(reloaded.active("bar", "1"), 1);
+}
@@ -0,0 +1,69 @@
+use berry_lib::twitch::supervisor::{Backoff, ConnectionState};
+use std::time::Duration;
+
+#[test]
+fn backoff_doubles_within_jitter_bounds() {
+    let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(10));
+    for attempt in 0..6u32 {
+        let ceiling = 100u64 * 2u64.pow(attempt);
+        let delay = backoff.next_delay().unwrap().as_millis() as u64;
+        assert!(
+            (ceiling / 2..=ceiling).contains(&delay),
+            "attempt {attempt}: {delay}ms not in {}..={ceiling}",
+            ceiling / 2
+        );
+    }
+    assert_eq!(backoff.attempt(), 6);
+}
+
+#[test]
+fn backoff_is_capped_at_max() {
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+    for _ in 0..40 {
+        assert!(backoff.next_delay().unwrap() <= Duration::from_secs(5));
+    }
+}
+
+#[test]
+fn backoff_resets_after_success() {
+    let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(60));
+    for _ in 0..5 {
+        backoff.next_delay();
+    }
+    backoff.reset();
+    assert_eq!(backoff.attempt(), 0);
+    assert!(backoff.next_delay().unwrap() <= Duration::from_millis(100));
+}
+
+#[test]
+fn backoff_runs_out_of_attempts() {
+    let mut backoff =
+        Backoff::new(Duration::from_millis(1), Duration::from_millis(10)).with_max_attempts(3);
+    assert!(backoff.next_delay().is_some());
+    assert!(backoff.next_delay().is_some());
+    assert!(backoff.next_delay().is_some());
+    assert!(backoff.next_delay().is_none());
+}
+
+#[test]
+fn connection_state_serializes_for_the_frontend() {
+    assert_eq!(
+        serde_json::to_value(ConnectionState::Connected).unwrap(),
+        serde_json::json!({ "state": "connected" })
+    );
+    assert_eq!(
+        serde_json::to_value(ConnectionState::Reconnecting {
+            attempt: 2,
+            delay_ms: 1500
+        })
+        .unwrap(),
+        serde_json::json!({ "state": "reconnecting", "attempt": 2, "delayMs": 1500 })
+    );
+    assert_eq!(
+        serde_json::to_value(ConnectionState::Failed {
+            reason: "ConnectionClosed".into()
+        })
+        .unwrap(),
+        serde_json::json!({ "state": "failed", "reason": "ConnectionClosed" })
+    );
+}
@@ -0,0 +1,95 @@
+use berry_lib::twitch::irc_message::IrcMessage;
+use berry_lib::twitch::tags::{Badges, Emote};
+use berry_lib::twitch::twitch_api::TwitchMessage;
+
+fn message(line: &str) -> TwitchMessage {
+    TwitchMessage::try_from(&IrcMessage::parse(line).unwrap()).unwrap()
+}
+
+#[test]
+fn reads_every_tag_from_a_privmsg() {
+    let msg = message(
+        "@badge-info=subscriber/14;badges=moderator/1,subscriber/12,bits/100;bits=250;color=#FF69B4;display-name=Chatter_One;emotes=25:0-4,12-16/1902:6-10;first-msg=1;id=885196de-cb67-427a-baa8-82f9b0fcd05f;mod=1;reply-parent-display-name=Streamer;reply-parent-msg-body=first\\sline;reply-parent-msg-id=6b13e51b;reply-parent-user-id=42;reply-parent-user-login=streamer;room-id=42;subscriber=1;tmi-sent-ts=1642696567751;turbo=0;user-id=713936733;user-type=mod :chatter_one!chatter_one@chatter_one.tmi.twitch.tv PRIVMSG #streamer :Kappa Keepo Kappa cheer250",
+    );
+
+    assert_eq!(msg.sender, "chatter_one");
+    assert_eq!(msg.display_name, "Chatter_One");
+    assert_eq!(msg.id, "885196de-cb67-427a-baa8-82f9b0fcd05f");
+    assert_eq!(msg.user_id, "713936733");
+    assert_eq!(msg.color.as_deref(), Some("#FF69B4"));
+    assert!(msg.badges.moderator);
+    assert!(!msg.badges.broadcaster);
+    assert!(!msg.badges.vip);
+    assert_eq!(msg.badges.subscriber, Some(14));
+    assert_eq!(msg.bits, Some(250));
+    assert!(msg.first_msg);
+    assert_eq!(
+        msg.emotes,
+        vec![
+            Emote {
+                id: "25".into(),
+                ranges: vec![(0, 4), (12, 16)],
+            },
+            Emote {
+                id: "1902".into(),
+                ranges: vec![(6, 10)],
+            },
+        ]
+    );
+
+    let parent = msg.reply_parent.unwrap();
+    assert_eq!(parent.msg_id, "6b13e51b");
+    assert_eq!(parent.user_id, "42");
+    assert_eq!(parent.user_login, "streamer");
+    assert_eq!(parent.display_name, "Streamer");
+    assert_eq!(parent.body, "first line");
+
+    assert_eq!(msg.sent_at.unwrap().timestamp_millis(), 1642696567751);
+}
+
+#[test]
+fn falls_back_when_tags_are_missing() {
+    let msg = message(":foo!foo@foo.tmi.twitch.tv PRIVMSG #bar :hi");
+    assert_eq!(msg.display_name, "foo");
+    assert_eq!(msg.id, "");
+    assert_eq!(msg.user_id, "");
+    assert_eq!(msg.color, None);
+    assert_eq!(msg.badges, Badges::default());
+    assert!(msg.emotes.is_empty());
+    assert_eq!(msg.bits, None);
+    assert!(!msg.first_msg);
+    assert!(msg.reply_parent.is_none());
+    assert!(msg.sent_at.is_none());
+}
+
+#[test]
+fn parses_broadcaster_and_vip_badges() {
+    let badges = Badges::parse("broadcaster/1,vip/1", "");
+    assert!(badges.broadcaster);
+    assert!(badges.vip);
+    assert!(!badges.moderator);
+    assert_eq!(badges.subscriber, None);
+    assert_eq!(
+        badges.raw,
+        vec![
+            ("broadcaster".to_string(), "1".to_string()),
+            ("vip".to_string(), "1".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn subscriber_months_prefer_badge_info() {
+    assert_eq!(Badges::parse("subscriber/3006", "subscriber/8").subscriber, Some(8));
+    assert_eq!(Badges::parse("subscriber/6", "").subscriber, Some(6));
+    assert_eq!(Badges::parse("founder/0", "founder/20").subscriber, Some(20));
+    assert!(!Badges::parse("bits/100", "").is_subscriber());
+}
+
+#[test]
+fn emote_char_count_sums_ranges() {
+    let emotes = Emote::parse_list("25:0-4,12-16/1902:6-10");
+    assert_eq!(emotes[0].char_count(), 10);
+    assert_eq!(emotes[1].char_count(), 5);
+    assert!(Emote::parse_list("").is_empty());
+}
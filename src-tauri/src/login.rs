@@ -93,6 +93,7 @@ pub async fn request_device_authorization() -> Result<app_bin::DeviceAuthBinary,
         "moderator:manage:chat_messages",
         "moderator:read:chat_settings",
         "moderator:manage:chat_settings",
+        "moderator:manage:warnings",
         "user:edit",
         "user:edit:follows",
         "user:manage:blocked_users",
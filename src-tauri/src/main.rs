@@ -4,13 +4,39 @@
 
 mod login;
 mod app_checks;
+mod moderation;
 
 
 fn main() {
     tauri::Builder::default()
+        .manage(moderation::load_policy_store())
+        .manage(moderation::load_strike_ledger())
+        .manage(moderation::load_audit_log())
+        .manage(moderation::load_blocked_terms())
+        .manage(moderation::load_review_queue())
+        .manage(berry_lib::moderation::batcher::ModerationMetrics::new())
         .invoke_handler(tauri::generate_handler![
             app_checks::check_port,
             login::request_device_authorization,
+            moderation::get_moderation_policy,
+            moderation::set_moderation_policy,
+            moderation::set_moderation_rule,
+            moderation::reset_moderation_policy,
+            moderation::get_strikes,
+            moderation::clear_strikes,
+            moderation::get_strike_config,
+            moderation::set_strike_config,
+            moderation::query_audit_log,
+            moderation::export_audit_log,
+            moderation::get_shadow_report,
+            moderation::get_blocked_terms,
+            moderation::add_blocked_term,
+            moderation::remove_blocked_term,
+            moderation::sync_blocked_terms,
+            moderation::get_moderation_metrics,
+            moderation::reset_moderation_metrics,
+            moderation::get_review_queue,
+            moderation::resolve_review,
             ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,315 @@
+//! Tauri commands for editing moderation settings from the frontend.
+//!
+//! The `PolicyStore`, `StrikeLedger`, `AuditLog`, `BlockedTermStore`,
+//! `ReviewQueue` and `ModerationMetrics` are managed state shared with the
+//! bot, so saved changes apply to the next moderated message without a
+//! restart.
+
+use berry_lib::moderation::audit::{AuditEntry, AuditLog, AuditQuery, ExportFormat};
+use berry_lib::moderation::batcher::{MetricsSnapshot, ModerationMetrics};
+use berry_lib::moderation::blocked_terms::{BlockedTerm, BlockedTermStore, SyncReport};
+use berry_lib::moderation::policy::{CategoryRule, ModerationPolicy, PolicyStore};
+use berry_lib::moderation::review::{ReviewDecision, ReviewItem, ReviewQueue};
+use berry_lib::moderation::shadow::{ShadowReport, DEFAULT_MATCH_WINDOW_SECS};
+use berry_lib::moderation::strikes::{StrikeConfig, StrikeLedger, UserStrikes};
+use berry_lib::openai::moderation::ModerationCategory;
+use berry_lib::twitch::helix::HelixClient;
+use berry_lib::twitch::identity::validate_token;
+use berry_lib::twitch::twitch_api::normalize_channel;
+use berry_lib::twitch::twitch_endpoint::get_user_twitch_id;
+use colored::*;
+use tauri::State;
+
+/// Loads the saved policies, falling back to defaults kept in memory if
+/// the file cannot be read.
+pub fn load_policy_store() -> PolicyStore {
+    PolicyStore::load().unwrap_or_else(|e| {
+        println!("{}: {}", "Failed to load moderation policies".red(), e);
+        PolicyStore::in_memory()
+    })
+}
+
+/// Loads the saved strikes, falling back to an empty ledger kept in memory
+/// if the file cannot be read.
+pub fn load_strike_ledger() -> StrikeLedger {
+    StrikeLedger::load().unwrap_or_else(|e| {
+        println!("{}: {}", "Failed to load strikes".red(), e);
+        StrikeLedger::in_memory()
+    })
+}
+
+/// Loads the audit log, falling back to one kept in memory if the file
+/// cannot be read.
+pub fn load_audit_log() -> AuditLog {
+    AuditLog::load().unwrap_or_else(|e| {
+        println!("{}: {}", "Failed to load audit log".red(), e);
+        AuditLog::in_memory()
+    })
+}
+
+/// Loads the saved blocked terms, falling back to an empty list kept in
+/// memory if the file cannot be read.
+pub fn load_blocked_terms() -> BlockedTermStore {
+    BlockedTermStore::load().unwrap_or_else(|e| {
+        println!("{}: {}", "Failed to load blocked terms".red(), e);
+        BlockedTermStore::in_memory()
+    })
+}
+
+/// Loads the saved review queue, falling back to an empty queue kept in
+/// memory if the file cannot be read.
+pub fn load_review_queue() -> ReviewQueue {
+    ReviewQueue::load().unwrap_or_else(|e| {
+        println!("{}: {}", "Failed to load review queue".red(), e);
+        ReviewQueue::in_memory()
+    })
+}
+
+/// Returns the channel's policy, or the default if none was saved.
+#[tauri::command]
+pub fn get_moderation_policy(channel: String, policies: State<PolicyStore>) -> ModerationPolicy {
+    policies.get(&channel)
+}
+
+/// Validates and saves a whole policy for the channel.
+///
+/// # Errors
+///
+/// Returns an error message if a threshold or timeout is out of range or
+/// the policy cannot be saved.
+#[tauri::command]
+pub fn set_moderation_policy(
+    channel: String,
+    policy: ModerationPolicy,
+    policies: State<PolicyStore>,
+) -> Result<(), String> {
+    policies.set(&channel, policy).map_err(|e| e.to_string())
+}
+
+/// Changes the rule for one category, e.g. `"hate/threatening"`, and
+/// returns the updated policy. Categories OpenAI added after this version,
+/// such as `"illicit"`, are accepted by name.
+///
+/// # Errors
+///
+/// Returns an error message if the category is empty, the rule is out of
+/// range or the policy cannot be saved.
+#[tauri::command]
+pub fn set_moderation_rule(
+    channel: String,
+    category: String,
+    rule: CategoryRule,
+    policies: State<PolicyStore>,
+) -> Result<ModerationPolicy, String> {
+    if category.trim().is_empty() {
+        return Err("Moderation category must not be empty".to_string());
+    }
+    let mut policy = policies.get(&channel);
+    *policy.rule_mut(&ModerationCategory::from(category)) = rule;
+    policies
+        .set(&channel, policy.clone())
+        .map_err(|e| e.to_string())?;
+    Ok(policy)
+}
+
+/// Goes back to the default policy for the channel and returns it.
+#[tauri::command]
+pub fn reset_moderation_policy(
+    channel: String,
+    policies: State<PolicyStore>,
+) -> Result<ModerationPolicy, String> {
+    policies.reset(&channel).map_err(|e| e.to_string())?;
+    Ok(policies.get(&channel))
+}
+
+/// Returns the users with active strikes in the channel.
+#[tauri::command]
+pub fn get_strikes(channel: String, strikes: State<StrikeLedger>) -> Vec<UserStrikes> {
+    strikes.users(&channel)
+}
+
+/// Forgets a user's strikes in the channel and returns how many there were.
+#[tauri::command]
+pub fn clear_strikes(
+    channel: String,
+    user_id: String,
+    strikes: State<StrikeLedger>,
+) -> Result<usize, String> {
+    strikes.clear(&channel, &user_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_strike_config(strikes: State<StrikeLedger>) -> StrikeConfig {
+    strikes.config()
+}
+
+/// Validates and saves the punishment ladder and strike decay.
+///
+/// # Errors
+///
+/// Returns an error message if the ladder is empty, a timeout is out of
+/// range or the config cannot be saved.
+#[tauri::command]
+pub fn set_strike_config(config: StrikeConfig, strikes: State<StrikeLedger>) -> Result<(), String> {
+    strikes.set_config(config).map_err(|e| e.to_string())
+}
+
+/// Returns the moderation decisions matching the query, oldest first.
+#[tauri::command]
+pub fn query_audit_log(query: AuditQuery, audit: State<AuditLog>) -> Vec<AuditEntry> {
+    audit.query(&query)
+}
+
+/// Writes the matching decisions to a CSV or JSONL file at `path`.
+///
+/// # Errors
+///
+/// Returns an error message if the file cannot be written.
+#[tauri::command]
+pub fn export_audit_log(
+    query: AuditQuery,
+    format: ExportFormat,
+    path: String,
+    audit: State<AuditLog>,
+) -> Result<(), String> {
+    let file = std::fs::File::create(&path).map_err(|e| e.to_string())?;
+    audit
+        .export(&query, format, std::io::BufWriter::new(file))
+        .map_err(|e| e.to_string())
+}
+
+/// Compares the channel's shadow decisions with the actions its moderators
+/// took, optionally limited to a time range in Unix seconds.
+#[tauri::command]
+pub fn get_shadow_report(
+    channel: String,
+    since: Option<i64>,
+    until: Option<i64>,
+    audit: State<AuditLog>,
+) -> ShadowReport {
+    let query = AuditQuery {
+        channel: Some(channel),
+        since,
+        until,
+        ..Default::default()
+    };
+    ShadowReport::build(&audit.query(&query), DEFAULT_MATCH_WINDOW_SECS)
+}
+
+#[tauri::command]
+pub fn get_blocked_terms(channel: String, terms: State<BlockedTermStore>) -> Vec<BlockedTerm> {
+    terms.terms(&channel)
+}
+
+/// Adds a literal, wildcard or regex term to the channel, or changes the
+/// punishment of one it already has.
+///
+/// # Errors
+///
+/// Returns an error message if the term is empty, the regex is invalid or
+/// the terms cannot be saved.
+#[tauri::command]
+pub fn add_blocked_term(
+    channel: String,
+    term: BlockedTerm,
+    terms: State<BlockedTermStore>,
+) -> Result<Vec<BlockedTerm>, String> {
+    terms.add(&channel, term).map_err(|e| e.to_string())?;
+    Ok(terms.terms(&channel))
+}
+
+/// Removes a term by its pattern. Synced terms are removed on Twitch at the
+/// next sync.
+#[tauri::command]
+pub fn remove_blocked_term(
+    channel: String,
+    pattern: String,
+    terms: State<BlockedTermStore>,
+) -> Result<Vec<BlockedTerm>, String> {
+    terms
+        .remove(&channel, &pattern)
+        .map_err(|e| e.to_string())?;
+    Ok(terms.terms(&channel))
+}
+
+/// Syncs the channel's terms with its Twitch blocked-terms list, as the
+/// moderator the access token belongs to.
+///
+/// # Errors
+///
+/// Returns an error message if the token is invalid, lacks the
+/// `moderator:manage:blocked_terms` scope, or Twitch cannot be reached.
+#[tauri::command]
+pub async fn sync_blocked_terms(
+    channel: String,
+    access_token: String,
+    terms: State<'_, BlockedTermStore>,
+) -> Result<SyncReport, String> {
+    let helix = moderator_helix(&access_token).await?;
+    let broadcaster_id = get_user_twitch_id(&normalize_channel(&channel), &access_token)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    terms
+        .sync(&channel, &helix, &broadcaster_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// How many moderation requests batching and caching saved, and how long
+/// OpenAI took to answer.
+#[tauri::command]
+pub fn get_moderation_metrics(metrics: State<ModerationMetrics>) -> MetricsSnapshot {
+    metrics.snapshot()
+}
+
+#[tauri::command]
+pub fn reset_moderation_metrics(metrics: State<ModerationMetrics>) {
+    metrics.reset();
+}
+
+/// Messages waiting for a moderator, in one channel or in all of them.
+#[tauri::command]
+pub fn get_review_queue(channel: Option<String>, review: State<ReviewQueue>) -> Vec<ReviewItem> {
+    review.pending(channel.as_deref())
+}
+
+/// Approves, overrides or dismisses a queued message as the moderator the
+/// access token belongs to, and records the decision in the audit log.
+///
+/// # Errors
+///
+/// Returns an error message if the message is no longer queued, the token
+/// is invalid, or the decision cannot be saved. An action Twitch refuses is
+/// not an error; the returned entry records it as failed and the message
+/// stays queued.
+#[tauri::command]
+pub async fn resolve_review(
+    id: u64,
+    decision: ReviewDecision,
+    access_token: String,
+    review: State<'_, ReviewQueue>,
+    audit: State<'_, AuditLog>,
+) -> Result<AuditEntry, String> {
+    let helix = match decision {
+        ReviewDecision::Dismiss => None,
+        _ => Some(moderator_helix(&access_token).await?),
+    };
+    review
+        .resolve(id, decision, helix.as_ref(), &audit)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// A Helix client acting as the moderator the access token belongs to.
+async fn moderator_helix(access_token: &str) -> Result<HelixClient, String> {
+    let client_id = std::env::var("TWITCH_CLIENT_ID").map_err(|e| e.to_string())?;
+    let moderator = validate_token(access_token, &reqwest::Client::new())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(HelixClient::new(
+        &client_id,
+        access_token,
+        &moderator.user_id,
+    ))
+}